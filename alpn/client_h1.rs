@@ -1,72 +1,37 @@
 use std::{
-  fs, io::{self, Write}, net::SocketAddr, path::Path, sync::Arc
-};
-use anyhow::{Context, Result};
-use rustls::{
-  pki_types::CertificateDer,
-  crypto::{CryptoProvider, aws_lc_rs},
+  io::{self, Write}, net::SocketAddr, path::Path,
 };
+use anyhow::Result;
+use log::info;
+use quic_rs_demo::{files, logging, qlog::{Qlog, Vantage}, scenarios::{self, alpn}};
+use rustls::crypto::{CryptoProvider, aws_lc_rs};
 
-use quinn::{
-  crypto::rustls::QuicClientConfig,
-  Endpoint,
-  ClientConfig,
-};
 const CERT_DIR: &str = "/tmp/quinn_certs";
 const H1_ALPN: &[&[u8]] = &[b"h1"];
 
 #[tokio::main]
 async fn main() -> Result<()> {
-  let cert_dir: &Path = Path::new(CERT_DIR);
-  let cert_path= cert_dir.join("cert.der");
-  let mut cert_root = rustls::RootCertStore::empty();
-  cert_root.add(CertificateDer::from(fs::read(cert_path)?))?;
-
+  logging::init();
+  let qlog = Qlog::from_env(Vantage::Client)?;
   CryptoProvider::install_default(
     aws_lc_rs::default_provider()
   ).expect("failed to install default crypto provider");
 
-  let mut tls_config = rustls::ClientConfig::builder()
-    .with_root_certificates(cert_root)
-    .with_no_client_auth();
-  tls_config.alpn_protocols = H1_ALPN.iter().map(|&x| x.into()).collect();
-
-  let quic_config = QuicClientConfig::try_from(tls_config)?;
-  let client_config = ClientConfig::new(Arc::new(quic_config));
-  
+  let tls_config = alpn::client_tls(Path::new(CERT_DIR), H1_ALPN)?;
   let addr: SocketAddr = "127.0.0.1:4385".parse()?;
-  let mut endpoint = Endpoint::client(addr)?;
-  endpoint.set_default_client_config(client_config);
+  let endpoint = scenarios::client_endpoint(tls_config, addr)?;
 
   let server_addr = "127.0.0.1:4843".parse()?;
-  let conn = endpoint
-    .connect(server_addr,"localhost" )?
-    .await
-    .context("failed to connect to server")?;
-  
-  println!("connected to server {}", server_addr.to_string());
-
-  let (mut send, mut recv) = conn
-  .open_bi()
-  .await
-  .context("failed to open stream")?;
-
-  let req = format!("GET {}\r\n", "sample.json");
-  send.write_all(req.as_bytes())
-    .await
-    .context("failed to send request")?;
-  send.finish().unwrap();
-
-  let resp = recv
-    .read_to_end(usize::MAX)
-    .await
-    .context("failed to read response")?;
+  let conn = scenarios::connect(&endpoint, server_addr).await?;
+  let _trace = qlog.trace(&conn);
 
-  println!("response received:");
+  let resp = files::get(&conn, "sample.json").await?;
+  info!(conn_id = conn.stable_id(), bytes = resp.len(); "response received");
 
   io::stdout().write_all(&resp).unwrap();
   io::stdout().flush().unwrap();
   conn.close(0u32.into(), b"done");
+  endpoint.wait_idle().await;
 
   Ok(())
-}
\ No newline at end of file
+}
@@ -1,69 +1,81 @@
 use std::{
-  fs, io::{self, Write}, net::SocketAddr, path::Path, sync::Arc
+  io::{self, Write}, net::SocketAddr, path::Path, sync::Arc
 };
-use anyhow::{Context, Result};
-use rustls::{
-  pki_types::CertificateDer,
-  crypto::{CryptoProvider, aws_lc_rs},
-};
-
-use quinn::{
-  crypto::rustls::QuicClientConfig,
-  Endpoint,
-  ClientConfig,
+use anyhow::Result;
+use rustls::crypto::{CryptoProvider, aws_lc_rs};
+use log::info;
+use quic_rs_demo::{
+  config::env_or,
+  files,
+  impair::{self, ImpairedSocket, Impairment},
+  keylog,
+  logging,
+  pinning::PinnedServerVerifier,
+  qlog::{Qlog, Vantage},
+  scenarios,
 };
 const CERT_DIR: &str = "/tmp/quinn_certs";
 
 #[tokio::main]
 async fn main() -> Result<()> {
-  let cert_dir: &Path = Path::new(CERT_DIR);
-  let cert_path= cert_dir.join("cert.der");
-  let mut cert_root = rustls::RootCertStore::empty();
-  cert_root.add(CertificateDer::from(fs::read(cert_path)?))?;
-
+  logging::init();
+  let qlog = Qlog::from_env(Vantage::Client)?;
   CryptoProvider::install_default(
     aws_lc_rs::default_provider()
   ).expect("failed to install default crypto provider");
 
-  let tls_config = rustls::ClientConfig::builder()
-    .with_root_certificates(cert_root)
-    .with_no_client_auth();
-  let quic_config = QuicClientConfig::try_from(tls_config)?;
-  let client_config = ClientConfig::new(Arc::new(quic_config));
-  
+  // comma separated SPKI pins, the current key first and backup keys after it
+  let pins = env_or("SERVER_PINS", String::new())?;
+  let tls_config = if pins.is_empty() {
+    scenarios::client_tls(Path::new(CERT_DIR))?
+  } else {
+    let verifier = PinnedServerVerifier::from_list(&pins)?;
+    info!(pins:% = verifier.pins().join(","); "📌 trusting pinned server keys");
+    let mut tls_config = rustls::ClientConfig::builder()
+      .dangerous()
+      .with_custom_certificate_verifier(Arc::new(verifier))
+      .with_no_client_auth();
+    tls_config.key_log = keylog::from_env();
+    tls_config
+  };
+
   let addr: SocketAddr = "127.0.0.1:4385".parse()?;
-  let mut endpoint = Endpoint::client(addr)?;
-  endpoint.set_default_client_config(client_config);
+  let impairment = Impairment::from_env()?;
+  let (endpoint, impaired) = if impairment.is_perfect() {
+    (scenarios::client_endpoint(tls_config, addr)?, None)
+  } else {
+    let seed = env_or("NET_SEED", rand::random())?;
+    info!(seed; "🌧️ impairing what the client sends: {}", impairment);
+    let socket = ImpairedSocket::bind(addr, impairment, seed)?;
+    let mut endpoint = impair::endpoint(socket.clone(), None)?;
+    endpoint.set_default_client_config(scenarios::client_config(tls_config)?);
+    (endpoint, Some(socket))
+  };
 
   let server_addr = "127.0.0.1:4843".parse()?;
-  let conn = endpoint
-    .connect(server_addr,"localhost" )?
-    .await
-    .context("failed to connect to server")?;
-  
-  println!("connected to server {}", server_addr.to_string());
-
-  let (mut send, mut recv) = conn
-  .open_bi()
-  .await
-  .context("failed to open stream")?;
-
-  let req = format!("GET {}\r\n", "sample.json");
-  send.write_all(req.as_bytes())
-    .await
-    .context("failed to send request")?;
-  send.finish().unwrap();
-
-  let resp = recv
-    .read_to_end(usize::MAX)
-    .await
-    .context("failed to read response")?;
+  let conn = scenarios::connect(&endpoint, server_addr).await?;
+  let _trace = qlog.trace(&conn);
 
-  println!("response received:");
+  let resp = files::get(&conn, "sample.json").await?;
+  info!(conn_id = conn.stable_id(), bytes = resp.len(); "response received");
 
   io::stdout().write_all(&resp).unwrap();
   io::stdout().flush().unwrap();
+  if let Some(socket) = impaired {
+    println!();
+    let (path, impaired) = (conn.stats().path, socket.stats());
+    info!(
+      rtt_ms = path.rtt.as_millis(),
+      lost_packets = path.lost_packets,
+      congestion_events = path.congestion_events,
+      dropped = impaired.lost + impaired.too_big + impaired.overflowed,
+      reordered = impaired.reordered,
+      duplicated = impaired.duplicated;
+      "📉 path stats",
+    );
+  }
   conn.close(0u32.into(), b"done");
+  endpoint.wait_idle().await;
 
   Ok(())
-}
\ No newline at end of file
+}
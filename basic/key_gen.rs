@@ -1,29 +1,66 @@
-use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
+use rustls::pki_types::CertificateDer;
 use std::{
+  env,
   path::Path,
   fs,
 };
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use rcgen::{CertificateParams, KeyPair};
+use quic_rs_demo::{certs, pinning::pin_for_cert};
 
 const CERT_DIR: &str = "/tmp/quinn_certs";
 
 fn main() -> Result<()>{
+  let args: Vec<String> = env::args().skip(1).collect();
   let cert_dir = Path::new(CERT_DIR);
+  match args.first().map(String::as_str) {
+    None => generate(cert_dir),
+    Some("pin") => {
+      let cert_path = match args.get(1) {
+        Some(path) => Path::new(path).to_path_buf(),
+        None => cert_dir.join("cert.der"),
+      };
+      let cert = fs::read(&cert_path).context("failed to read certificate")?;
+      println!("📌 Pin for {}: {}", cert_path.display(), pin_for_cert(&cert)?);
+      Ok(())
+    }
+    Some("reissue") => reissue(cert_dir),
+    Some(other) => bail!("unknown command {:?}, expected no command, `pin [cert.der]` or `reissue`", other),
+  }
+}
+
+fn generate(cert_dir: &Path) -> Result<()> {
   if cert_dir.exists() {
     fs::remove_dir_all(cert_dir).context("failed to clear cert_dir")?;
   }
-  fs::create_dir_all(cert_dir).context("failed to create cert_dir")?;
+  let cert = certs::generate_self_signed(cert_dir)?;
+  let cert_path = cert_dir.join("cert.der");
+  let key_path = cert_dir.join("key.der");
+  println!("✅ Finished generating key!\n  📄 Cert: {}\n  🔑 Key:  {}\n  📌 Pin:  {}",
+    cert_path.display(),
+    key_path.display(),
+    pin_for_cert(&cert)?,
+  );
 
+  Ok(())
+}
+
+// a new certificate for the existing key, clients pinning the key keep trusting it
+fn reissue(cert_dir: &Path) -> Result<()> {
   let cert_path = cert_dir.join("cert.der");
   let key_path = cert_dir.join("key.der");
 
-  let cert_key = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
-  let priv_key = PrivatePkcs8KeyDer::from(cert_key.key_pair.serialize_der());
-  let cert: CertificateDer<'_> = cert_key.cert.into();
-  
+  let key = fs::read(&key_path).context("failed to read private key")?;
+  let key_pair = KeyPair::try_from(key.as_slice())?;
+  let cert: CertificateDer<'_> = CertificateParams::new(vec!["localhost".into()])?
+    .self_signed(&key_pair)?
+    .into();
+
   fs::write(&cert_path, &cert).context("failed to write certificate")?;
-  fs::write(&key_path, &priv_key.secret_pkcs8_der()).context("failed to write private key")?;
-  println!("✅ Finished generating key!\n  📄 Cert: {}\n  🔑 Key:  {}", cert_path.display(), key_path.display());
+  println!("✅ Re-issued certificate for the existing key!\n  📄 Cert: {}\n  📌 Pin:  {}",
+    cert_path.display(),
+    pin_for_cert(&cert)?,
+  );
 
   Ok(())
-}
\ No newline at end of file
+}
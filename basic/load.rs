@@ -0,0 +1,41 @@
+use std::{net::SocketAddr, path::Path};
+use anyhow::Result;
+use rustls::crypto::{CryptoProvider, aws_lc_rs};
+use log::info;
+use quic_rs_demo::{
+  config::env_or,
+  load::{self, LoadConfig},
+  logging,
+  scenarios,
+};
+const CERT_DIR: &str = "/tmp/quinn_certs";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+  logging::init();
+  CryptoProvider::install_default(
+    aws_lc_rs::default_provider()
+  ).expect("failed to install default crypto provider");
+
+  let config = LoadConfig::from_env()?;
+  let tls_config = scenarios::client_tls(Path::new(CERT_DIR))?;
+  let addr: SocketAddr = "127.0.0.1:4385".parse()?;
+  let endpoint = scenarios::client_endpoint(tls_config, addr)?;
+
+  let server_addr = env_or("SERVER_ADDR", "127.0.0.1:4843".parse::<SocketAddr>()?)?;
+  info!(
+    connections = config.connections,
+    streams = config.streams,
+    files:% = config.files.join(",");
+    "🏋️ loading {} with {:?}", server_addr, config.limit,
+  );
+  let report = load::run(&endpoint, server_addr, &config).await?;
+  endpoint.wait_idle().await;
+
+  if env_or("REPORT_FORMAT", String::new())? == "json" {
+    println!("{}", serde_json::to_string_pretty(&report)?);
+  } else {
+    print!("{}", report);
+  }
+  Ok(())
+}
@@ -0,0 +1,110 @@
+use std::{
+  fs, io::{self, Write}, net::SocketAddr, path::Path, sync::Arc, time::{Duration, Instant},
+};
+use anyhow::{Context, Result};
+use rustls::{
+  pki_types::CertificateDer,
+  crypto::{CryptoProvider, aws_lc_rs},
+};
+
+use quinn::{
+  Connection,
+  crypto::rustls::QuicClientConfig,
+  Endpoint,
+  ClientConfig,
+  ReadError,
+  ReadToEndError,
+};
+use log::{error, info};
+use quic_rs_demo::{cancellation::{fetch, AppError}, keylog, logging, qlog::{Qlog, Vantage}};
+const CERT_DIR: &str = "/tmp/quinn_certs";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+  logging::init();
+  let qlog = Qlog::from_env(Vantage::Client)?;
+  let cert_dir: &Path = Path::new(CERT_DIR);
+  let cert_path= cert_dir.join("cert.der");
+  let mut cert_root = rustls::RootCertStore::empty();
+  cert_root.add(CertificateDer::from(fs::read(cert_path)?))?;
+
+  CryptoProvider::install_default(
+    aws_lc_rs::default_provider()
+  ).expect("failed to install default crypto provider");
+
+  let mut tls_config = rustls::ClientConfig::builder()
+    .with_root_certificates(cert_root)
+    .with_no_client_auth();
+  tls_config.key_log = keylog::from_env();
+  let quic_config = QuicClientConfig::try_from(tls_config)?;
+  let client_config = ClientConfig::new(Arc::new(quic_config));
+
+  let addr: SocketAddr = "127.0.0.1:4385".parse()?;
+  let mut endpoint = Endpoint::client(addr)?;
+  endpoint.set_default_client_config(client_config);
+
+  let server_addr = "127.0.0.1:4843".parse()?;
+  let conn = endpoint
+    .connect(server_addr,"localhost" )?
+    .await
+    .context("failed to connect to server")?;
+
+  info!(conn_id = conn.stable_id(), remote:% = server_addr; "connected to server");
+  let _trace = qlog.trace(&conn);
+
+  info!("fetching sample.json...");
+  let resp = fetch(&conn, "sample.json", std::future::pending()).await?;
+  io::stdout().write_all(&resp).unwrap();
+  io::stdout().flush().unwrap();
+  println!();
+
+  info!("fetching large.bin, giving up after 50ms...");
+  let start = Instant::now();
+  let result = fetch(&conn, "large.bin", tokio::time::sleep(Duration::from_millis(50))).await;
+  report(result, AppError::Cancelled);
+  info!(elapsed:? = start.elapsed(); "abandoned, connection still usable");
+
+  info!("sending an incomplete request...");
+  report(stall(&conn).await, AppError::Timeout);
+
+  info!("fetching missing.json...");
+  let result = fetch(&conn, "missing.json", std::future::pending()).await;
+  report(result, AppError::ServerError);
+
+  conn.close(0u32.into(), b"done");
+  endpoint.wait_idle().await;
+  Ok(())
+}
+
+// never finishing the request leaves the server waiting until its deadline
+async fn stall(conn: &Connection) -> Result<Vec<u8>> {
+  let (mut send, mut recv) = conn
+    .open_bi()
+    .await
+    .context("failed to open stream")?;
+  send.write_all(b"GET sample")
+    .await
+    .context("failed to send request")?;
+  match recv.read_to_end(usize::MAX).await {
+    Err(ReadToEndError::Read(ReadError::Reset(code))) => {
+      Err(AppError::from_code(code).context("unknown reset code")?.into())
+    }
+    other => Ok(other?),
+  }
+}
+
+fn report(result: Result<Vec<u8>>, expected: AppError) {
+  match result {
+    Err(e) if e.downcast_ref::<AppError>() == Some(&expected) => {
+      info!("✅ Expected Error: {}", expected);
+    }
+    Err(e) => {
+      error!("❌ Unexpected error: {:#}", e);
+      std::process::exit(1);
+    }
+    Ok(resp) => {
+      error!(bytes = resp.len(); "❌ Unexpected success");
+      std::process::exit(1);
+    }
+  }
+}
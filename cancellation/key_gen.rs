@@ -0,0 +1,22 @@
+use std::{
+  path::Path,
+  fs,
+};
+use anyhow::{Context, Result};
+use quic_rs_demo::certs;
+
+const CERT_DIR: &str = "/tmp/quinn_certs";
+
+fn main() -> Result<()>{
+  let cert_dir = Path::new(CERT_DIR);
+  if cert_dir.exists() {
+    fs::remove_dir_all(cert_dir).context("failed to clear cert_dir")?;
+  }
+  certs::generate_self_signed(cert_dir)?;
+
+  let cert_path = cert_dir.join("cert.der");
+  let key_path = cert_dir.join("key.der");
+  println!("✅ Finished generating key!\n  📄 Cert: {}\n  🔑 Key:  {}", cert_path.display(), key_path.display());
+
+  Ok(())
+}
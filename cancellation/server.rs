@@ -0,0 +1,134 @@
+use rustls::{
+  pki_types::{CertificateDer, PrivateKeyDer},
+  crypto::{CryptoProvider, aws_lc_rs},
+};
+use std::{
+  fs, net::SocketAddr, path::Path, sync::Arc, time::{Duration, Instant},
+};
+use anyhow::{Context, Result};
+use quinn::{
+  crypto::rustls::QuicServerConfig,
+  Endpoint,
+  ServerConfig,
+};
+use log::{info, warn};
+use quic_rs_demo::{
+  admission::Admission,
+  cancellation::{FileServer, Outcome},
+  keylog,
+  logging::{self, spawn_logged},
+  metrics::Metrics,
+  qlog::{Qlog, Vantage},
+  scenarios,
+  shutdown,
+};
+
+const CERT_DIR: &str = "/tmp/quinn_certs";
+const FILES_DIR: &str = "/tmp/quinn_files";
+const LARGE_FILE_SIZE: usize = 64 * 1024 * 1024;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+  logging::init();
+  let cert_dir: &Path = Path::new(CERT_DIR);
+  let cert_path= cert_dir.join("cert.der");
+  let key_path = cert_dir.join("key.der");
+
+  let bytes = fs::read(cert_path).context("failed to read certificate")?;
+  let cert = CertificateDer::from(bytes);
+
+  let bytes = fs::read(key_path).context("failed to read private key")?;
+  let key =  PrivateKeyDer::try_from(bytes).map_err(anyhow::Error::msg)?;
+
+  CryptoProvider::install_default(
+    aws_lc_rs::default_provider()
+  ).expect("failed to install default crypto provider");
+
+  let mut tls_config = rustls::ServerConfig::builder()
+  .with_no_client_auth()
+  .with_single_cert(vec![cert], key)?;
+  tls_config.key_log = keylog::from_env();
+
+  let quic_config = QuicServerConfig::try_from(tls_config)?;
+  let server_config = ServerConfig::with_crypto(Arc::new(quic_config));
+
+  prepare_files().context("failed to prepare files")?;
+  let file_server = Arc::new(
+    FileServer::new(FILES_DIR).request_timeout(Duration::from_secs(1))
+  );
+
+  let addr: SocketAddr = "127.0.0.1:4843".parse()?;
+  let endpoint = Endpoint::server(server_config, addr)?;
+
+  let addr = endpoint.local_addr()?;
+  info!(addr:% = addr; "🚀 QUIC server listening");
+  let qlog = Qlog::from_env(Vantage::Server)?;
+  let metrics = Metrics::from_env("cancellation-server").await?;
+  let admission = Admission::from_env()?;
+
+  let serving = {
+    let (endpoint, metrics) = (endpoint.clone(), metrics.clone());
+    async move {
+      while let Some((conn, slot)) = admission.accept(&endpoint, &metrics).await {
+        let remote = conn.remote_address();
+        info!(remote:%; "accepting connection");
+        spawn_logged(
+          format!("connection from {}", remote),
+          slot.hold(handle_conn(conn, file_server.clone(), qlog.clone(), metrics.clone())),
+        );
+      }
+    }
+  };
+  shutdown::serve(&endpoint, &metrics, serving).await
+}
+
+// the large file gives the client something worth abandoning halfway
+fn prepare_files() -> Result<()> {
+  let files_dir = Path::new(FILES_DIR);
+  fs::create_dir_all(files_dir)?;
+  let sample = Path::new(file!()).parent().unwrap().join("sample.json");
+  fs::copy(sample, files_dir.join("sample.json"))?;
+  let large = files_dir.join("large.bin");
+  if fs::metadata(&large).map(|m| m.len() as usize != LARGE_FILE_SIZE).unwrap_or(true) {
+    fs::write(&large, vec![b'q'; LARGE_FILE_SIZE])?;
+  }
+  Ok(())
+}
+
+async fn handle_conn(
+  incomming: quinn::Incoming,
+  file_server: Arc<FileServer>,
+  qlog: Qlog,
+  metrics: Arc<Metrics>,
+) -> Result<()> {
+  let started = Instant::now();
+  let conn = incomming.await.inspect_err(|e| metrics.refused(Some(e)))?;
+  metrics.accepted(&conn, started.elapsed());
+  let conn_id = conn.stable_id();
+  info!(conn_id, remote:% = conn.remote_address(); "established connection");
+  let trace = qlog.trace(&conn);
+  loop {
+    let (send, recv) = match scenarios::accept_bi(&conn, &metrics).await {
+      Err(quinn::ConnectionError::ApplicationClosed { .. }) => {
+        info!(conn_id; "connection closed");
+        return Ok(());
+      }
+      Err(e) => {
+        return Err(e.into());
+      }
+      Ok(s) => s
+    };
+    let file_server = file_server.clone();
+    let trace = trace.clone();
+    let metrics = metrics.clone();
+    tokio::spawn(async move {
+      let stream = send.id();
+      match metrics.stream(trace.stream(stream, file_server.handle_stream(send, recv))).await {
+        Ok(Outcome::Completed(bytes)) => info!(conn_id, stream:%, bytes; "✅ stream completed"),
+        Ok(Outcome::Cancelled) => info!(conn_id, stream:%; "🛑 stream cancelled by client, file released"),
+        Ok(Outcome::TimedOut) => warn!(conn_id, stream:%; "⏰ stream timed out waiting for request"),
+        Err(e) => warn!(conn_id, stream:%; "❌ stream reset with server error: {:#}", e),
+      }
+    });
+  }
+}
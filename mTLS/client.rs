@@ -1,133 +1,89 @@
 use std::{
-  fs, io::{self, Write}, net::SocketAddr, path::Path, sync::Arc,
+  io::{self, Write}, net::SocketAddr, path::Path,
 };
-use anyhow::{Context, Result};
-use rustls::{
-  pki_types::{CertificateDer,PrivateKeyDer},
-  crypto::{CryptoProvider, aws_lc_rs},
+use anyhow::Result;
+use rustls::crypto::{CryptoProvider, aws_lc_rs};
+use log::{error, info};
+use quic_rs_demo::{
+  config::env_or,
+  files,
+  logging,
+  qlog::{Qlog, Vantage},
+  scenarios::{self, mtls},
 };
 
-use quinn::{
-  crypto::rustls::QuicClientConfig,
-  Endpoint,
-  ClientConfig,
-};
-const SERVER_CERT_PATH: &str = "/tmp/quinn_certs/server_cert.der";
-const CLIENT_CERT_PATH: &str = "/tmp/quinn_certs/client_cert.der";
-const CLIENT_KEY_PATH: &str = "/tmp/quinn_certs/client_key.der";
+const CERT_DIR: &str = "/tmp/quinn_certs";
 
 #[tokio::main]
 async fn main() -> Result<()> {
-  // setup authenticated client
-  let server_cert_path = Path::new(SERVER_CERT_PATH);
-  let mut remote_cert_root = rustls::RootCertStore::empty();
-  remote_cert_root.add(CertificateDer::from(fs::read(server_cert_path)?))?;
-
-  let cert_path = Path::new(CLIENT_CERT_PATH);
-  let key_path = Path::new(CLIENT_KEY_PATH);
-
-  let bytes: Vec<u8> = fs::read(cert_path).context("failed to read certificate")?;
-  let cert = CertificateDer::try_from(bytes)?;
-
-  let bytes = fs::read(key_path).context("failed to read private key")?;
-  let key =  PrivateKeyDer::try_from(bytes).map_err(anyhow::Error::msg)?;
+  logging::init();
+  let qlog = Qlog::from_env(Vantage::Client)?;
+  // must match the server's setting, it decides whether the anonymous client gets in
+  let allow_unauthenticated = env_or("ALLOW_UNAUTHENTICATED", false)?;
 
   CryptoProvider::install_default(
     aws_lc_rs::default_provider()
   ).expect("failed to install default crypto provider");
 
-  let tls_config = rustls::ClientConfig::builder()
-    .with_root_certificates(remote_cert_root.clone())
-    .with_client_auth_cert(vec![cert], key)?;
-
-  let quic_config = QuicClientConfig::try_from(tls_config)?;
-  let client_config = ClientConfig::new(Arc::new(quic_config));
-  
+  // setup authenticated client
+  let cert_dir = Path::new(CERT_DIR);
   let addr: SocketAddr = "127.0.0.1:4385".parse()?;
-  let mut endpoint = Endpoint::client(addr)?;
-  endpoint.set_default_client_config(client_config);
+  let endpoint = scenarios::client_endpoint(mtls::client_tls(cert_dir, true)?, addr)?;
 
   let server_addr = "127.0.0.1:4843".parse()?;
-  let conn = endpoint
-    .connect(server_addr,"localhost" )?
-    .await
-    .context("failed to connect to server")?;
-  
-  println!("connected to server {}", server_addr.to_string());
+  let conn = scenarios::connect(&endpoint, server_addr).await?;
+  let _trace = qlog.trace(&conn);
 
-  let (mut send, mut recv) = conn
-  .open_bi()
-  .await
-  .context("failed to open stream")?;
-
-  let req = format!("GET {}\r\n", "sample.json");
-  send.write_all(req.as_bytes())
-    .await
-    .context("failed to send request")?;
-  send.finish().unwrap();
-
-  let resp = recv
-    .read_to_end(usize::MAX)
-    .await
-    .context("failed to read response")?;
-
-  println!("response received:");
-
-  io::stdout().write_all(&resp).unwrap();
-  io::stdout().flush().unwrap();
+  for filename in ["sample.json", "partner.json", "secret.json"] {
+    info!("requesting {}...", filename);
+    let resp = files::get(&conn, filename).await?;
+    print_response(&resp);
+  }
   conn.close(0u32.into(), b"done");
 
   // anonymous client will not be authenticated
-  let tls_config = rustls::ClientConfig::builder()
-    .with_root_certificates(remote_cert_root)
-    .with_no_client_auth();
-
-  let quic_config = QuicClientConfig::try_from(tls_config)?;
-  let client_config = ClientConfig::new(Arc::new(quic_config));
-
   let addr: SocketAddr = "127.0.0.1:4386".parse()?;
-  let mut endpoint = Endpoint::client(addr)?;
-  endpoint.set_default_client_config(client_config);
-
-  println!("\n\nanonymous client connecting...");
-  let server_addr = "127.0.0.1:4843".parse()?;
-  let conn = endpoint
-    .connect(server_addr,"localhost" )?
-    .await
-    .context("failed to connect to server")?;
-
-  println!("connected to server {}", server_addr.to_string());
-
-  println!("anonymous client opening stream...");
-  let (mut send, mut recv) = conn
-    .open_bi()
-    .await
-    .context("failed to open stream")?;
-  println!("done opening stream!");
-
-  println!("anonymous client sending request...");
-  let req = format!("GET {}\r\n", "sample.json");
-    send.write_all(req.as_bytes())
-      .await
-      .context("failed to send request")?;
-    send.finish().unwrap();
-  println!("done sending request!");
-
-  println!("anonymous client reading response...");
-  let result = recv
-      .read_to_end(usize::MAX)
-      .await;
+  let endpoint = scenarios::client_endpoint(mtls::client_tls(cert_dir, false)?, addr)?;
+
+  info!("anonymous client connecting...");
+  let conn = scenarios::connect(&endpoint, server_addr).await?;
+  let _trace = qlog.trace(&conn);
+
+  // a server with optional client auth lets us in, restricted to the anonymous paths
+  if allow_unauthenticated {
+    for filename in ["sample.json", "partner.json"] {
+      info!("anonymous client requesting {}...", filename);
+      let resp = files::get(&conn, filename).await?;
+      print_response(&resp);
+    }
+    conn.close(0u32.into(), b"done");
+    endpoint.wait_idle().await;
+    return Ok(());
+  }
 
-  match result {
+  // the server only rejects the handshake after our Finished arrives, so
+  // depending on timing the failure surfaces when opening, writing or reading
+  info!("anonymous client requesting sample.json...");
+  match files::get(&conn, "sample.json").await {
     Ok(_) => {
       // We expected this to fail, so success is an error.
-      eprintln!("❌ Unexpected success: expected the connection to fail due to missing certificate.");
-      std::process::exit(1); // or return Err(...) if inside a function
+      error!("❌ Unexpected success: expected the connection to fail due to missing certificate.");
+      std::process::exit(1);
     }
     Err(e) => {
-      println!("✅ Expected Error: {:?}", e);
+      info!("✅ Expected Error: {:#}", e);
     }
   }
   conn.close(0u32.into(), b"done");
+  endpoint.wait_idle().await;
   Ok(())
-}
\ No newline at end of file
+}
+
+fn print_response(resp: &[u8]) {
+  info!(bytes = resp.len(); "response received");
+  io::stdout().write_all(resp).unwrap();
+  if !resp.ends_with(b"\n") {
+    println!();
+  }
+  io::stdout().flush().unwrap();
+}
@@ -1,45 +1,61 @@
-use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
 use std::{
+  env,
   path::Path,
   fs,
 };
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use quic_rs_demo::scenarios::mtls::{self, Revocation};
 
 const CERT_DIR: &str = "/tmp/quinn_certs";
 
 fn main() -> Result<()>{
+  let args: Vec<String> = env::args().skip(1).collect();
   let cert_dir = Path::new(CERT_DIR);
+  match args.first().map(String::as_str) {
+    None => generate(cert_dir),
+    Some("revoke") => {
+      let cert_path = match args.get(1) {
+        Some(path) => Path::new(path).to_path_buf(),
+        None => cert_dir.join(mtls::CLIENT_CERT),
+      };
+      revoke(cert_dir, &cert_path)
+    }
+    Some(other) => bail!("unknown command {:?}, expected no command or `revoke [cert.der]`", other),
+  }
+}
+
+fn generate(cert_dir: &Path) -> Result<()> {
   if cert_dir.exists() {
     fs::remove_dir_all(cert_dir).context("failed to clear cert_dir")?;
   }
-  fs::create_dir_all(cert_dir).context("failed to create cert_dir")?;
-
-  let server_cert_path = cert_dir.join("server_cert.der");
-  let server_key_path = cert_dir.join("server_key.der");
-
-  let cert_key = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
-  let priv_key = PrivatePkcs8KeyDer::from(cert_key.key_pair.serialize_der());
-  let cert: CertificateDer<'_> = cert_key.cert.into();
-  
-  fs::write(&server_cert_path, &cert).context("failed to write certificate")?;
-  fs::write(&server_key_path, &priv_key.secret_pkcs8_der()).context("failed to write private key")?;
-
-  let client_cert_path = cert_dir.join("client_cert.der");
-  let client_key_path = cert_dir.join("client_key.der");
-
-  let cert_key = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
-  let priv_key = PrivatePkcs8KeyDer::from(cert_key.key_pair.serialize_der());
-  let cert: CertificateDer<'_> = cert_key.cert.into();
-  
-  fs::write(&client_cert_path, &cert).context("failed to write certificate")?;
-  fs::write(&client_key_path, &priv_key.secret_pkcs8_der()).context("failed to write private key")?;
-
-  println!("✅ Finished generating key!\n  📄 Server Cert: {}\n  🔑 Server Key:  {}\n  📄 Client Cert: {}\n  🔑 Client Key:  {}", 
-    server_cert_path.display(), 
-    server_key_path.display(),
-    client_cert_path.display(),
-    client_key_path.display(),
+  let identity = mtls::generate(cert_dir)?;
+
+  println!("✅ Finished generating key!\n  📄 Server Cert: {}\n  🔑 Server Key:  {}\n  📄 CA Cert:     {}\n  🔑 CA Key:      {}\n  📄 Client Cert: {}\n  🔑 Client Key:  {}\n  📜 CRL:         {}",
+    cert_dir.join(mtls::SERVER_CERT).display(),
+    cert_dir.join(mtls::SERVER_KEY).display(),
+    cert_dir.join(mtls::CA_CERT).display(),
+    cert_dir.join(mtls::CA_KEY).display(),
+    cert_dir.join(mtls::CLIENT_CERT).display(),
+    cert_dir.join(mtls::CLIENT_KEY).display(),
+    cert_dir.join(mtls::CRL).display(),
   );
+  println!("  🪪 Client Identity: {}", identity);
 
   Ok(())
-}
\ No newline at end of file
+}
+
+fn revoke(cert_dir: &Path, cert_path: &Path) -> Result<()> {
+  match mtls::revoke(cert_dir, cert_path)? {
+    Revocation::AlreadyRevoked { subject } => println!("⚠️  {} is already revoked", subject),
+    Revocation::Revoked { subject, serial, crl_number, count } => {
+      println!("✅ Revoked {} (serial {})\n  📜 CRL #{}: {} ({} revoked)",
+        subject,
+        serial,
+        crl_number,
+        cert_dir.join(mtls::CRL).display(),
+        count,
+      );
+    }
+  }
+  Ok(())
+}
@@ -1,115 +1,101 @@
 use std::{
-  fs, io::{self, Write}, net::SocketAddr, path::Path, sync::Arc, time::Duration,
+  fs, io::{self, Write}, net::SocketAddr, path::Path, time::{Duration, SystemTime},
 };
 use anyhow::{Context, Result};
-use rustls::{
-  pki_types::CertificateDer,
-  crypto::{CryptoProvider, aws_lc_rs},
-};
-
-use quinn::{
-  Connection,
-  crypto::rustls::QuicClientConfig,
-  Endpoint,
-  ClientConfig,
+use rustls::crypto::{CryptoProvider, aws_lc_rs};
+use quinn::Connection;
+use log::{debug, info};
+use quic_rs_demo::{
+  files,
+  logging,
+  qlog::{Qlog, Vantage},
+  scenarios::{self, datagram, multiplexing::Pushed},
+  upload::upload,
 };
 const CERT_DIR: &str = "/tmp/quinn_certs";
 
 #[tokio::main]
 async fn main() -> Result<()> {
-  let cert_dir: &Path = Path::new(CERT_DIR);
-  let cert_path= cert_dir.join("cert.der");
-  let mut cert_root = rustls::RootCertStore::empty();
-  cert_root.add(CertificateDer::from(fs::read(cert_path)?))?;
-
+  logging::init();
+  let qlog = Qlog::from_env(Vantage::Client)?;
   CryptoProvider::install_default(
     aws_lc_rs::default_provider()
   ).expect("failed to install default crypto provider");
 
-  let tls_config = rustls::ClientConfig::builder()
-    .with_root_certificates(cert_root)
-    .with_no_client_auth();
-  let quic_config = QuicClientConfig::try_from(tls_config)?;
-  let client_config = ClientConfig::new(Arc::new(quic_config));
-  
+  let tls_config = scenarios::client_tls(Path::new(CERT_DIR))?;
   let addr: SocketAddr = "127.0.0.1:4385".parse()?;
-  let mut endpoint = Endpoint::client(addr)?;
-  endpoint.set_default_client_config(client_config);
+  let endpoint = scenarios::client_endpoint(tls_config, addr)?;
 
   let server_addr = "127.0.0.1:4843".parse()?;
-  let conn = endpoint
-    .connect(server_addr,"localhost" )?
-    .await
-    .context("failed to connect to server")?;
-  
-  println!("connected to server {}", server_addr.to_string());
+  let conn = scenarios::connect(&endpoint, server_addr).await?;
+  let _trace = qlog.trace(&conn);
+
+  let mut pushed = Pushed::listen(&conn);
 
   tokio::try_join!(
     get_sample(&conn, 0),
     get_sample(&conn, 1),
-    upload_data(&conn),
+    upload_data(&conn, &mut pushed),
     send_datagram(&conn),
   )?;
 
-  println!("\nclosing connection...");
+  wait_for_change(&mut pushed).await?;
+
+  info!("closing connection...");
   // gracefully close the connection
   conn.close(0u32.into(), b"done");
   tokio::time::sleep(Duration::from_millis(100)).await;
-  println!("done");
+  info!("done");
   Ok(())
 }
 
 async fn get_sample(conn: &Connection, stream_no: u32) -> Result<()> {
-  println!("\nopen bidirectional stream number {}", stream_no);
-  let (mut send, mut recv) = conn
-  .open_bi()
-  .await
-  .context("failed to open bi_stream")?;
-  println!("stream number {} is sending request ...", stream_no);
-  let req = format!("GET {}\r\n", "sample.json");
-  send.write_all(req.as_bytes())
-    .await
-    .context("failed to send request")?;
-  send.finish().unwrap();
-
-  println!("stream number {} is receiving response ...", stream_no);
-  let resp = recv
-    .read_to_end(usize::MAX)
-    .await
-    .context("failed to read response")?;
-
-  println!("\nresponse received on stream number {}:", stream_no);
+  debug!(stream_no; "sending request ...");
+  let resp = files::get(conn, "sample.json").await?;
+  info!(stream_no, bytes = resp.len(); "response received");
 
   io::stdout().write_all(&resp).unwrap();
   io::stdout().flush().unwrap();
-  println!("");
+  println!();
   Ok(())
 }
 
-async fn upload_data(conn: &Connection) -> Result<()> {
-  println!("\nopen unidirectional stream");
-  let mut send = conn
-  .open_uni()
-  .await
-  .context("failed to open uni_stream")?;
-
+async fn upload_data(conn: &Connection, pushed: &mut Pushed) -> Result<()> {
+  debug!("open unidirectional stream");
   let path = Path::new(file!());
   let path = path.parent().unwrap().join("sample.json");
   let data = fs::read(&path).context("failed reading file")?;
-  println!("uni_stream uploading data...");
-  send.write_all(&data)
-    .await
-    .context("failed to send request")?;
-  send.finish().unwrap();
-  println!("Done uploading data with uni_stream!");
+  info!("uni_stream uploading data...");
+  upload(conn, "sample.json", &data).await?;
+  debug!("Done uploading data with uni_stream!");
+  let confirmation = pushed.confirmation().await?;
+  info!("server confirmed: {}", confirmation);
+
+  info!("uploading more data than the quota allows...");
+  upload(conn, "too_big.bin", &vec![0u8; 2 * 1024 * 1024]).await?;
+  let confirmation = pushed.confirmation().await?;
+  info!("server confirmed: {}", confirmation);
+  Ok(())
+}
+
+// client and server share the disk in this demo, so touching the file the
+// server serves stands in for someone editing it on the server
+async fn wait_for_change(pushed: &mut Pushed) -> Result<()> {
+  info!("touching sample.json to simulate an edit on the server...");
+  let path = Path::new(file!()).parent().unwrap().join("sample.json");
+  fs::File::options()
+    .append(true)
+    .open(&path)?
+    .set_modified(SystemTime::now())?;
+  let notification = pushed.change(Duration::from_secs(3)).await?;
+  info!("🔔 server pushed: {} changed", notification.body);
   Ok(())
 }
 
 async fn send_datagram(conn: &Connection) -> Result<()> {
-  println!("\nStart sending/receiving datagram...");
-  conn.send_datagram(b"Hello from client"[..].into()).context("failed to send datagram")?;
-  let msg = conn.read_datagram().await.context("failed to receive datagram response")?;
-  println!("recevied datagram response: \n{}", std::str::from_utf8(&msg)?);
-  println!("Done sending/receiving datagram!");
+  info!("Start sending/receiving datagram...");
+  let resp = datagram::send(conn, "Hello from client").await?;
+  info!("recevied datagram response: {}", resp);
+  debug!("Done sending/receiving datagram!");
   Ok(())
-}
\ No newline at end of file
+}
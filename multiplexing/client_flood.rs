@@ -0,0 +1,119 @@
+use std::{
+  fs, net::SocketAddr, path::Path, sync::Arc, time::{Duration, Instant},
+};
+use anyhow::{Context, Result, bail};
+use rustls::{
+  pki_types::CertificateDer,
+  crypto::{CryptoProvider, aws_lc_rs},
+};
+
+use quinn::{
+  Connection,
+  crypto::rustls::QuicClientConfig,
+  Endpoint,
+  ClientConfig,
+};
+use log::info;
+use quic_rs_demo::{config::env_or, keylog, logging, qlog::{Qlog, Vantage}};
+use tokio::task::JoinSet;
+const CERT_DIR: &str = "/tmp/quinn_certs";
+const STREAMS: usize = 40;
+const HOLD_MS: u64 = 200;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+  logging::init();
+  let qlog = Qlog::from_env(Vantage::Client)?;
+  let streams = env_or("STREAMS", STREAMS)?;
+  if streams == 0 {
+    bail!("STREAMS must be at least 1");
+  }
+  let hold = Duration::from_millis(env_or("HOLD_MS", HOLD_MS)?);
+
+  let cert_dir: &Path = Path::new(CERT_DIR);
+  let cert_path= cert_dir.join("cert.der");
+  let mut cert_root = rustls::RootCertStore::empty();
+  cert_root.add(CertificateDer::from(fs::read(cert_path)?))?;
+
+  CryptoProvider::install_default(
+    aws_lc_rs::default_provider()
+  ).expect("failed to install default crypto provider");
+
+  let mut tls_config = rustls::ClientConfig::builder()
+    .with_root_certificates(cert_root)
+    .with_no_client_auth();
+  tls_config.key_log = keylog::from_env();
+  let quic_config = QuicClientConfig::try_from(tls_config)?;
+  let client_config = ClientConfig::new(Arc::new(quic_config));
+
+  let addr: SocketAddr = "127.0.0.1:4385".parse()?;
+  let mut endpoint = Endpoint::client(addr)?;
+  endpoint.set_default_client_config(client_config);
+
+  let server_addr = "127.0.0.1:4843".parse()?;
+  let conn = endpoint
+    .connect(server_addr,"localhost" )?
+    .await
+    .context("failed to connect to server")?;
+
+  info!(conn_id = conn.stable_id(), remote:% = server_addr; "connected to server");
+  let _trace = qlog.trace(&conn);
+  info!(streams, hold:?; "opening all streams at once, each held open...");
+
+  let start = Instant::now();
+  let mut tasks = JoinSet::new();
+  for stream_no in 0..streams {
+    let conn = conn.clone();
+    tasks.spawn(async move {
+      hold_stream(&conn, stream_no, hold).await
+    });
+  }
+  let mut blocked = Vec::with_capacity(streams);
+  while let Some(result) = tasks.join_next().await {
+    blocked.push(result??);
+  }
+  let elapsed = start.elapsed();
+
+  blocked.sort();
+  let queued = blocked.iter().filter(|d| **d >= Duration::from_millis(1)).count();
+  let stats = conn.stats();
+  println!("📊 {} streams done in {:?}", streams, elapsed);
+  println!("  queued in open_bi:   {} of {}", queued, streams);
+  println!("  blocked time min:    {:?}", blocked[0]);
+  println!("  blocked time p50:    {:?}", percentile(&blocked, 50));
+  println!("  blocked time p90:    {:?}", percentile(&blocked, 90));
+  println!("  blocked time max:    {:?}", blocked[blocked.len() - 1]);
+  println!("  MAX_STREAMS received: {}", stats.frame_rx.max_streams_bidi);
+
+  conn.close(0u32.into(), b"done");
+  endpoint.wait_idle().await;
+  Ok(())
+}
+
+// returns how long open_bi() waited for the server to grant stream credit
+async fn hold_stream(conn: &Connection, stream_no: usize, hold: Duration) -> Result<Duration> {
+  let start = Instant::now();
+  let (mut send, mut recv) = conn
+    .open_bi()
+    .await
+    .context("failed to open bi_stream")?;
+  let blocked = start.elapsed();
+  info!(stream_no, stream:% = send.id(), blocked:?; "stream opened");
+
+  let req = format!("GET {}\r\n", "sample.json");
+  send.write_all(req.as_bytes())
+    .await
+    .context("failed to send request")?;
+  // the server cannot read the request to its end until the stream is finished,
+  // so the stream keeps counting against the limit meanwhile
+  tokio::time::sleep(hold).await;
+  send.finish().unwrap();
+  recv.read_to_end(usize::MAX)
+    .await
+    .context("failed to read response")?;
+  Ok(blocked)
+}
+
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+  sorted[(sorted.len() - 1) * p / 100]
+}
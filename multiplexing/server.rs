@@ -1,205 +1,67 @@
-use rustls::{
-  pki_types::{CertificateDer, PrivateKeyDer},
-  crypto::{CryptoProvider, aws_lc_rs},
-};
-use core::ascii;
-use std::{
-  fmt::Write, fs, io::{self}, net::SocketAddr, path::Path, str, sync::Arc, time::Duration
-};
-use io::Write as IoWrite;
-use anyhow::{Context, Result, bail};
+use rustls::crypto::{CryptoProvider, aws_lc_rs};
+use std::{net::SocketAddr, path::Path, sync::Arc, time::Duration};
+use anyhow::Result;
 use quinn::{
   crypto::rustls::QuicServerConfig,
   Endpoint,
   ServerConfig,
   TransportConfig,
-  Incoming,
-  Connection,
-  SendStream, 
-  RecvStream,
-  ConnectionError,
 };
-use colored::*;
-// use  proto::crypto::rustls::QuicServerConfig,
+use log::info;
+use quic_rs_demo::{
+  admission::Admission,
+  config::env_or,
+  logging,
+  metrics::Metrics,
+  push::FileWatcher,
+  qlog::{Qlog, Vantage},
+  scenarios::{self, multiplexing},
+  shutdown,
+  transport::FlowControl,
+  upload::UploadService,
+};
 
 const CERT_DIR: &str = "/tmp/quinn_certs";
+const UPLOAD_DIR: &str = "/tmp/quinn_uploads";
+const UPLOAD_QUOTA: u64 = 1024 * 1024;
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
 
 #[tokio::main]
 async fn main() -> Result<()> {
-  let cert_dir: &Path = Path::new(CERT_DIR);
-  let cert_path= cert_dir.join("cert.der");
-  let key_path = cert_dir.join("key.der");
-
-  let bytes = fs::read(cert_path).context("failed to read certificate")?;
-  let cert = CertificateDer::try_from(bytes)?;
-
-  let bytes = fs::read(key_path).context("failed to read private key")?;
-  let key =  PrivateKeyDer::try_from(bytes).map_err(anyhow::Error::msg)?;
-
+  logging::init();
   CryptoProvider::install_default(
     aws_lc_rs::default_provider()
   ).expect("failed to install default crypto provider");
 
-  let tls_config = rustls::ServerConfig::builder()
-  .with_no_client_auth()
-  .with_single_cert(vec![cert], key)?;
+  let tls_config = scenarios::server_tls(Path::new(CERT_DIR))?;
 
+  let flow_control = FlowControl::from_env()?;
   let mut transport_config = TransportConfig::default();
+  flow_control.apply(&mut transport_config)?;
   transport_config.max_idle_timeout(Some(Duration::from_secs(2).try_into()?));
 
   let quic_config = QuicServerConfig::try_from(tls_config)?;
   let mut server_config = ServerConfig::with_crypto(Arc::new(quic_config));
   server_config.transport_config(Arc::new(transport_config));
 
+  let upload_dir = env_or("UPLOAD_DIR", UPLOAD_DIR.to_string())?;
+  let quota = env_or("UPLOAD_QUOTA", UPLOAD_QUOTA)?;
+  let uploads = Arc::new(UploadService::new(upload_dir, quota)?);
+  // clients are told when a file they fetched changes on disk
+  let watcher = FileWatcher::new();
+  watcher.watch(WATCH_INTERVAL);
+
   let addr: SocketAddr = "127.0.0.1:4843".parse()?;
   let endpoint = Endpoint::server(server_config, addr)?;
 
   let addr = endpoint.local_addr()?;
-  println!(
-    "{} {}",
-    "🚀 QUIC server listening at:".bold().green(),
-    addr.to_string().blue());
-
-  while let Some(conn) = endpoint.accept().await {
-    println!("accepting connection from {}", conn.remote_address());
-    tokio::spawn(async move {
-      handle_conn(conn).await
-    });  
-  }
-  Ok(())
-}
-
-async fn handle_conn(incomming: Incoming) -> Result<()> {
-  let conn = incomming.await?;
-  println!("established connection from {}", conn.remote_address());
-  loop {
-    tokio::select! {
-      result = conn.accept_bi() => {
-        match result {
-          Err(e) => {
-            return handle_error(e);
-          }
-          Ok((send, recv)) => {
-            println!("accepting bidirectional stream...");
-            tokio::spawn(async move {
-              handle_bi_stream(send, recv).await
-            });
-          }
-        }
-      }
-
-      result = conn.accept_uni() => {
-        match result {
-          Err(e) => {
-            return handle_error(e);
-          }
-          Ok(recv) => {
-            println!("accepting unidirectional stream...");
-            tokio::spawn(async move {
-              handle_uni_stream(recv).await
-            });
-          }
-        }
-      }
-
-      result = conn.read_datagram() => {
-        match result {
-          Err(e) => {
-            return handle_error(e);
-          }
-          Ok(msg) => {
-            println!("accepting datagram from client...");
-            if let Err(e) = handle_datagram(&conn, msg) {
-              eprintln!("datagram error {:?}", e);
-            }
-          }
-        }
-      }
-    }
-  }
+  info!(addr:% = addr; "🚀 QUIC server listening");
+  let qlog = Qlog::from_env(Vantage::Server)?;
+  let metrics = Metrics::from_env("multiplexing-server").await?;
+  let admission = Admission::from_env()?;
+  info!(flow_control:%; "flow control");
+
+  let root = Path::new(file!()).parent().unwrap().to_path_buf();
+  let serving = multiplexing::serve(endpoint.clone(), admission, root, uploads, watcher, qlog, metrics.clone());
+  shutdown::serve(&endpoint, &metrics, serving).await
 }
-
-async fn handle_bi_stream(mut send: SendStream, mut recv:  RecvStream) -> Result<()> {
-  let req = recv
-  .read_to_end(64*1024)
-  .await
-  .context("failed reading request")?;
-
-  let mut escaped = String::new();
-  for &x in &req {
-    for c in ascii::escape_default(x) {
-      escaped.write_char(c as char).unwrap();
-    }
-  }
-  println!("req {}", escaped);
-
-  let resp = handle_req(&req).unwrap_or_else(
-    |e| {
-      println!("handle request failed: {}", e);
-      String::from("failed to handle request").into_bytes()
-  });
-  send.write_all(&resp).await.context("failed to send response")?;
-  send.finish().unwrap();
-  println!("complete bidirectional stream handling!");
-  Ok(())
-}
-
-fn handle_req(req: &[u8]) -> Result<Vec<u8>> {
-  // only accept GET request
-  if req.len() < 4 || &req[0..4] != b"GET " {
-    bail!("missing GET");
-  }
-  if req[4..].len() < 2 || &req[req.len() - 2..] != b"\r\n" {
-      bail!("missing \\r\\n");
-  }
-  let filename = &req[4..req.len()-2];
-  let filename = str::from_utf8(&filename).context("filename is malformed UTF-8")?;
-  let path = Path::new(file!());
-  let path = path.parent().unwrap().join(filename);
-  let bytes = fs::read(&path).context("failed reading file")?;
-  Ok(bytes)
-}
-
-async fn handle_uni_stream(mut recv: RecvStream) -> Result<()> {
-  let data = recv
-    .read_to_end(64*1024)
-    .await
-    .context("failed reading data")?;
-
-    println!("\ndata received from client:");
-
-  io::stdout().write_all(&data).unwrap();
-  io::stdout().flush().unwrap();
-  println!("\nDone handle uni_stream!");
-  Ok(())
-}
-
-fn handle_error(e: ConnectionError) -> Result<()> {
-  match e {
-    quinn::ConnectionError::ApplicationClosed { .. } => {
-      println!("connection closed");
-      Ok(())
-    }
-    quinn::ConnectionError::TimedOut { .. } => {
-      println!("timeout waiting, drop connection");
-      Ok(())
-    }
-    other => Err(other.into()),
-  }
-}
-
-fn handle_datagram(conn: &Connection, msg: bytes::Bytes) -> Result<()> {
-  let msg = std::str::from_utf8(&msg);
-  match msg {
-    Ok(msg) => {
-      println!("received datagram: \n{}", msg);
-      println!("sending datagram to client {}...", conn.remote_address());
-      conn.send_datagram(b"Hello from server"[..].into()).context("failed to send datagram response")?;
-      println!("Done respond to datagram!");
-      Ok(())
-    }
-    Err(e) => {
-      Err(e.into())
-    }
-  }
-}
\ No newline at end of file
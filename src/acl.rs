@@ -0,0 +1,64 @@
+use std::{fs, path::Path};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::{files, identity::ClientIdentity};
+
+/// Maps client identities to the paths they may request, loaded from JSON:
+///
+/// ```json
+/// { "rules": [ { "identity": "cn:alice", "paths": ["sample.json", "public-*"] } ] }
+/// ```
+///
+/// An identity is matched by `cn:<common name>`, `san:<subject alt name>`,
+/// `spki:<base64 sha256>`, `*` for any authenticated client or `anonymous`
+/// for clients that presented no certificate. A path is either exact, a
+/// prefix ending in `*`, or `*` for everything. Only plain file names are
+/// matched, see [`files::valid_filename`]: nothing allows `../secret.json`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Acl {
+  rules: Vec<AclRule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct AclRule {
+  identity: String,
+  paths: Vec<String>,
+}
+
+impl Acl {
+  pub fn load(path: &Path) -> Result<Self> {
+    let bytes = fs::read(path).context("failed to read ACL")?;
+    serde_json::from_slice(&bytes).context("failed to parse ACL")
+  }
+
+  /// `identity` is `None` for an anonymous client.
+  pub fn allows(&self, identity: Option<&ClientIdentity>, path: &str) -> bool {
+    if !files::valid_filename(path) {
+      return false;
+    }
+    self.rules
+      .iter()
+      .filter(|rule| matches_identity(&rule.identity, identity))
+      .any(|rule| rule.paths.iter().any(|pattern| matches_path(pattern, path)))
+  }
+}
+
+fn matches_identity(pattern: &str, identity: Option<&ClientIdentity>) -> bool {
+  let Some(identity) = identity else {
+    return pattern == "anonymous";
+  };
+  match pattern.split_once(':') {
+    Some(("cn", cn)) => identity.common_name.as_deref() == Some(cn),
+    Some(("san", san)) => identity.sans.iter().any(|s| s == san),
+    Some(("spki", spki)) => identity.spki_sha256 == spki,
+    _ => pattern == "*",
+  }
+}
+
+fn matches_path(pattern: &str, path: &str) -> bool {
+  match pattern.strip_suffix('*') {
+    Some(prefix) => path.starts_with(prefix),
+    None => pattern == path,
+  }
+}
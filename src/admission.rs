@@ -0,0 +1,346 @@
+//! Admission control: what a server does with each `Incoming` before its
+//! handshake. Unvalidated addresses must answer a Retry once the server is
+//! loaded or when they send more connection attempts than any real client.
+//! Validated addresses doing that are ignored for a while, and connections
+//! past the global or per-IP cap are refused.
+//!
+//! An unvalidated address may be spoofed, so only attempts from validated ones
+//! can get an IP ignored: a flood forged from a victim's IP costs the victim
+//! one more round trip, never its access.
+
+use std::{
+  collections::HashMap,
+  fmt,
+  future::Future,
+  mem,
+  net::{IpAddr, SocketAddr},
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+use anyhow::Result;
+use log::{debug, info};
+use quinn::{Endpoint, Incoming};
+
+use crate::{config::env_or, metrics::Metrics};
+
+const ATTEMPTS_WINDOW: Duration = Duration::from_secs(1);
+/// How long a validated source sending too many attempts is ignored.
+const IGNORE_DURATION: Duration = Duration::from_secs(10);
+/// Sources whose attempts are counted at once. Spoofed floods use many, new
+/// sources past this are not tracked: unvalidated ones must answer a Retry,
+/// validated ones take the place of a source that never validated, and are
+/// ignored when there is none.
+pub const MAX_TRACKED_SOURCES: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+  /// connections open or in their handshake
+  pub max_connections: usize,
+  pub max_per_ip: usize,
+  /// connections beyond which clients must validate their address with a
+  /// Retry, 0 to always ask
+  pub retry_above: usize,
+  /// connection attempts per second from one IP beyond which unvalidated ones
+  /// must answer a Retry and validated ones get the IP ignored
+  pub max_attempts_per_sec: u32,
+}
+
+impl Default for Limits {
+  fn default() -> Self {
+    Self { max_connections: 1024, max_per_ip: 32, retry_above: 256, max_attempts_per_sec: 100 }
+  }
+}
+
+impl Limits {
+  /// Reads `MAX_CONNECTIONS`, `MAX_CONNECTIONS_PER_IP`, `RETRY_ABOVE` and
+  /// `MAX_ATTEMPTS_PER_SEC`.
+  pub fn from_env() -> Result<Self> {
+    let default = Self::default();
+    Ok(Self {
+      max_connections: env_or("MAX_CONNECTIONS", default.max_connections)?,
+      max_per_ip: env_or("MAX_CONNECTIONS_PER_IP", default.max_per_ip)?,
+      retry_above: env_or("RETRY_ABOVE", default.retry_above)?,
+      max_attempts_per_sec: env_or("MAX_ATTEMPTS_PER_SEC", default.max_attempts_per_sec)?,
+    })
+  }
+}
+
+impl fmt::Display for Limits {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{} connections, {} per IP, retry above {}, {} attempts/s per IP",
+      self.max_connections, self.max_per_ip, self.retry_above, self.max_attempts_per_sec,
+    )
+  }
+}
+
+/// What to do with an `Incoming`. An accepted one holds a [`Slot`].
+#[derive(Debug)]
+pub enum Decision {
+  Accept(Slot),
+  Refuse,
+  Retry,
+  Ignore,
+}
+
+impl Decision {
+  pub const NAMES: [&'static str; 4] = ["accept", "refuse", "retry", "ignore"];
+
+  pub(crate) fn index(&self) -> usize {
+    match self {
+      Self::Accept(_) => 0,
+      Self::Refuse => 1,
+      Self::Retry => 2,
+      Self::Ignore => 3,
+    }
+  }
+}
+
+impl fmt::Display for Decision {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(Self::NAMES[self.index()])
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Window {
+  started: Instant,
+  attempts: u32,
+}
+
+impl Window {
+  // counts an attempt, returning the attempts in the current window
+  fn count(&mut self, now: Instant) -> u32 {
+    if now.duration_since(self.started) >= ATTEMPTS_WINDOW {
+      *self = Self { started: now, attempts: 0 };
+    }
+    self.attempts += 1;
+    self.attempts
+  }
+}
+
+// attempts from unvalidated and validated addresses are counted apart, so a
+// spoofed flood does not count against the real owner of the IP
+#[derive(Debug)]
+struct Source {
+  unvalidated: Window,
+  validated: Window,
+  ignored_until: Option<Instant>,
+}
+
+impl Source {
+  fn new(now: Instant) -> Self {
+    let window = Window { started: now, attempts: 0 };
+    Self { unvalidated: window, validated: window, ignored_until: None }
+  }
+
+  // only spoofable attempts were counted, so nothing is lost by forgetting it
+  fn unvalidated_only(&self) -> bool {
+    self.validated.attempts == 0
+  }
+}
+
+// the sources seen in this attempts window and the one before: a source
+// silent for a whole window is forgotten when the next one starts, at the
+// cost of dropping one map instead of scanning for idle sources
+#[derive(Debug)]
+struct Sources {
+  current: HashMap<IpAddr, Source>,
+  previous: HashMap<IpAddr, Source>,
+  rotated: Instant,
+}
+
+impl Sources {
+  fn new(now: Instant) -> Self {
+    Self { current: HashMap::new(), previous: HashMap::new(), rotated: now }
+  }
+
+  // the source of `ip`, none when it is new and MAX_TRACKED_SOURCES are
+  // tracked, unless it is validated and a source that never was makes room
+  fn get(&mut self, ip: IpAddr, now: Instant, validated: bool) -> Option<&mut Source> {
+    let since = now.duration_since(self.rotated);
+    if since >= ATTEMPTS_WINDOW {
+      let current = mem::take(&mut self.current);
+      self.previous = if since < 2 * ATTEMPTS_WINDOW { current } else { HashMap::new() };
+      self.rotated = now;
+    }
+    if !self.current.contains_key(&ip) {
+      let source = match self.previous.remove(&ip) {
+        Some(source) => source,
+        None if self.current.len() + self.previous.len() >= MAX_TRACKED_SOURCES => {
+          if !validated || !self.evict_unvalidated() {
+            return None;
+          }
+          Source::new(now)
+        }
+        None => Source::new(now),
+      };
+      self.current.insert(ip, source);
+    }
+    self.current.get_mut(&ip)
+  }
+
+  // forgets a source that never validated, the older generation first. Scans
+  // the table, but only validated sources get here and each took a round trip
+  fn evict_unvalidated(&mut self) -> bool {
+    for sources in [&mut self.previous, &mut self.current] {
+      let evicted = sources.iter().find(|(_, source)| source.unvalidated_only()).map(|(ip, _)| *ip);
+      if let Some(ip) = evicted {
+        sources.remove(&ip);
+        return true;
+      }
+    }
+    false
+  }
+}
+
+#[derive(Debug)]
+struct State {
+  open: usize,
+  // only IPs with open connections, at most `max_connections` of them
+  open_per_ip: HashMap<IpAddr, usize>,
+  sources: Sources,
+}
+
+/// Decides on incoming connections against [`Limits`], counting the ones open
+/// through the [`Slot`]s it hands out.
+#[derive(Debug)]
+pub struct Admission {
+  limits: Limits,
+  state: Mutex<State>,
+}
+
+impl Admission {
+  pub fn new(limits: Limits) -> Arc<Self> {
+    let state = State { open: 0, open_per_ip: HashMap::new(), sources: Sources::new(Instant::now()) };
+    Arc::new(Self { limits, state: Mutex::new(state) })
+  }
+
+  /// Admits with [`Limits::from_env`].
+  pub fn from_env() -> Result<Arc<Self>> {
+    let limits = Limits::from_env()?;
+    info!(limits:%; "🚪 admitting connections");
+    Ok(Self::new(limits))
+  }
+
+  pub fn limits(&self) -> Limits {
+    self.limits
+  }
+
+  /// Connections accepted and not closed yet.
+  pub fn open(&self) -> usize {
+    self.state.lock().unwrap().open
+  }
+
+  /// Decides on a connection attempt from `remote`, whose address is
+  /// `validated` when it answered a Retry.
+  pub fn decide(self: &Arc<Self>, remote: SocketAddr, validated: bool) -> Decision {
+    let now = Instant::now();
+    let ip = remote.ip();
+    let max_attempts = self.limits.max_attempts_per_sec;
+    let mut state = self.state.lock().unwrap();
+    let open = state.open;
+    let source = state.sources.get(ip, now, validated);
+
+    if let Some(source) = &source {
+      if source.ignored_until.is_some_and(|until| now < until) {
+        return Decision::Ignore;
+      }
+    }
+    if !validated {
+      // an untracked source cannot be rate limited, so it must validate first
+      let flooding = source.is_none_or(|source| source.unvalidated.count(now) > max_attempts);
+      if flooding || open >= self.limits.retry_above {
+        return Decision::Retry;
+      }
+    } else {
+      // an untracked validated source could flood unchecked
+      let Some(source) = source else {
+        return Decision::Ignore;
+      };
+      if source.validated.count(now) > max_attempts {
+        source.ignored_until = Some(now + IGNORE_DURATION);
+        return Decision::Ignore;
+      }
+    }
+    let open_from_ip = state.open_per_ip.get(&ip).copied().unwrap_or(0);
+    if open >= self.limits.max_connections || open_from_ip >= self.limits.max_per_ip {
+      return Decision::Refuse;
+    }
+
+    *state.open_per_ip.entry(ip).or_default() += 1;
+    state.open += 1;
+    Decision::Accept(Slot { admission: self.clone(), ip })
+  }
+
+  /// Decides on `incoming` and carries the decision out, counting it in
+  /// `metrics`. Returns the connection to handshake when it is accepted.
+  pub fn admit(self: &Arc<Self>, incoming: Incoming, metrics: &Metrics) -> Option<(Incoming, Slot)> {
+    let remote = incoming.remote_address();
+    let decision = self.decide(remote, incoming.remote_address_validated());
+    metrics.admission(&decision);
+    match decision {
+      Decision::Accept(slot) => return Some((incoming, slot)),
+      Decision::Refuse => {
+        info!(remote:%; "🚫 refusing connection, too many open");
+        incoming.refuse();
+      }
+      Decision::Retry => {
+        debug!(remote:%; "🔁 asking to validate address");
+        // only fails for an address already validated, which never gets here
+        if let Err(e) = incoming.retry() {
+          e.into_incoming().refuse();
+        }
+      }
+      // a flood would fill the log
+      Decision::Ignore => {
+        debug!(remote:%; "🙈 ignoring connection attempt");
+        incoming.ignore();
+      }
+    }
+    None
+  }
+
+  /// The next connection accepted on `endpoint`, none once it closes.
+  pub async fn accept(self: &Arc<Self>, endpoint: &Endpoint, metrics: &Metrics) -> Option<(Incoming, Slot)> {
+    loop {
+      let incoming = endpoint.accept().await?;
+      if let Some(admitted) = self.admit(incoming, metrics) {
+        return Some(admitted);
+      }
+    }
+  }
+
+  fn release(&self, ip: IpAddr) {
+    let mut state = self.state.lock().unwrap();
+    state.open -= 1;
+    if let Some(open) = state.open_per_ip.get_mut(&ip) {
+      *open -= 1;
+      if *open == 0 {
+        state.open_per_ip.remove(&ip);
+      }
+    }
+  }
+}
+
+/// An accepted connection's place in the limits, given back when dropped.
+#[derive(Debug)]
+pub struct Slot {
+  admission: Arc<Admission>,
+  ip: IpAddr,
+}
+
+impl Slot {
+  /// Runs `task`, the connection's, holding the slot until it finishes or is dropped.
+  pub async fn hold<F: Future>(self, task: F) -> F::Output {
+    let _slot = self;
+    task.await
+  }
+}
+
+impl Drop for Slot {
+  fn drop(&mut self) {
+    self.admission.release(self.ip);
+  }
+}
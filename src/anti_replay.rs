@@ -0,0 +1,115 @@
+use std::{
+  collections::HashMap, fmt, sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex}, time::{Duration, Instant},
+};
+use log::warn;
+use rustls::server::StoresServerSessions;
+use sha2::{Digest, Sha256};
+
+/// Remembers which session tickets have been redeemed within a time window.
+///
+/// Servers sharing a session store must share the register too, or a flight
+/// captured at one of them can be replayed at another. Implementations backed
+/// by a shared store answer synchronously, since rustls asks during the
+/// handshake, and should refuse when the store cannot be reached.
+pub trait StrikeRegister: fmt::Debug + Send + Sync {
+  /// Records `id` for `window` and returns whether it was new.
+  fn insert(&self, id: &[u8; 32], window: Duration) -> bool;
+}
+
+/// A [`StrikeRegister`] for a single server process.
+///
+/// When full it refuses every new ticket until entries expire: a resumption
+/// falling back to a full handshake is cheap, a replay getting through is not.
+#[derive(Debug)]
+pub struct MemoryStrikeRegister {
+  capacity: usize,
+  seen: Mutex<HashMap<[u8; 32], Instant>>,
+}
+
+impl MemoryStrikeRegister {
+  pub fn new(capacity: usize) -> Self {
+    Self { capacity, seen: Mutex::new(HashMap::new()) }
+  }
+
+  pub fn len(&self) -> usize {
+    self.seen.lock().unwrap().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+impl StrikeRegister for MemoryStrikeRegister {
+  fn insert(&self, id: &[u8; 32], window: Duration) -> bool {
+    let now = Instant::now();
+    let mut seen = self.seen.lock().unwrap();
+    if seen.get(id).is_some_and(|expires| *expires > now) {
+      return false;
+    }
+    if seen.len() >= self.capacity {
+      seen.retain(|_, expires| *expires > now);
+      if seen.len() >= self.capacity {
+        return false;
+      }
+    }
+    seen.insert(*id, now + window);
+    true
+  }
+}
+
+/// Wraps a session store so every ticket can be redeemed only once.
+///
+/// 0-RTT needs stateful tickets, and a store shared by several servers cannot
+/// always delete a ticket everywhere the moment it is taken: a captured
+/// ClientHello and its 0-RTT data can then be replayed at another server. A
+/// ticket seen again within `lifetime` is treated as unknown, so the handshake
+/// falls back to a full one and the early data is rejected. `lifetime` must be
+/// at least as long as `inner` keeps a session.
+///
+/// A client that is sent a HelloRetryRequest offers its ticket twice in one
+/// handshake, the second offer then falls back to a full handshake too.
+#[derive(Debug)]
+pub struct AntiReplayStore {
+  inner: Arc<dyn StoresServerSessions>,
+  register: Arc<dyn StrikeRegister>,
+  lifetime: Duration,
+  refused: AtomicU64,
+}
+
+impl AntiReplayStore {
+  pub fn new(inner: Arc<dyn StoresServerSessions>, register: Arc<dyn StrikeRegister>, lifetime: Duration) -> Self {
+    Self { inner, register, lifetime, refused: AtomicU64::new(0) }
+  }
+
+  /// How many replayed tickets have been refused so far.
+  pub fn refused(&self) -> u64 {
+    self.refused.load(Ordering::Relaxed)
+  }
+}
+
+impl StoresServerSessions for AntiReplayStore {
+  fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+    self.inner.put(key, value)
+  }
+
+  fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+    self.inner.get(key)
+  }
+
+  fn take(&self, key: &[u8]) -> Option<Vec<u8>> {
+    // unknown tickets never reach the register
+    let value = self.inner.take(key)?;
+    let id: [u8; 32] = Sha256::digest(key).into();
+    if !self.register.insert(&id, self.lifetime) {
+      self.refused.fetch_add(1, Ordering::Relaxed);
+      warn!("🛑 refused a session ticket that was already redeemed");
+      return None;
+    }
+    Some(value)
+  }
+
+  fn can_cache(&self) -> bool {
+    self.inner.can_cache()
+  }
+}
@@ -0,0 +1,206 @@
+use std::{
+  fmt, future::Future, path::PathBuf, str, time::Duration,
+};
+use anyhow::{Context, Result, bail};
+use quinn::{
+  Connection,
+  SendStream,
+  RecvStream,
+  ReadError,
+  ReadToEndError,
+  WriteError,
+  VarInt,
+};
+use tokio::{fs::File, io::AsyncReadExt, time};
+
+use crate::{files, wire};
+
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Application error codes carried in `RESET_STREAM` and `STOP_SENDING` frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppError {
+  /// the requester lost interest in the response
+  Cancelled,
+  /// the request did not arrive before the server's deadline
+  Timeout,
+  /// the server could not produce the response
+  ServerError,
+  /// the server refused the request, e.g. an upload over quota
+  Rejected,
+  /// the request arrived as 0-RTT data but is not safe to replay, resend it
+  /// once the handshake is done
+  TooEarly,
+  /// the server is shutting down, sent when it closes the connections left
+  /// at the end of its drain
+  ShuttingDown,
+}
+
+impl AppError {
+  pub const fn code(self) -> VarInt {
+    match self {
+      AppError::Cancelled => VarInt::from_u32(0x10),
+      AppError::Timeout => VarInt::from_u32(0x11),
+      AppError::ServerError => VarInt::from_u32(0x12),
+      AppError::Rejected => VarInt::from_u32(0x13),
+      AppError::TooEarly => VarInt::from_u32(0x14),
+      AppError::ShuttingDown => VarInt::from_u32(0x15),
+    }
+  }
+
+  pub fn from_code(code: VarInt) -> Option<Self> {
+    [
+      AppError::Cancelled,
+      AppError::Timeout,
+      AppError::ServerError,
+      AppError::Rejected,
+      AppError::TooEarly,
+      AppError::ShuttingDown,
+    ]
+      .into_iter()
+      .find(|e| e.code() == code)
+  }
+}
+
+impl fmt::Display for AppError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      AppError::Cancelled => write!(f, "request cancelled"),
+      AppError::Timeout => write!(f, "request timed out"),
+      AppError::ServerError => write!(f, "server error"),
+      AppError::Rejected => write!(f, "request rejected"),
+      AppError::TooEarly => write!(f, "request sent too early"),
+      AppError::ShuttingDown => write!(f, "server shutting down"),
+    }
+  }
+}
+
+impl std::error::Error for AppError {}
+
+/// How the server finished with a request stream. Requests that could not be
+/// served are reported as errors after resetting the stream with
+/// [`AppError::ServerError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+  /// the whole file was sent, carrying the number of bytes
+  Completed(u64),
+  /// the client stopped or reset the stream
+  Cancelled,
+  /// the request did not arrive in time
+  TimedOut,
+}
+
+/// Serves `GET <filename>\r\n` requests from a directory, streaming the file
+/// in chunks so that a cancelled request stops touching the file right away.
+#[derive(Debug, Clone)]
+pub struct FileServer {
+  root: PathBuf,
+  request_timeout: Duration,
+  chunk_size: usize,
+}
+
+impl FileServer {
+  pub fn new(root: impl Into<PathBuf>) -> Self {
+    Self {
+      root: root.into(),
+      request_timeout: DEFAULT_REQUEST_TIMEOUT,
+      chunk_size: DEFAULT_CHUNK_SIZE,
+    }
+  }
+
+  pub fn request_timeout(mut self, timeout: Duration) -> Self {
+    self.request_timeout = timeout;
+    self
+  }
+
+  pub fn chunk_size(mut self, size: usize) -> Self {
+    self.chunk_size = size;
+    self
+  }
+
+  pub async fn handle_stream(&self, mut send: SendStream, mut recv: RecvStream) -> Result<Outcome> {
+    let req = match time::timeout(self.request_timeout, recv.read_to_end(MAX_REQUEST_SIZE)).await {
+      Err(_) => {
+        let _ = recv.stop(AppError::Timeout.code());
+        let _ = send.reset(AppError::Timeout.code());
+        return Ok(Outcome::TimedOut);
+      }
+      Ok(Err(ReadToEndError::Read(ReadError::Reset(_)))) => {
+        let _ = send.reset(AppError::Cancelled.code());
+        return Ok(Outcome::Cancelled);
+      }
+      Ok(result) => result.context("failed reading request")?,
+    };
+
+    let mut file = match self.open(&req).await {
+      Ok(file) => file,
+      Err(e) => {
+        let _ = send.reset(AppError::ServerError.code());
+        return Err(e);
+      }
+    };
+
+    let mut buf = vec![0u8; self.chunk_size];
+    let mut sent = 0u64;
+    loop {
+      let n = match file.read(&mut buf).await {
+        Ok(0) => break,
+        Ok(n) => n,
+        Err(e) => {
+          let _ = send.reset(AppError::ServerError.code());
+          return Err(e).context("failed reading file");
+        }
+      };
+      match send.write_all(&buf[..n]).await {
+        Ok(()) => sent += n as u64,
+        // dropping the file here is what releases it as soon as the client gives up
+        Err(WriteError::Stopped(_)) => return Ok(Outcome::Cancelled),
+        Err(e) => return Err(e).context("failed to send response"),
+      }
+    }
+    wire::finish(&mut send).await.context("failed to finish stream")?;
+    Ok(Outcome::Completed(sent))
+  }
+
+  async fn open(&self, req: &[u8]) -> Result<File> {
+    File::open(files::resolve(&self.root, req)?).await.context("failed opening file")
+  }
+}
+
+/// Requests `filename` and reads the response until it ends or `cancel` resolves.
+///
+/// Cancelling stops the response stream with [`AppError::Cancelled`]. A stream
+/// reset by the server surfaces as the matching [`AppError`], which callers can
+/// recover with `downcast_ref`.
+pub async fn fetch(conn: &Connection, filename: &str, cancel: impl Future<Output = ()>) -> Result<Vec<u8>> {
+  let (mut send, mut recv) = conn
+    .open_bi()
+    .await
+    .context("failed to open stream")?;
+
+  let req = format!("GET {}\r\n", filename);
+  send.write_all(req.as_bytes())
+    .await
+    .context("failed to send request")?;
+  send.finish().context("failed to finish request")?;
+
+  let resp = tokio::select! {
+    resp = recv.read_to_end(usize::MAX) => Some(resp),
+    _ = cancel => None,
+  };
+  match resp {
+    Some(Ok(resp)) => Ok(resp),
+    Some(Err(ReadToEndError::Read(ReadError::Reset(code)))) => match AppError::from_code(code) {
+      Some(e) => Err(e).context("server reset the stream"),
+      None => bail!("server reset the stream with unknown code {}", code),
+    },
+    Some(Err(e)) => Err(e).context("failed to read response"),
+    None => {
+      let _ = recv.stop(AppError::Cancelled.code());
+      let _ = send.reset(AppError::Cancelled.code());
+      Err(AppError::Cancelled.into())
+    }
+  }
+}
@@ -0,0 +1,155 @@
+use std::{
+  fs, path::{Path, PathBuf}, sync::{Arc, Mutex, RwLock}, time::{Duration, SystemTime},
+};
+use anyhow::{Context, Result, anyhow, bail};
+use log::{info, warn};
+use rustls::{
+  crypto::CryptoProvider,
+  pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+  server::{ClientHello, ResolvesServerCert},
+  sign::CertifiedKey,
+  RootCertStore,
+};
+use tokio::task::JoinHandle;
+
+use crate::pinning::pin_for_cert;
+
+/// Writes a new self-signed certificate for `localhost` and its key to
+/// `cert.der` and `key.der` in `dir`, creating the directory if needed.
+pub fn generate_self_signed(dir: &Path) -> Result<CertificateDer<'static>> {
+  fs::create_dir_all(dir).context("failed to create cert_dir")?;
+  let cert_key = rcgen::generate_simple_self_signed(vec!["localhost".into()])?;
+  let key = PrivatePkcs8KeyDer::from(cert_key.key_pair.serialize_der());
+  let cert: CertificateDer<'static> = cert_key.cert.into();
+  fs::write(dir.join("cert.der"), &cert).context("failed to write certificate")?;
+  fs::write(dir.join("key.der"), key.secret_pkcs8_der()).context("failed to write private key")?;
+  Ok(cert)
+}
+
+/// Loads a certificate chain, DER or PEM.
+pub fn load_chain(cert_path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+  let bytes = fs::read(cert_path).context("failed to read certificate")?;
+  let chain = if bytes.starts_with(b"-----BEGIN") {
+    CertificateDer::pem_slice_iter(&bytes)
+      .collect::<Result<Vec<_>, _>>()
+      .context("malformed certificate PEM")?
+  } else {
+    vec![CertificateDer::from(bytes)]
+  };
+  if chain.is_empty() {
+    bail!("no certificate in {}", cert_path.display());
+  }
+  Ok(chain)
+}
+
+/// Loads a private key, DER or PEM.
+pub fn load_key(key_path: &Path) -> Result<PrivateKeyDer<'static>> {
+  let bytes = fs::read(key_path).context("failed to read private key")?;
+  if bytes.starts_with(b"-----BEGIN") {
+    PrivateKeyDer::from_pem_slice(&bytes).context("malformed private key PEM")
+  } else {
+    PrivateKeyDer::try_from(bytes).map_err(anyhow::Error::msg)
+  }
+}
+
+/// Trusts the certificates in `cert_path`, for clients of a self-signed server.
+pub fn load_roots(cert_path: &Path) -> Result<RootCertStore> {
+  let mut roots = RootCertStore::empty();
+  for cert in load_chain(cert_path)? {
+    roots.add(cert).context("invalid root certificate")?;
+  }
+  Ok(roots)
+}
+
+/// Loads a certificate chain and its private key, each either DER or PEM,
+/// and checks that the key belongs to the end-entity certificate.
+pub fn load_certified_key(cert_path: &Path, key_path: &Path, provider: &CryptoProvider) -> Result<CertifiedKey> {
+  let chain = load_chain(cert_path)?;
+  let key = load_key(key_path)?;
+  let key = provider.key_provider.load_private_key(key).context("unsupported private key")?;
+
+  let certified = CertifiedKey::new(chain, key);
+  certified.keys_match().context("private key does not match the certificate")?;
+  Ok(certified)
+}
+
+/// Serves the certificate and key found at two paths and swaps in new ones
+/// when the files change.
+///
+/// Only handshakes that start after a swap see the new certificate,
+/// established connections are not affected. A pair that fails to load, for
+/// instance because only one of the two files has been replaced so far, is
+/// reported and the previous certificate stays in use.
+#[derive(Debug)]
+pub struct ReloadingCertResolver {
+  cert_path: PathBuf,
+  key_path: PathBuf,
+  provider: Arc<CryptoProvider>,
+  current: RwLock<Arc<CertifiedKey>>,
+  seen: Mutex<[Option<SystemTime>; 2]>,
+}
+
+impl ReloadingCertResolver {
+  pub fn new(cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Result<Self> {
+    let cert_path = cert_path.into();
+    let key_path = key_path.into();
+    let provider = CryptoProvider::get_default()
+      .cloned()
+      .ok_or_else(|| anyhow!("no default crypto provider installed"))?;
+    let seen = modified(&cert_path, &key_path);
+    let current = load_certified_key(&cert_path, &key_path, &provider)?;
+    Ok(Self {
+      cert_path,
+      key_path,
+      provider,
+      current: RwLock::new(Arc::new(current)),
+      seen: Mutex::new(seen),
+    })
+  }
+
+  /// The certificate new handshakes are served.
+  pub fn current(&self) -> Arc<CertifiedKey> {
+    self.current.read().unwrap().clone()
+  }
+
+  /// Loads the files again and swaps them in if the key matches the certificate.
+  pub fn reload(&self) -> Result<Arc<CertifiedKey>> {
+    *self.seen.lock().unwrap() = modified(&self.cert_path, &self.key_path);
+    let certified = Arc::new(load_certified_key(&self.cert_path, &self.key_path, &self.provider)?);
+    *self.current.write().unwrap() = certified.clone();
+    Ok(certified)
+  }
+
+  /// Polls the files every `interval` and reloads them when either one
+  /// changes, until the returned task is aborted.
+  pub fn watch(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+    let resolver = self.clone();
+    tokio::spawn(async move {
+      let mut ticker = tokio::time::interval(interval);
+      loop {
+        ticker.tick().await;
+        if modified(&resolver.cert_path, &resolver.key_path) == *resolver.seen.lock().unwrap() {
+          continue;
+        }
+        match resolver.reload() {
+          Ok(certified) => info!(
+            cert:% = resolver.cert_path.display(),
+            pin = certified.end_entity_cert().ok().and_then(|cert| pin_for_cert(cert).ok()).unwrap_or_default();
+            "🔄 reloaded certificate",
+          ),
+          Err(e) => warn!("failed to reload certificate, keeping the previous one: {:#}", e),
+        }
+      }
+    })
+  }
+}
+
+impl ResolvesServerCert for ReloadingCertResolver {
+  fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+    Some(self.current())
+  }
+}
+
+fn modified(cert_path: &Path, key_path: &Path) -> [Option<SystemTime>; 2] {
+  [cert_path, key_path].map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+}
@@ -0,0 +1,14 @@
+use std::{env, str::FromStr};
+use anyhow::{Result, anyhow};
+
+/// Reads `name` from the environment, falling back to `default` when it is unset.
+pub fn env_or<T>(name: &str, default: T) -> Result<T>
+where
+  T: FromStr,
+  T::Err: std::fmt::Display,
+{
+  match env::var(name) {
+    Ok(value) => value.parse().map_err(|e| anyhow!("invalid {}={:?}: {}", name, value, e)),
+    Err(_) => Ok(default),
+  }
+}
@@ -0,0 +1,254 @@
+use std::{fmt, sync::atomic::{AtomicU64, Ordering}};
+use anyhow::{Context, Result, bail};
+use quinn::{
+  Connecting,
+  Connection,
+  ReadError,
+  ReadToEndError,
+  SendStream,
+  WriteError,
+  ZeroRttAccepted,
+};
+use tokio::sync::watch;
+use crate::cancellation::AppError;
+
+/// Whether a route may run on 0-RTT data, which an attacker can replay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EarlyData {
+  /// idempotent, running it twice does no harm
+  Safe,
+  /// has side effects, must wait for the handshake
+  Unsafe,
+}
+
+/// The 0-RTT policy of every route a server answers.
+///
+/// Routes are matched by the longest prefix of the request, e.g. `GET ` or
+/// `POST /something`. A request no route matches is [`EarlyData::Unsafe`].
+#[derive(Debug, Clone, Default)]
+pub struct Routes {
+  routes: Vec<(Vec<u8>, EarlyData)>,
+}
+
+impl Routes {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn route(mut self, prefix: &str, early_data: EarlyData) -> Self {
+    self.routes.push((prefix.as_bytes().to_vec(), early_data));
+    self
+  }
+
+  pub fn early_data(&self, req: &[u8]) -> EarlyData {
+    self.routes
+      .iter()
+      .filter(|(prefix, _)| req.starts_with(prefix))
+      .max_by_key(|(prefix, _)| prefix.len())
+      .map_or(EarlyData::Unsafe, |(_, early_data)| *early_data)
+  }
+
+  /// Returns whether `req` may be handled now. An unsafe request that arrived
+  /// as 0-RTT data is answered by resetting `send` with [`AppError::TooEarly`].
+  pub fn admit(&self, req: &[u8], is_0rtt: bool, send: &mut SendStream) -> bool {
+    if is_0rtt && self.early_data(req) == EarlyData::Unsafe {
+      let _ = send.reset(AppError::TooEarly.code());
+      return false;
+    }
+    true
+  }
+}
+
+/// What became of 0-RTT on a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZeroRtt {
+  /// no early data was sent, e.g. there was no session ticket to resume with
+  NotOffered,
+  /// the server processed the early data
+  Accepted,
+  /// the server dropped the early data, requests sent in it are lost
+  Rejected,
+}
+
+impl fmt::Display for ZeroRtt {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ZeroRtt::NotOffered => write!(f, "not offered"),
+      ZeroRtt::Accepted => write!(f, "accepted"),
+      ZeroRtt::Rejected => write!(f, "rejected"),
+    }
+  }
+}
+
+/// Counts connections by their [`ZeroRtt`] outcome.
+#[derive(Debug, Default)]
+pub struct ZeroRttCounters {
+  not_offered: AtomicU64,
+  accepted: AtomicU64,
+  rejected: AtomicU64,
+}
+
+impl ZeroRttCounters {
+  pub fn record(&self, outcome: ZeroRtt) {
+    let counter = match outcome {
+      ZeroRtt::NotOffered => &self.not_offered,
+      ZeroRtt::Accepted => &self.accepted,
+      ZeroRtt::Rejected => &self.rejected,
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub fn get(&self, outcome: ZeroRtt) -> u64 {
+    match outcome {
+      ZeroRtt::NotOffered => &self.not_offered,
+      ZeroRtt::Accepted => &self.accepted,
+      ZeroRtt::Rejected => &self.rejected,
+    }.load(Ordering::Relaxed)
+  }
+}
+
+impl fmt::Display for ZeroRttCounters {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} accepted, {} rejected, {} not offered",
+      self.get(ZeroRtt::Accepted),
+      self.get(ZeroRtt::Rejected),
+      self.get(ZeroRtt::NotOffered),
+    )
+  }
+}
+
+/// Tracks 0-RTT on the server side of a connection.
+///
+/// Quinn only learns whether early data was accepted on the client: on the
+/// server `ZeroRttAccepted` always resolves to true, whether the client sent
+/// early data or not. What the server can see is whether a stream arrived as
+/// 0-RTT data, so a connection counts as accepted once one has. A rejected
+/// flight never reaches the server's streams and reports as not offered, as
+/// does a client that resumed with early keys but had no request ready in time.
+#[derive(Debug, Default)]
+pub struct ServerZeroRtt {
+  early_streams: AtomicU64,
+}
+
+impl ServerZeroRtt {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Records a stream, pass `RecvStream::is_0rtt`.
+  pub fn stream(&self, is_0rtt: bool) {
+    if is_0rtt {
+      self.early_streams.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+
+  pub fn early_streams(&self) -> u64 {
+    self.early_streams.load(Ordering::Relaxed)
+  }
+
+  pub fn outcome(&self) -> ZeroRtt {
+    if self.early_streams() > 0 { ZeroRtt::Accepted } else { ZeroRtt::NotOffered }
+  }
+}
+
+/// A client connection that may still be in its 0-RTT phase.
+///
+/// Requests the server answers with [`AppError::TooEarly`], or that were lost
+/// because the server rejected 0-RTT altogether, are sent again once the
+/// handshake is done, so callers never check `is_0rtt` themselves.
+#[derive(Debug, Clone)]
+pub struct EarlyClient {
+  conn: Connection,
+  // `None` until the handshake is done
+  zero_rtt: watch::Receiver<Option<ZeroRtt>>,
+}
+
+impl EarlyClient {
+  /// Sends early data if there is a session ticket to resume with, otherwise
+  /// waits for the full handshake.
+  pub async fn connect(connecting: Connecting) -> Result<Self> {
+    match connecting.into_0rtt() {
+      Ok((conn, accepted)) => Ok(Self::new(conn, accepted)),
+      Err(connecting) => Ok(Self::established(connecting.await.context("failed to connect")?)),
+    }
+  }
+
+  /// Wraps a connection returned by `Connecting::into_0rtt`.
+  pub fn new(conn: Connection, accepted: ZeroRttAccepted) -> Self {
+    let (tx, zero_rtt) = watch::channel(None);
+    tokio::spawn(async move {
+      // resolves once the handshake is done
+      let outcome = if accepted.await { ZeroRtt::Accepted } else { ZeroRtt::Rejected };
+      let _ = tx.send(Some(outcome));
+    });
+    Self { conn, zero_rtt }
+  }
+
+  /// Wraps a connection whose handshake is already done without early data.
+  pub fn established(conn: Connection) -> Self {
+    let (_, zero_rtt) = watch::channel(Some(ZeroRtt::NotOffered));
+    Self { conn, zero_rtt }
+  }
+
+  pub fn connection(&self) -> &Connection {
+    &self.conn
+  }
+
+  pub fn is_handshake_done(&self) -> bool {
+    self.zero_rtt.borrow().is_some()
+  }
+
+  /// Waits until requests are no longer sent as 0-RTT data.
+  pub async fn handshake_done(&self) {
+    self.zero_rtt().await;
+  }
+
+  /// Waits for the handshake and tells what became of the early data. A
+  /// connection that failed during the handshake reports it as rejected.
+  pub async fn zero_rtt(&self) -> ZeroRtt {
+    let mut zero_rtt = self.zero_rtt.clone();
+    let outcome = zero_rtt.wait_for(Option::is_some).await.map(|outcome| *outcome);
+    outcome.ok().flatten().unwrap_or(ZeroRtt::Rejected)
+  }
+
+  /// Sends `req` on a new stream and reads the whole response.
+  pub async fn request(&self, req: &[u8]) -> Result<Vec<u8>> {
+    let early = !self.is_handshake_done();
+    if let Some(resp) = self.attempt(req).await? {
+      return Ok(resp);
+    }
+    if !early {
+      bail!("server refused the request as too early after the handshake");
+    }
+    self.handshake_done().await;
+    self.attempt(req)
+      .await?
+      .context("server refused the request as too early after the handshake")
+  }
+
+  // `None` means the request has to wait for the handshake
+  async fn attempt(&self, req: &[u8]) -> Result<Option<Vec<u8>>> {
+    let (mut send, mut recv) = self.conn
+      .open_bi()
+      .await
+      .context("failed to open stream")?;
+
+    match send.write_all(req).await {
+      Ok(()) => {}
+      Err(WriteError::ZeroRttRejected) => return Ok(None),
+      Err(e) => return Err(e).context("failed to send request"),
+    }
+    send.finish().context("failed to finish request")?;
+
+    match recv.read_to_end(usize::MAX).await {
+      Ok(resp) => Ok(Some(resp)),
+      Err(ReadToEndError::Read(ReadError::ZeroRttRejected)) => Ok(None),
+      Err(ReadToEndError::Read(ReadError::Reset(code))) => match AppError::from_code(code) {
+        Some(AppError::TooEarly) => Ok(None),
+        Some(e) => Err(e).context("server reset the stream"),
+        None => bail!("server reset the stream with unknown code {}", code),
+      },
+      Err(e) => Err(e).context("failed to read response"),
+    }
+  }
+}
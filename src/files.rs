@@ -0,0 +1,84 @@
+use std::{fs, path::{Path, PathBuf}, str};
+use anyhow::{Context, Result, bail};
+use log::{debug, info, warn};
+use quinn::{Connection, RecvStream, SendStream};
+
+use crate::wire::{self, escape};
+
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+/// The filename of a `GET <filename>\r\n` request.
+///
+/// Filenames stay inside the served directory: path separators and a
+/// leading dot are refused.
+pub fn parse_get(req: &[u8]) -> Result<&str> {
+  let Some(filename) = req.strip_prefix(b"GET ") else {
+    bail!("missing GET");
+  };
+  let Some(filename) = filename.strip_suffix(b"\r\n") else {
+    bail!("missing \\r\\n");
+  };
+  let filename = str::from_utf8(filename).context("filename is malformed UTF-8")?;
+  if !valid_filename(filename) {
+    bail!("invalid filename {:?}", filename);
+  }
+  Ok(filename)
+}
+
+/// Whether `filename` names a file directly inside the served directory:
+/// not empty, without path separators and not starting with a dot.
+pub fn valid_filename(filename: &str) -> bool {
+  !filename.is_empty() && !filename.contains(['/', '\\']) && !filename.starts_with('.')
+}
+
+/// Where the file a `GET` request names lives under `root`.
+pub fn resolve(root: &Path, req: &[u8]) -> Result<PathBuf> {
+  Ok(root.join(parse_get(req)?))
+}
+
+/// Reads the file a `GET` request names from `root`.
+pub fn read(root: &Path, req: &[u8]) -> Result<Vec<u8>> {
+  fs::read(resolve(root, req)?).context("failed reading file")
+}
+
+/// Reads the request on a bidirectional stream and answers it with what
+/// `handle` returns. A request `handle` fails on is logged and answered with
+/// an error message.
+pub async fn respond<F>(conn_id: usize, (mut send, mut recv): (SendStream, RecvStream), handle: F) -> Result<()>
+where
+  F: FnOnce(&[u8]) -> Result<Vec<u8>>,
+{
+  let stream = send.id();
+  let req = recv
+    .read_to_end(MAX_REQUEST_SIZE)
+    .await
+    .context("failed reading request")?;
+  info!(conn_id, stream:%; "req {}", escape(&req));
+
+  let resp = handle(&req).unwrap_or_else(|e| {
+    warn!(conn_id, stream:%; "handle request failed: {}", e);
+    String::from("failed to handle request").into_bytes()
+  });
+  send.write_all(&resp).await.context("failed to send response")?;
+  wire::finish(&mut send).await.context("failed to finish stream")?;
+  debug!(conn_id, stream:%; "complete stream handling!");
+  Ok(())
+}
+
+/// Requests `filename` on a new stream and reads the whole response.
+pub async fn get(conn: &Connection, filename: &str) -> Result<Vec<u8>> {
+  let (mut send, mut recv) = conn
+    .open_bi()
+    .await
+    .context("failed to open stream")?;
+
+  let req = format!("GET {}\r\n", filename);
+  send.write_all(req.as_bytes())
+    .await
+    .context("failed to send request")?;
+  send.finish().context("failed to finish request")?;
+
+  recv.read_to_end(usize::MAX)
+    .await
+    .context("failed to read response")
+}
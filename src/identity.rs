@@ -0,0 +1,80 @@
+use std::{fmt, net::IpAddr};
+use anyhow::{Context, Result, anyhow};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use quinn::Connection;
+use rustls::pki_types::CertificateDer;
+use sha2::{Digest, Sha256};
+use x509_parser::{extensions::GeneralName, prelude::*};
+
+/// Who is on the other end of a connection, as told by their certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIdentity {
+  /// the full subject distinguished name, e.g. `CN=alice, O=Quinn Demo`
+  pub subject: String,
+  pub common_name: Option<String>,
+  /// DNS names, e-mail addresses, URIs and IP addresses from the SAN extension
+  pub sans: Vec<String>,
+  /// base64 SHA-256 of the DER SubjectPublicKeyInfo
+  pub spki_sha256: String,
+}
+
+impl ClientIdentity {
+  pub fn from_der(der: &[u8]) -> Result<Self> {
+    let (_, cert) = X509Certificate::from_der(der).map_err(|e| anyhow!("invalid certificate: {}", e))?;
+    let common_name = cert
+      .subject()
+      .iter_common_name()
+      .next()
+      .and_then(|cn| cn.as_str().ok())
+      .map(str::to_string);
+
+    let mut sans = Vec::new();
+    if let Some(san) = cert.subject_alternative_name().map_err(|e| anyhow!("invalid SAN extension: {}", e))? {
+      for name in &san.value.general_names {
+        match name {
+          GeneralName::DNSName(s) | GeneralName::RFC822Name(s) | GeneralName::URI(s) => sans.push(s.to_string()),
+          GeneralName::IPAddress(bytes) => {
+            if let Ok(ip) = <[u8; 4]>::try_from(*bytes).map(IpAddr::from)
+              .or_else(|_| <[u8; 16]>::try_from(*bytes).map(IpAddr::from)) {
+              sans.push(ip.to_string());
+            }
+          }
+          _ => {}
+        }
+      }
+    }
+
+    Ok(Self {
+      subject: cert.subject().to_string(),
+      common_name,
+      sans,
+      spki_sha256: spki_sha256(cert.public_key().raw),
+    })
+  }
+
+  /// Reads the identity from the end-entity certificate the peer presented,
+  /// `None` when it presented none.
+  pub fn from_connection(conn: &Connection) -> Result<Option<Self>> {
+    let Some(identity) = conn.peer_identity() else {
+      return Ok(None);
+    };
+    let certs = identity
+      .downcast::<Vec<CertificateDer<'static>>>()
+      .map_err(|_| anyhow!("peer identity is not a certificate chain"))?;
+    match certs.first() {
+      Some(cert) => Ok(Some(Self::from_der(cert).context("failed to parse peer certificate")?)),
+      None => Ok(None),
+    }
+  }
+}
+
+impl fmt::Display for ClientIdentity {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} (SANs [{}], SPKI sha256 {})", self.subject, self.sans.join(", "), self.spki_sha256)
+  }
+}
+
+/// Base64 SHA-256 of a DER SubjectPublicKeyInfo, the same form used for key pins.
+pub fn spki_sha256(spki_der: &[u8]) -> String {
+  BASE64_STANDARD.encode(Sha256::digest(spki_der))
+}
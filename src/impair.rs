@@ -0,0 +1,344 @@
+//! A UDP socket for Quinn that makes the network worse on purpose: it loses,
+//! delays, reorders, duplicates and throttles what it sends, and drops
+//! datagrams over its MTU. Every decision comes from a seeded RNG, so the
+//! same seed and the same datagrams give the same fates.
+//!
+//! Only outgoing datagrams are impaired. Give both endpoints an
+//! [`ImpairedSocket`] to impair both directions.
+
+use std::{
+  cmp::{Ordering, Reverse},
+  collections::BinaryHeap,
+  fmt,
+  io::{self, IoSliceMut},
+  net::{IpAddr, SocketAddr},
+  pin::Pin,
+  sync::{Arc, Mutex},
+  task::{Context, Poll},
+  time::{Duration, Instant},
+};
+use anyhow::{Result, bail};
+use log::debug;
+use quinn::{
+  udp::{EcnCodepoint, RecvMeta, Transmit},
+  AsyncUdpSocket,
+  Endpoint,
+  EndpointConfig,
+  Runtime,
+  ServerConfig,
+  TokioRuntime,
+  UdpPoller,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::sync::mpsc;
+
+use crate::config::env_or;
+
+/// How long a reordered datagram is held back, letting the ones sent after it overtake it.
+const REORDER_DELAY: Duration = Duration::from_millis(5);
+/// Bytes waiting for a bandwidth limited link before it drops what is sent, like a router's buffer.
+pub const DEFAULT_QUEUE: usize = 64 * 1024;
+
+/// What happens to each datagram on the way out, a perfect network by default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Impairment {
+  /// chance a datagram is lost, from 0 to 1
+  pub loss: f64,
+  pub latency: Duration,
+  /// up to this much extra delay, picked uniformly for each datagram
+  pub jitter: Duration,
+  /// chance a datagram is held back so later ones arrive first
+  pub reorder: f64,
+  /// chance a datagram arrives twice
+  pub duplicate: f64,
+  /// bytes per second the link carries, datagrams queue up behind each other
+  pub bandwidth: Option<u64>,
+  /// bytes that can queue up behind `bandwidth`, datagrams that do not fit are dropped
+  pub queue: usize,
+  /// largest UDP payload that gets through, bigger datagrams are dropped
+  pub mtu: Option<usize>,
+}
+
+impl Default for Impairment {
+  fn default() -> Self {
+    Self {
+      loss: 0.0,
+      latency: Duration::ZERO,
+      jitter: Duration::ZERO,
+      reorder: 0.0,
+      duplicate: 0.0,
+      bandwidth: None,
+      queue: DEFAULT_QUEUE,
+      mtu: None,
+    }
+  }
+}
+
+impl Impairment {
+  /// Reads `NET_LOSS`, `NET_LATENCY_MS`, `NET_JITTER_MS`, `NET_REORDER`,
+  /// `NET_DUPLICATE`, `NET_BANDWIDTH` in bytes per second, `NET_QUEUE` in
+  /// bytes and `NET_MTU`, where 0 means no limit for bandwidth and MTU.
+  pub fn from_env() -> Result<Self> {
+    let impairment = Self {
+      loss: env_or("NET_LOSS", 0.0)?,
+      latency: Duration::from_millis(env_or("NET_LATENCY_MS", 0)?),
+      jitter: Duration::from_millis(env_or("NET_JITTER_MS", 0)?),
+      reorder: env_or("NET_REORDER", 0.0)?,
+      duplicate: env_or("NET_DUPLICATE", 0.0)?,
+      bandwidth: Some(env_or("NET_BANDWIDTH", 0)?).filter(|&b| b > 0),
+      queue: env_or("NET_QUEUE", DEFAULT_QUEUE)?,
+      mtu: Some(env_or("NET_MTU", 0)?).filter(|&m| m > 0),
+    };
+    for (name, chance) in [("NET_LOSS", impairment.loss), ("NET_REORDER", impairment.reorder), ("NET_DUPLICATE", impairment.duplicate)] {
+      if !(0.0..=1.0).contains(&chance) {
+        bail!("invalid {}={}: not between 0 and 1", name, chance);
+      }
+    }
+    Ok(impairment)
+  }
+
+  pub fn is_perfect(&self) -> bool {
+    *self == Self::default()
+  }
+}
+
+impl fmt::Display for Impairment {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "loss {}%, latency {:?} + jitter {:?}, reorder {}%, duplicate {}%",
+      self.loss * 100.0,
+      self.latency,
+      self.jitter,
+      self.reorder * 100.0,
+      self.duplicate * 100.0,
+    )?;
+    if let Some(bandwidth) = self.bandwidth {
+      write!(f, ", {}B/s with a {}B queue", bandwidth, self.queue)?;
+    }
+    if let Some(mtu) = self.mtu {
+      write!(f, ", mtu {}B", mtu)?;
+    }
+    Ok(())
+  }
+}
+
+/// What became of the datagrams sent so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImpairStats {
+  pub datagrams: u64,
+  pub lost: u64,
+  /// dropped for being over the MTU
+  pub too_big: u64,
+  /// dropped because the queue behind the bandwidth limit was full
+  pub overflowed: u64,
+  pub reordered: u64,
+  pub duplicated: u64,
+}
+
+/// A UDP socket impairing what it sends, see [`Impairment`].
+#[derive(Debug)]
+pub struct ImpairedSocket {
+  inner: Arc<dyn AsyncUdpSocket>,
+  link: Mutex<Link>,
+  delayed: mpsc::UnboundedSender<Delayed>,
+}
+
+#[derive(Debug)]
+struct Link {
+  impairment: Impairment,
+  rng: StdRng,
+  // when the link is done with the datagrams queued on it
+  busy_until: Instant,
+  sent: u64,
+  stats: ImpairStats,
+}
+
+impl ImpairedSocket {
+  /// Binds `addr`, impairing with the RNG seeded by `seed`. Must be called
+  /// inside a Tokio runtime, which delivers the delayed datagrams.
+  pub fn bind(addr: SocketAddr, impairment: Impairment, seed: u64) -> io::Result<Arc<Self>> {
+    let inner = TokioRuntime.wrap_udp_socket(std::net::UdpSocket::bind(addr)?)?;
+    Ok(Arc::new(Self::new(inner, impairment, seed)))
+  }
+
+  pub fn new(inner: Arc<dyn AsyncUdpSocket>, impairment: Impairment, seed: u64) -> Self {
+    let (delayed, queued) = mpsc::unbounded_channel();
+    tokio::spawn(deliver(inner.clone(), queued));
+    let link = Link {
+      impairment,
+      rng: StdRng::seed_from_u64(seed),
+      busy_until: Instant::now(),
+      sent: 0,
+      stats: ImpairStats::default(),
+    };
+    Self { inner, link: Mutex::new(link), delayed }
+  }
+
+  /// Changes the impairment from the next datagram on, e.g. to drop the MTU
+  /// mid-connection. The RNG carries on where it was.
+  pub fn set(&self, impairment: Impairment) {
+    self.link.lock().unwrap().impairment = impairment;
+  }
+
+  pub fn stats(&self) -> ImpairStats {
+    self.link.lock().unwrap().stats
+  }
+}
+
+impl Link {
+  // when each copy of a `len` byte datagram sent `now` arrives, none when it is dropped
+  fn arrivals(&mut self, len: usize, now: Instant) -> Vec<Instant> {
+    let impairment = &self.impairment;
+    self.stats.datagrams += 1;
+    if impairment.mtu.is_some_and(|mtu| len > mtu) {
+      self.stats.too_big += 1;
+      return Vec::new();
+    }
+    if self.rng.random::<f64>() < impairment.loss {
+      self.stats.lost += 1;
+      return Vec::new();
+    }
+    let mut departure = now;
+    if let Some(bandwidth) = impairment.bandwidth {
+      // tail drop: the bytes still waiting to go out and this datagram must fit
+      let queued = self.busy_until.saturating_duration_since(now).as_secs_f64() * bandwidth as f64;
+      if queued + len as f64 > impairment.queue as f64 {
+        self.stats.overflowed += 1;
+        return Vec::new();
+      }
+      let transmission = Duration::from_secs_f64(len as f64 / bandwidth as f64);
+      self.busy_until = self.busy_until.max(now) + transmission;
+      departure = self.busy_until;
+    }
+    let copies = if self.rng.random::<f64>() < impairment.duplicate {
+      self.stats.duplicated += 1;
+      2
+    } else {
+      1
+    };
+    (0..copies).map(|_| {
+      let mut arrival = departure + impairment.latency + impairment.jitter.mul_f64(self.rng.random());
+      if self.rng.random::<f64>() < impairment.reorder {
+        self.stats.reordered += 1;
+        arrival += REORDER_DELAY;
+      }
+      arrival
+    }).collect()
+  }
+}
+
+impl AsyncUdpSocket for ImpairedSocket {
+  fn create_io_poller(self: Arc<Self>) -> Pin<Box<dyn UdpPoller>> {
+    self.inner.clone().create_io_poller()
+  }
+
+  fn try_send(&self, transmit: &Transmit) -> io::Result<()> {
+    let now = Instant::now();
+    let segment_size = transmit.segment_size.unwrap_or(transmit.contents.len()).max(1);
+    let mut link = self.link.lock().unwrap();
+    for contents in transmit.contents.chunks(segment_size) {
+      for at in link.arrivals(contents.len(), now) {
+        link.sent += 1;
+        let datagram = Delayed {
+          at,
+          seq: link.sent,
+          destination: transmit.destination,
+          ecn: transmit.ecn,
+          src_ip: transmit.src_ip,
+          contents: contents.to_vec(),
+        };
+        if self.delayed.send(datagram).is_err() {
+          return Err(io::Error::other("impaired socket stopped delivering"));
+        }
+      }
+    }
+    Ok(())
+  }
+
+  fn poll_recv(&self, cx: &mut Context, bufs: &mut [IoSliceMut<'_>], meta: &mut [RecvMeta]) -> Poll<io::Result<usize>> {
+    self.inner.poll_recv(cx, bufs, meta)
+  }
+
+  fn local_addr(&self) -> io::Result<SocketAddr> {
+    self.inner.local_addr()
+  }
+
+  // one datagram per transmit, so each gets its own fate
+  fn max_transmit_segments(&self) -> usize {
+    1
+  }
+
+  fn max_receive_segments(&self) -> usize {
+    self.inner.max_receive_segments()
+  }
+
+  fn may_fragment(&self) -> bool {
+    self.inner.may_fragment()
+  }
+}
+
+/// An endpoint sending through `socket`, accepting connections when
+/// `server_config` is given.
+pub fn endpoint(socket: Arc<ImpairedSocket>, server_config: Option<ServerConfig>) -> io::Result<Endpoint> {
+  Endpoint::new_with_abstract_socket(EndpointConfig::default(), server_config, socket, Arc::new(TokioRuntime))
+}
+
+#[derive(Debug)]
+struct Delayed {
+  at: Instant,
+  // keeps datagrams due at the same time in the order they were sent
+  seq: u64,
+  destination: SocketAddr,
+  ecn: Option<EcnCodepoint>,
+  src_ip: Option<IpAddr>,
+  contents: Vec<u8>,
+}
+
+impl PartialEq for Delayed {
+  fn eq(&self, other: &Self) -> bool {
+    (self.at, self.seq) == (other.at, other.seq)
+  }
+}
+
+impl Eq for Delayed {}
+
+impl PartialOrd for Delayed {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Delayed {
+  fn cmp(&self, other: &Self) -> Ordering {
+    (self.at, self.seq).cmp(&(other.at, other.seq))
+  }
+}
+
+// sends each datagram when it is due, until the socket is dropped
+async fn deliver(socket: Arc<dyn AsyncUdpSocket>, mut queued: mpsc::UnboundedReceiver<Delayed>) {
+  let mut pending = BinaryHeap::new();
+  loop {
+    let due = pending.peek().map(|Reverse(datagram): &Reverse<Delayed>| datagram.at);
+    tokio::select! {
+      datagram = queued.recv() => match datagram {
+        Some(datagram) => pending.push(Reverse(datagram)),
+        None => return,
+      },
+      _ = tokio::time::sleep_until(due.unwrap_or_else(Instant::now).into()), if due.is_some() => {
+        let Reverse(datagram) = pending.pop().unwrap();
+        let transmit = Transmit {
+          destination: datagram.destination,
+          ecn: datagram.ecn,
+          contents: &datagram.contents,
+          segment_size: None,
+          src_ip: datagram.src_ip,
+        };
+        // a full socket buffer loses the datagram, like a full router queue would
+        if let Err(e) = socket.try_send(&transmit) {
+          debug!(destination:% = datagram.destination; "impaired datagram not sent: {}", e);
+        }
+      }
+    }
+  }
+}
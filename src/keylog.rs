@@ -0,0 +1,30 @@
+use std::{env, fs::OpenOptions, sync::{Arc, Once}};
+use log::warn;
+use rustls::{KeyLog, KeyLogFile, NoKeyLog};
+
+const KEYLOG_ENV: &str = "SSLKEYLOGFILE";
+
+static WARNING: Once = Once::new();
+
+/// The key log for a TLS config: TLS secrets are appended to the file named by
+/// `SSLKEYLOGFILE` in the NSS key log format, which Wireshark reads to decrypt
+/// QUIC captures. Without the variable nothing is logged.
+///
+/// Anyone holding the file can decrypt the traffic, so this warns once per process.
+pub fn from_env() -> Arc<dyn KeyLog> {
+  let Some(path) = env::var_os(KEYLOG_ENV) else {
+    return Arc::new(NoKeyLog);
+  };
+  WARNING.call_once(|| {
+    warn!(
+      path:% = path.to_string_lossy();
+      "🔓 {} is set, TLS secrets are written to it and anyone with the file can decrypt this traffic",
+      KEYLOG_ENV,
+    );
+    // rustls opens the file again and stays silent when it cannot
+    if let Err(e) = OpenOptions::new().append(true).create(true).open(&path) {
+      warn!(path:% = path.to_string_lossy(); "cannot open the key log, no secrets will be written: {}", e);
+    }
+  });
+  Arc::new(KeyLogFile::new())
+}
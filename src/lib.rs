@@ -0,0 +1,27 @@
+//! Building blocks shared by the examples and exercised by the integration tests.
+
+pub mod acl;
+pub mod admission;
+pub mod anti_replay;
+pub mod cancellation;
+pub mod certs;
+pub mod config;
+pub mod early_data;
+pub mod files;
+pub mod identity;
+pub mod impair;
+pub mod keylog;
+pub mod load;
+pub mod logging;
+pub mod metrics;
+pub mod pinning;
+pub mod push;
+pub mod qlog;
+pub mod revocation;
+pub mod scenarios;
+pub mod shutdown;
+pub mod sni;
+pub mod tickets;
+pub mod transport;
+pub mod upload;
+pub mod wire;
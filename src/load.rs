@@ -0,0 +1,269 @@
+//! A load generator for the file servers: `connections` connections with
+//! `streams` concurrent streams each, requesting files with `GET` until a
+//! request count or a duration is reached.
+//!
+//! Request `n` always asks for `files[n % files.len()]`, so a run with a
+//! request count asks for the same files whatever the timing.
+
+use std::{
+  collections::BTreeMap,
+  fmt,
+  net::SocketAddr,
+  sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex},
+  time::{Duration, Instant},
+};
+use anyhow::{Result, bail};
+use quinn::{Connection, Endpoint};
+use serde::Serialize;
+use tokio::task::JoinSet;
+
+use crate::{config::env_or, files};
+
+/// What a server answers a request it failed on with, see [`files::respond`].
+const SERVER_ERROR: &[u8] = b"failed to handle request";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+  Requests(u64),
+  Duration(Duration),
+}
+
+#[derive(Debug, Clone)]
+pub struct LoadConfig {
+  pub connections: usize,
+  /// concurrent streams on each connection
+  pub streams: usize,
+  pub files: Vec<String>,
+  pub limit: Limit,
+}
+
+impl LoadConfig {
+  /// Reads `LOAD_CONNECTIONS`, `LOAD_STREAMS`, `LOAD_FILES` as a comma
+  /// separated list, and `LOAD_DURATION_SECS` or else `LOAD_REQUESTS`.
+  pub fn from_env() -> Result<Self> {
+    let files: Vec<String> = env_or("LOAD_FILES", String::from("sample.json"))?
+      .split(',')
+      .map(|file| file.trim().to_string())
+      .filter(|file| !file.is_empty())
+      .collect();
+    let limit = match env_or("LOAD_DURATION_SECS", 0)? {
+      0 => Limit::Requests(env_or("LOAD_REQUESTS", 1000)?),
+      secs => Limit::Duration(Duration::from_secs(secs)),
+    };
+    let config = Self {
+      connections: env_or("LOAD_CONNECTIONS", 10)?,
+      streams: env_or("LOAD_STREAMS", 4)?,
+      files,
+      limit,
+    };
+    if config.connections == 0 || config.streams == 0 || config.files.is_empty() {
+      bail!("a load run needs at least one connection, one stream and one file");
+    }
+    Ok(config)
+  }
+}
+
+/// Latency percentiles, in milliseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Percentiles {
+  pub p50_ms: f64,
+  pub p90_ms: f64,
+  pub p99_ms: f64,
+  pub max_ms: f64,
+}
+
+impl Percentiles {
+  fn of(mut samples: Vec<Duration>) -> Self {
+    samples.sort();
+    let rank = |p: f64| {
+      // nearest rank
+      let index = ((p * samples.len() as f64).ceil() as usize).max(1) - 1;
+      samples.get(index).map_or(0.0, |d| d.as_secs_f64() * 1000.0)
+    };
+    Self { p50_ms: rank(0.5), p90_ms: rank(0.9), p99_ms: rank(0.99), max_ms: rank(1.0) }
+  }
+}
+
+impl fmt::Display for Percentiles {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "p50 {:.2}ms, p90 {:.2}ms, p99 {:.2}ms, max {:.2}ms", self.p50_ms, self.p90_ms, self.p99_ms, self.max_ms)
+  }
+}
+
+/// The outcome of a load run, printed as text or serialized as JSON.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadReport {
+  pub connections: usize,
+  pub streams: usize,
+  pub elapsed_secs: f64,
+  /// requests answered with the file
+  pub requests: u64,
+  pub failed: u64,
+  pub bytes: u64,
+  pub requests_per_sec: f64,
+  pub bytes_per_sec: f64,
+  pub latency: Percentiles,
+  pub handshake: Percentiles,
+  /// requests answered, per file
+  pub files: BTreeMap<String, u64>,
+  /// failed requests and handshakes, per error
+  pub errors: BTreeMap<String, u64>,
+}
+
+impl fmt::Display for LoadReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(
+      f,
+      "📊 {} requests in {:.2}s over {} connections x {} streams, {} failed",
+      self.requests, self.elapsed_secs, self.connections, self.streams, self.failed,
+    )?;
+    writeln!(f, "🚀 {:.1} req/s, {:.1} KiB/s", self.requests_per_sec, self.bytes_per_sec / 1024.0)?;
+    writeln!(f, "⏱️  request latency {}", self.latency)?;
+    writeln!(f, "🤝 handshake latency {}", self.handshake)?;
+    for (file, count) in &self.files {
+      writeln!(f, "📄 {}: {}", file, count)?;
+    }
+    for (error, count) in &self.errors {
+      writeln!(f, "❌ {}: {}", error, count)?;
+    }
+    Ok(())
+  }
+}
+
+#[derive(Debug, Default)]
+struct Samples {
+  latencies: Vec<Duration>,
+  handshakes: Vec<Duration>,
+  failed: u64,
+  bytes: u64,
+  files: BTreeMap<String, u64>,
+  errors: BTreeMap<String, u64>,
+}
+
+impl Samples {
+  fn error(&mut self, error: String) {
+    *self.errors.entry(error).or_default() += 1;
+  }
+}
+
+#[derive(Debug)]
+struct Run {
+  config: LoadConfig,
+  deadline: Option<Instant>,
+  // the number of the next request
+  next: AtomicU64,
+  samples: Mutex<Samples>,
+}
+
+impl Run {
+  // the file the next request asks for, none once the run is over
+  fn next_file(&self) -> Option<&str> {
+    if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+      return None;
+    }
+    let n = self.next.fetch_add(1, Ordering::Relaxed);
+    if let Limit::Requests(count) = self.config.limit {
+      if n >= count {
+        return None;
+      }
+    }
+    let files = &self.config.files;
+    Some(&files[(n % files.len() as u64) as usize])
+  }
+}
+
+/// Runs `config` against the server at `addr`, which has a certificate for
+/// `localhost`, connecting from `endpoint`.
+pub async fn run(endpoint: &Endpoint, addr: SocketAddr, config: &LoadConfig) -> Result<LoadReport> {
+  if config.files.is_empty() {
+    bail!("no files to request");
+  }
+  let started = Instant::now();
+  let deadline = match config.limit {
+    Limit::Duration(duration) => Some(started + duration),
+    Limit::Requests(_) => None,
+  };
+  let run = Arc::new(Run {
+    config: config.clone(),
+    deadline,
+    next: AtomicU64::new(0),
+    samples: Mutex::default(),
+  });
+
+  let mut connections = JoinSet::new();
+  for _ in 0..config.connections {
+    connections.spawn(connection(endpoint.clone(), addr, run.clone()));
+  }
+  while let Some(joined) = connections.join_next().await {
+    joined?;
+  }
+
+  let elapsed = started.elapsed().as_secs_f64();
+  let samples = std::mem::take(&mut *run.samples.lock().unwrap());
+  let requests = samples.latencies.len() as u64;
+  Ok(LoadReport {
+    connections: config.connections,
+    streams: config.streams,
+    elapsed_secs: elapsed,
+    requests,
+    failed: samples.failed,
+    bytes: samples.bytes,
+    requests_per_sec: requests as f64 / elapsed,
+    bytes_per_sec: samples.bytes as f64 / elapsed,
+    latency: Percentiles::of(samples.latencies),
+    handshake: Percentiles::of(samples.handshakes),
+    files: samples.files,
+    errors: samples.errors,
+  })
+}
+
+async fn connection(endpoint: Endpoint, addr: SocketAddr, run: Arc<Run>) {
+  let started = Instant::now();
+  let connected = match endpoint.connect(addr, "localhost") {
+    Ok(connecting) => connecting.await.map_err(|e| e.to_string()),
+    Err(e) => Err(e.to_string()),
+  };
+  let conn = match connected {
+    Ok(conn) => conn,
+    Err(e) => {
+      run.samples.lock().unwrap().error(format!("handshake failed: {}", e));
+      return;
+    }
+  };
+  run.samples.lock().unwrap().handshakes.push(started.elapsed());
+
+  let mut streams = JoinSet::new();
+  for _ in 0..run.config.streams {
+    streams.spawn(requests(conn.clone(), run.clone()));
+  }
+  while streams.join_next().await.is_some() {}
+  conn.close(0u32.into(), b"done");
+}
+
+// one request at a time on `conn`, until the run is over or the connection is lost
+async fn requests(conn: Connection, run: Arc<Run>) {
+  while let Some(file) = run.next_file() {
+    let started = Instant::now();
+    let resp = files::get(&conn, file).await;
+    let latency = started.elapsed();
+    let mut samples = run.samples.lock().unwrap();
+    match resp {
+      Ok(resp) if resp == SERVER_ERROR => {
+        samples.failed += 1;
+        samples.error(format!("server failed on {}", file));
+      }
+      Ok(resp) => {
+        samples.latencies.push(latency);
+        samples.bytes += resp.len() as u64;
+        *samples.files.entry(file.to_string()).or_default() += 1;
+      }
+      Err(e) => {
+        samples.failed += 1;
+        samples.error(format!("{:#}", e));
+        if conn.close_reason().is_some() {
+          return;
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,58 @@
+use std::{env, fmt, future::Future, io::Write};
+use anyhow::Result;
+use log::kv::{Key, Value, VisitSource};
+use serde_json::{Map, Number, Value as Json};
+use tokio::task::JoinHandle;
+
+/// Installs the logger every server and client uses.
+///
+/// `RUST_LOG` picks levels per target as usual and defaults to `info`.
+/// `LOG_FORMAT=json` writes one JSON object per line, with the key-value
+/// fields of the record next to its level, target and message.
+pub fn init() {
+  let mut builder = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+  if env::var("LOG_FORMAT").is_ok_and(|format| format == "json") {
+    builder.format(|buf, record| {
+      let mut line = Map::new();
+      line.insert("ts".into(), buf.timestamp_millis().to_string().into());
+      line.insert("level".into(), record.level().as_str().into());
+      line.insert("target".into(), record.target().into());
+      line.insert("msg".into(), record.args().to_string().into());
+      let _ = record.key_values().visit(&mut Fields(&mut line));
+      writeln!(buf, "{}", Json::Object(line))
+    });
+  }
+  builder.init();
+}
+
+struct Fields<'a>(&'a mut Map<String, Json>);
+
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+  fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+    let value = if let Some(n) = value.to_u64() {
+      Json::Number(n.into())
+    } else if let Some(n) = value.to_i64() {
+      Json::Number(n.into())
+    } else if let Some(n) = value.to_f64().and_then(Number::from_f64) {
+      Json::Number(n)
+    } else if let Some(b) = value.to_bool() {
+      Json::Bool(b)
+    } else {
+      Json::String(value.to_string())
+    };
+    self.0.insert(key.to_string(), value);
+    Ok(())
+  }
+}
+
+/// Spawns `task` and logs its error instead of dropping it with the handle.
+pub fn spawn_logged<F>(name: impl fmt::Display + Send + 'static, task: F) -> JoinHandle<()>
+where
+  F: Future<Output = Result<()>> + Send + 'static,
+{
+  tokio::spawn(async move {
+    if let Err(e) = task.await {
+      log::warn!(task:% = name; "task failed: {:#}", e);
+    }
+  })
+}
@@ -0,0 +1,337 @@
+use std::{
+  collections::HashMap, fmt::Write, future::Future, net::SocketAddr,
+  sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc, Mutex},
+  time::Duration,
+};
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use quinn::{Connection, ConnectionError};
+use tokio::{
+  io::{AsyncReadExt, AsyncWriteExt},
+  net::{TcpListener, TcpStream},
+  task::JoinHandle,
+};
+
+use crate::{admission::Decision, config::env_or};
+
+const SECONDS_BUCKETS: &[f64] = &[0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+const CWND_BUCKETS: &[f64] = &[12_000.0, 24_000.0, 48_000.0, 96_000.0, 192_000.0, 384_000.0, 768_000.0, 1_536_000.0];
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+// a scraper that has not sent its request by then is dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// after failing to accept, e.g. out of file descriptors, which retrying at once would not fix
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+// TLS alerts a server sends when it does not accept the client's certificate:
+// bad, unsupported, revoked, expired or unknown certificate, unknown CA,
+// access denied and certificate required
+const AUTH_ALERTS: &[u8] = &[42, 43, 44, 45, 46, 48, 49, 116];
+
+#[derive(Debug)]
+struct Histogram {
+  bounds: &'static [f64],
+  state: Mutex<HistogramState>,
+}
+
+#[derive(Debug, Default)]
+struct HistogramState {
+  buckets: Vec<u64>,
+  count: u64,
+  sum: f64,
+}
+
+impl Histogram {
+  fn new(bounds: &'static [f64]) -> Self {
+    let state = HistogramState { buckets: vec![0; bounds.len()], ..Default::default() };
+    Self { bounds, state: Mutex::new(state) }
+  }
+
+  fn observe(&self, value: f64) {
+    let mut state = self.state.lock().unwrap();
+    for (bucket, bound) in state.buckets.iter_mut().zip(self.bounds) {
+      if value <= *bound {
+        *bucket += 1;
+      }
+    }
+    state.count += 1;
+    state.sum += value;
+  }
+
+  fn render(&self, out: &mut String, name: &str, help: &str, endpoint: &str) {
+    let state = self.state.lock().unwrap();
+    header(out, name, help, "histogram");
+    for (bucket, bound) in state.buckets.iter().zip(self.bounds) {
+      let _ = writeln!(out, "{}_bucket{{endpoint=\"{}\",le=\"{}\"}} {}", name, endpoint, bound, bucket);
+    }
+    let _ = writeln!(out, "{}_bucket{{endpoint=\"{}\",le=\"+Inf\"}} {}", name, endpoint, state.count);
+    let _ = writeln!(out, "{}_sum{{endpoint=\"{}\"}} {}", name, endpoint, state.sum);
+    let _ = writeln!(out, "{}_count{{endpoint=\"{}\"}} {}", name, endpoint, state.count);
+  }
+}
+
+// traffic of the connections that already closed, live ones are read at scrape time
+#[derive(Debug, Default)]
+struct Connections {
+  live: HashMap<usize, Connection>,
+  bytes_sent: u64,
+  bytes_received: u64,
+  lost_packets: u64,
+}
+
+impl Connections {
+  fn totals(&self) -> (u64, u64, u64) {
+    self.live.values().map(Connection::stats).fold(
+      (self.bytes_sent, self.bytes_received, self.lost_packets),
+      |(sent, received, lost), stats| {
+        (sent + stats.udp_tx.bytes, received + stats.udp_rx.bytes, lost + stats.path.lost_packets)
+      },
+    )
+  }
+}
+
+/// Counters and histograms of one server endpoint, rendered in the Prometheus
+/// text format.
+///
+/// Traffic counters add up the statistics of live connections when scraped,
+/// RTT and congestion window are observed once per connection as it closes.
+#[derive(Debug)]
+pub struct Metrics {
+  endpoint: String,
+  accepted: AtomicU64,
+  refused: AtomicU64,
+  decisions: [AtomicU64; 4],
+  auth_failures: AtomicU64,
+  zero_rtt_accepted: AtomicU64,
+  streams: AtomicU64,
+  active_streams: AtomicU64,
+  draining: AtomicBool,
+  connections: Mutex<Connections>,
+  handshake: Histogram,
+  rtt: Histogram,
+  cwnd: Histogram,
+}
+
+impl Metrics {
+  /// `endpoint` labels every sample, telling servers apart on one dashboard.
+  pub fn new(endpoint: &str) -> Arc<Self> {
+    Arc::new(Self {
+      endpoint: endpoint.to_string(),
+      accepted: AtomicU64::new(0),
+      refused: AtomicU64::new(0),
+      decisions: Default::default(),
+      auth_failures: AtomicU64::new(0),
+      zero_rtt_accepted: AtomicU64::new(0),
+      streams: AtomicU64::new(0),
+      active_streams: AtomicU64::new(0),
+      draining: AtomicBool::new(false),
+      connections: Mutex::new(Connections::default()),
+      handshake: Histogram::new(SECONDS_BUCKETS),
+      rtt: Histogram::new(SECONDS_BUCKETS),
+      cwnd: Histogram::new(CWND_BUCKETS),
+    })
+  }
+
+  /// Serves the metrics on `METRICS_ADDR` when it is set.
+  pub async fn from_env(endpoint: &str) -> Result<Arc<Self>> {
+    let metrics = Self::new(endpoint);
+    let addr = env_or("METRICS_ADDR", String::new())?;
+    if !addr.is_empty() {
+      let addr: SocketAddr = addr.parse().with_context(|| format!("invalid METRICS_ADDR={:?}", addr))?;
+      let (addr, _task) = metrics.serve(addr).await?;
+      info!(addr:%; "📈 serving metrics on http://{}/metrics", addr);
+    }
+    Ok(metrics)
+  }
+
+  /// Counts `conn` as accepted and follows it until it closes.
+  pub fn accepted(self: &Arc<Self>, conn: &Connection, handshake: Duration) {
+    self.accepted.fetch_add(1, Ordering::Relaxed);
+    self.handshake.observe(handshake.as_secs_f64());
+    self.connections.lock().unwrap().live.insert(conn.stable_id(), conn.clone());
+    let metrics = self.clone();
+    let conn = conn.clone();
+    tokio::spawn(async move {
+      conn.closed().await;
+      metrics.closed(&conn);
+    });
+  }
+
+  fn closed(&self, conn: &Connection) {
+    let stats = conn.stats();
+    let mut connections = self.connections.lock().unwrap();
+    if connections.live.remove(&conn.stable_id()).is_none() {
+      return;
+    }
+    connections.bytes_sent += stats.udp_tx.bytes;
+    connections.bytes_received += stats.udp_rx.bytes;
+    connections.lost_packets += stats.path.lost_packets;
+    drop(connections);
+    self.rtt.observe(stats.path.rtt.as_secs_f64());
+    self.cwnd.observe(stats.path.cwnd as f64);
+  }
+
+  /// Counts a connection the server turned away. A handshake that failed on
+  /// the client's certificate is an authentication failure as well.
+  pub fn refused(&self, reason: Option<&ConnectionError>) {
+    self.refused.fetch_add(1, Ordering::Relaxed);
+    if let Some(ConnectionError::TransportError(e)) = reason {
+      let code = u64::from(e.code);
+      if (0x100..0x200).contains(&code) && AUTH_ALERTS.contains(&((code - 0x100) as u8)) {
+        self.auth_failure();
+      }
+    }
+  }
+
+  /// Counts an admission decision, connections refused or ignored are
+  /// refused as well.
+  pub fn admission(&self, decision: &Decision) {
+    self.decisions[decision.index()].fetch_add(1, Ordering::Relaxed);
+    if matches!(decision, Decision::Refuse | Decision::Ignore) {
+      self.refused(None);
+    }
+  }
+
+  pub fn auth_failure(&self) {
+    self.auth_failures.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub fn zero_rtt_accepted(&self) {
+    self.zero_rtt_accepted.fetch_add(1, Ordering::Relaxed);
+  }
+
+  /// Runs `task`, counting it as an active stream until it finishes or is dropped.
+  pub fn stream<F: Future>(self: &Arc<Self>, task: F) -> impl Future<Output = F::Output> {
+    let guard = ActiveStream::new(self.clone());
+    async move {
+      let _guard = guard;
+      task.await
+    }
+  }
+
+  /// Streams currently being handled, see [`Metrics::stream`].
+  pub fn active_streams(&self) -> u64 {
+    self.active_streams.load(Ordering::Relaxed)
+  }
+
+  /// Marks the server as draining: the streams in flight finish, new ones are
+  /// turned away, see [`crate::scenarios::accept_bi`].
+  pub fn drain(&self) {
+    self.draining.store(true, Ordering::Relaxed);
+  }
+
+  pub fn draining(&self) -> bool {
+    self.draining.load(Ordering::Relaxed)
+  }
+
+  /// The metrics in the Prometheus text exposition format.
+  pub fn render(&self) -> String {
+    let (bytes_sent, bytes_received, lost_packets, active) = {
+      let connections = self.connections.lock().unwrap();
+      let (sent, received, lost) = connections.totals();
+      (sent, received, lost, connections.live.len() as u64)
+    };
+    let mut out = String::new();
+    let endpoint = self.endpoint.as_str();
+    let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+    for (name, help, kind, value) in [
+      ("quic_connections_accepted_total", "Connections that completed the handshake.", "counter", load(&self.accepted)),
+      ("quic_connections_refused_total", "Connections turned away or failing the handshake.", "counter", load(&self.refused)),
+      ("quic_auth_failures_total", "Clients that failed to authenticate.", "counter", load(&self.auth_failures)),
+      ("quic_zero_rtt_accepted_total", "Connections whose 0-RTT data was accepted.", "counter", load(&self.zero_rtt_accepted)),
+      ("quic_active_connections", "Connections currently open.", "gauge", active),
+      ("quic_streams_total", "Streams handled.", "counter", load(&self.streams)),
+      ("quic_active_streams", "Streams currently being handled.", "gauge", load(&self.active_streams)),
+      ("quic_sent_bytes_total", "UDP payload bytes sent.", "counter", bytes_sent),
+      ("quic_received_bytes_total", "UDP payload bytes received.", "counter", bytes_received),
+      ("quic_lost_packets_total", "Packets declared lost.", "counter", lost_packets),
+    ] {
+      header(&mut out, name, help, kind);
+      let _ = writeln!(out, "{}{{endpoint=\"{}\"}} {}", name, endpoint, value);
+    }
+    let name = "quic_admission_decisions_total";
+    header(&mut out, name, "Incoming connections by admission decision.", "counter");
+    for (decision, counter) in Decision::NAMES.iter().zip(&self.decisions) {
+      let _ = writeln!(out, "{}{{endpoint=\"{}\",decision=\"{}\"}} {}", name, endpoint, decision, load(counter));
+    }
+    self.handshake.render(&mut out, "quic_handshake_duration_seconds", "Time to complete the handshake.", endpoint);
+    self.rtt.render(&mut out, "quic_rtt_seconds", "Smoothed RTT of connections when they closed.", endpoint);
+    self.cwnd.render(&mut out, "quic_congestion_window_bytes", "Congestion window of connections when they closed.", endpoint);
+    out
+  }
+
+  /// Answers `GET /metrics` over plain HTTP on `addr`, returning the bound
+  /// address and the task serving it.
+  pub async fn serve(self: &Arc<Self>, addr: SocketAddr) -> Result<(SocketAddr, JoinHandle<()>)> {
+    let listener = TcpListener::bind(addr).await.with_context(|| format!("failed to bind metrics on {}", addr))?;
+    let addr = listener.local_addr()?;
+    let metrics = self.clone();
+    let task = tokio::spawn(async move {
+      loop {
+        let (stream, peer) = match listener.accept().await {
+          Ok(accepted) => accepted,
+          Err(e) => {
+            warn!("failed to accept a metrics connection: {}", e);
+            tokio::time::sleep(ACCEPT_BACKOFF).await;
+            continue;
+          }
+        };
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+          if let Err(e) = metrics.scrape(stream).await {
+            debug!(peer:%; "metrics request failed: {:#}", e);
+          }
+        });
+      }
+    });
+    Ok((addr, task))
+  }
+
+  async fn scrape(&self, mut stream: TcpStream) -> Result<()> {
+    let mut request = Vec::new();
+    let read = async {
+      let mut buf = [0u8; 1024];
+      while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 || request.len() + n > MAX_REQUEST_SIZE {
+          anyhow::bail!("incomplete request");
+        }
+        request.extend_from_slice(&buf[..n]);
+      }
+      Ok(())
+    };
+    tokio::time::timeout(REQUEST_TIMEOUT, read).await.context("timed out reading the request")??;
+    let (status, content_type, body) = if request.starts_with(b"GET /metrics ") {
+      ("200 OK", "text/plain; version=0.0.4", self.render())
+    } else {
+      ("404 Not Found", "text/plain", String::from("not found\n"))
+    };
+    let response = format!(
+      "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+      status, content_type, body.len(), body,
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+  }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+  let _ = writeln!(out, "# HELP {} {}", name, help);
+  let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+struct ActiveStream(Arc<Metrics>);
+
+impl ActiveStream {
+  fn new(metrics: Arc<Metrics>) -> Self {
+    metrics.streams.fetch_add(1, Ordering::Relaxed);
+    metrics.active_streams.fetch_add(1, Ordering::Relaxed);
+    Self(metrics)
+  }
+}
+
+impl Drop for ActiveStream {
+  fn drop(&mut self) {
+    self.0.active_streams.fetch_sub(1, Ordering::Relaxed);
+  }
+}
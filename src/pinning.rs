@@ -0,0 +1,122 @@
+use std::sync::Arc;
+use anyhow::{Result, anyhow, bail};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use rustls::{
+  client::{
+    danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    verify_server_name,
+  },
+  crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+  pki_types::{CertificateDer, ServerName, UnixTime},
+  server::ParsedCertificate,
+  CertificateError, DigitallySignedStruct, SignatureScheme,
+};
+use x509_parser::{prelude::FromDer, certificate::X509Certificate, time::ASN1Time};
+
+use crate::identity::spki_sha256;
+
+/// Pin for the key in a DER certificate, see [`spki_sha256`].
+pub fn pin_for_cert(der: &[u8]) -> Result<String> {
+  let (_, cert) = X509Certificate::from_der(der).map_err(|e| anyhow!("invalid certificate: {}", e))?;
+  Ok(spki_sha256(cert.public_key().raw))
+}
+
+/// Trusts a server by the key it holds instead of by who signed its certificate.
+///
+/// The handshake succeeds when the end-entity certificate carries one of the
+/// pinned SPKI SHA-256 hashes. Keep a backup pin for a key that is not
+/// deployed yet, so the server can move to it without breaking clients.
+/// The certificate must still be within its validity period and name the
+/// server, and the handshake signature is checked against its key, so a
+/// certificate re-issued for the same key keeps working.
+///
+/// Intermediates are ignored: nothing here chains them to the end entity,
+/// so a pinned intermediate would vouch for any key.
+#[derive(Debug)]
+pub struct PinnedServerVerifier {
+  pins: Vec<String>,
+  provider: Arc<CryptoProvider>,
+}
+
+impl PinnedServerVerifier {
+  pub fn new(pins: impl IntoIterator<Item = impl Into<String>>) -> Result<Self> {
+    let pins: Vec<String> = pins.into_iter().map(Into::into).collect();
+    if pins.is_empty() {
+      bail!("at least one pin is required");
+    }
+    for pin in &pins {
+      match BASE64_STANDARD.decode(pin) {
+        Ok(hash) if hash.len() == 32 => {}
+        _ => bail!("pin {:?} is not a base64 SHA-256 hash", pin),
+      }
+    }
+    let provider = CryptoProvider::get_default()
+      .cloned()
+      .ok_or_else(|| anyhow!("no default crypto provider installed"))?;
+    Ok(Self { pins, provider })
+  }
+
+  /// Parses a comma separated list of pins, e.g. from an environment variable.
+  pub fn from_list(list: &str) -> Result<Self> {
+    Self::new(list.split(',').map(str::trim).filter(|pin| !pin.is_empty()))
+  }
+
+  pub fn pins(&self) -> &[String] {
+    &self.pins
+  }
+
+  fn is_pinned(&self, cert: &CertificateDer<'_>) -> bool {
+    pin_for_cert(cert).is_ok_and(|pin| self.pins.contains(&pin))
+  }
+}
+
+impl ServerCertVerifier for PinnedServerVerifier {
+  fn verify_server_cert(
+    &self,
+    end_entity: &CertificateDer<'_>,
+    _intermediates: &[CertificateDer<'_>],
+    server_name: &ServerName<'_>,
+    _ocsp_response: &[u8],
+    now: UnixTime,
+  ) -> Result<ServerCertVerified, rustls::Error> {
+    let (_, cert) = X509Certificate::from_der(end_entity)
+      .map_err(|_| rustls::Error::InvalidCertificate(CertificateError::BadEncoding))?;
+    let now = ASN1Time::from_timestamp(now.as_secs() as i64)
+      .map_err(|_| rustls::Error::General("system time out of range".into()))?;
+    if now < cert.validity().not_before {
+      return Err(rustls::Error::InvalidCertificate(CertificateError::NotValidYet));
+    }
+    if now > cert.validity().not_after {
+      return Err(rustls::Error::InvalidCertificate(CertificateError::Expired));
+    }
+    verify_server_name(&ParsedCertificate::try_from(end_entity)?, server_name)?;
+
+    if self.is_pinned(end_entity) {
+      Ok(ServerCertVerified::assertion())
+    } else {
+      Err(rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure))
+    }
+  }
+
+  fn verify_tls12_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+  }
+
+  fn verify_tls13_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+  }
+
+  fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+    self.provider.signature_verification_algorithms.supported_schemes()
+  }
+}
@@ -0,0 +1,168 @@
+use std::{
+  collections::HashMap, fs, path::{Path, PathBuf}, str, sync::{Arc, Mutex}, time::{Duration, SystemTime},
+};
+use anyhow::{Context, Result, bail};
+use log::warn;
+use quinn::{Connection, ConnectionError};
+use tokio::task::JoinHandle;
+
+use crate::wire::read_line;
+
+const MAX_NOTIFICATION_SIZE: usize = 1024;
+
+/// Carried on a server-opened unidirectional stream as `<KIND> <body>\r\n`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+  pub kind: String,
+  pub body: String,
+}
+
+impl Notification {
+  pub fn new(kind: &str, body: &str) -> Self {
+    Self { kind: kind.to_string(), body: body.to_string() }
+  }
+
+  pub fn line(&self) -> String {
+    format!("{} {}", self.kind, self.body)
+  }
+
+  pub fn encode(&self) -> Vec<u8> {
+    format!("{}\r\n", self.line()).into_bytes()
+  }
+
+  pub fn parse(line: &[u8]) -> Result<Self> {
+    let line = str::from_utf8(line).context("notification is malformed UTF-8")?;
+    match line.split_once(' ') {
+      Some((kind, body)) if !kind.is_empty() => Ok(Self::new(kind, body)),
+      _ => bail!("malformed notification {:?}", line),
+    }
+  }
+}
+
+/// Opens a unidirectional stream to `conn` and delivers `notification` on it.
+pub async fn notify(conn: &Connection, notification: &Notification) -> Result<()> {
+  let mut send = conn.open_uni().await.context("failed to open push stream")?;
+  send.write_all(&notification.encode()).await.context("failed to push notification")?;
+  send.finish()?;
+  Ok(())
+}
+
+struct Watched {
+  modified: Option<SystemTime>,
+  subscribers: Vec<Connection>,
+}
+
+/// Remembers which connections fetched which files and pushes a
+/// `CHANGED <filename>` notification to them whenever a file is modified.
+#[derive(Default)]
+pub struct FileWatcher {
+  files: Mutex<HashMap<PathBuf, Watched>>,
+}
+
+impl FileWatcher {
+  pub fn new() -> Arc<Self> {
+    Arc::new(Self::default())
+  }
+
+  pub fn subscribe(&self, conn: &Connection, path: &Path) {
+    let mut files = self.files.lock().unwrap();
+    let watched = files.entry(path.to_path_buf()).or_insert_with(|| Watched {
+      modified: modified(path),
+      subscribers: Vec::new(),
+    });
+    if !watched.subscribers.iter().any(|c| c.stable_id() == conn.stable_id()) {
+      watched.subscribers.push(conn.clone());
+    }
+  }
+
+  /// Polls the subscribed files every `interval` until the returned task is aborted.
+  pub fn watch(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+    let watcher = self.clone();
+    tokio::spawn(async move {
+      let mut ticker = tokio::time::interval(interval);
+      loop {
+        ticker.tick().await;
+        for (conn, notification) in watcher.changes() {
+          tokio::spawn(async move {
+            if let Err(e) = notify(&conn, &notification).await {
+              warn!(conn_id = conn.stable_id(), remote:% = conn.remote_address(); "failed to notify: {:#}", e);
+            }
+          });
+        }
+      }
+    })
+  }
+
+  fn changes(&self) -> Vec<(Connection, Notification)> {
+    let mut files = self.files.lock().unwrap();
+    files.retain(|_, watched| {
+      watched.subscribers.retain(|c| c.close_reason().is_none());
+      !watched.subscribers.is_empty()
+    });
+
+    let mut changes = Vec::new();
+    for (path, watched) in files.iter_mut() {
+      let current = modified(path);
+      if current == watched.modified {
+        continue;
+      }
+      watched.modified = current;
+      let filename = path.file_name().unwrap_or_default().to_string_lossy();
+      let notification = Notification::new("CHANGED", &filename);
+      for conn in &watched.subscribers {
+        changes.push((conn.clone(), notification.clone()));
+      }
+    }
+    changes
+  }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+  fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+type Handler = Box<dyn Fn(&Notification) + Send + Sync>;
+
+/// Client side of server push: handlers are registered per notification kind
+/// and [`PushListener::run`] dispatches every server-opened stream to them.
+#[derive(Default)]
+pub struct PushListener {
+  handlers: HashMap<String, Handler>,
+}
+
+impl PushListener {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn on(mut self, kind: &str, handler: impl Fn(&Notification) + Send + Sync + 'static) -> Self {
+    self.handlers.insert(kind.to_string(), Box::new(handler));
+    self
+  }
+
+  /// Accepts server-opened streams until the connection is closed.
+  pub async fn run(self, conn: Connection) -> Result<()> {
+    let handlers = Arc::new(self.handlers);
+    loop {
+      let mut recv = match conn.accept_uni().await {
+        Ok(recv) => recv,
+        Err(ConnectionError::ApplicationClosed(_) | ConnectionError::LocallyClosed) => return Ok(()),
+        Err(e) => return Err(e.into()),
+      };
+      let handlers = handlers.clone();
+      tokio::spawn(async move {
+        let notification = match read_line(&mut recv, MAX_NOTIFICATION_SIZE).await {
+          Ok(line) => Notification::parse(&line),
+          Err(e) => Err(e),
+        };
+        match notification {
+          Ok(n) => match handlers.get(&n.kind) {
+            Some(handler) => handler(&n),
+            None => warn!(kind = n.kind.as_str(); "no handler for notification"),
+          },
+          Err(e) => warn!("failed reading push stream: {:#}", e),
+        }
+      });
+    }
+  }
+}
@@ -0,0 +1,294 @@
+use std::{
+  fmt, fs, future::Future, path::PathBuf, sync::Arc,
+  time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use anyhow::{Context, Result};
+use log::warn;
+use quinn::{Connection, ConnectionError, ConnectionStats, Dir, StreamId};
+use serde_json::{json, Value};
+use tokio::{fs::File, io::AsyncWriteExt, sync::{mpsc, oneshot}};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Which end of the connection a trace is written from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vantage {
+  Client,
+  Server,
+}
+
+impl fmt::Display for Vantage {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Vantage::Client => "client",
+      Vantage::Server => "server",
+    })
+  }
+}
+
+/// Writes a qlog trace per connection into a directory, as JSON-SEQ files
+/// (`.sqlog`) that qvis opens.
+///
+/// Quinn 0.11 has no qlog hooks, so traces are built from what the public API
+/// shows: [`Connection::stats`] is sampled for datagrams, RTT, congestion window,
+/// losses and congestion events, and [`QlogTrace::stream`] records streams the
+/// application handles. Packet numbers and frame contents are not available.
+#[derive(Debug, Clone)]
+pub struct Qlog {
+  dir: Option<PathBuf>,
+  vantage: Vantage,
+  interval: Duration,
+}
+
+impl Qlog {
+  /// Traces into `dir`, creating it if needed.
+  pub fn new(dir: impl Into<PathBuf>, vantage: Vantage) -> Result<Self> {
+    let dir = dir.into();
+    fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    Ok(Self { dir: Some(dir), vantage, interval: SAMPLE_INTERVAL })
+  }
+
+  /// A `Qlog` that traces nothing.
+  pub fn disabled(vantage: Vantage) -> Self {
+    Self { dir: None, vantage, interval: SAMPLE_INTERVAL }
+  }
+
+  /// Traces into `QLOG_DIR` when it is set.
+  pub fn from_env(vantage: Vantage) -> Result<Self> {
+    match std::env::var_os("QLOG_DIR") {
+      Some(dir) => Self::new(dir, vantage),
+      None => Ok(Self::disabled(vantage)),
+    }
+  }
+
+  /// How often connection statistics are sampled, 10ms by default.
+  pub fn interval(mut self, interval: Duration) -> Self {
+    self.interval = interval;
+    self
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.dir.is_some()
+  }
+
+  /// Starts tracing `conn` until it closes or the returned trace, and all its
+  /// clones, are dropped. A trace file that cannot be created is logged and the
+  /// connection goes untraced.
+  pub fn trace(&self, conn: &Connection) -> QlogTrace {
+    let Some(dir) = &self.dir else {
+      return QlogTrace { inner: None };
+    };
+    let reference_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let name = format!("{}-{}-{}.sqlog", self.vantage, reference_time.as_millis(), conn.stable_id());
+    let writer = Writer::spawn(dir.join(name), conn.stable_id());
+    let (stop, stopped) = oneshot::channel();
+    let trace = QlogTrace { inner: Some(Arc::new(Tracing { writer: writer.clone(), _stop: stop })) };
+    writer.record(&json!({
+      "qlog_version": "0.3",
+      "qlog_format": "JSON-SEQ",
+      "title": format!("quic-rs-demo {} connection {}", self.vantage, conn.stable_id()),
+      "trace": {
+        "vantage_point": { "type": self.vantage.to_string() },
+        "common_fields": {
+          "time_format": "relative",
+          "reference_time": reference_time.as_secs_f64() * 1000.0,
+          "group_id": conn.stable_id().to_string(),
+        },
+      },
+    }));
+    let local_ip = conn.local_ip().map(|ip| ip.to_string());
+    let remote = conn.remote_address();
+    writer.event("connectivity:connection_started", json!({
+      "src_ip": local_ip,
+      "dst_ip": remote.ip().to_string(),
+      "dst_port": remote.port(),
+    }));
+    sample(writer, conn.clone(), self.interval, stopped);
+    trace
+  }
+}
+
+// hands records to a task writing them to the trace file, so the runtime never
+// waits on the disk
+#[derive(Debug, Clone)]
+struct Writer {
+  records: mpsc::UnboundedSender<Vec<u8>>,
+  start: Instant,
+}
+
+impl Writer {
+  fn spawn(path: PathBuf, conn_id: usize) -> Self {
+    let (records, mut pending) = mpsc::unbounded_channel::<Vec<u8>>();
+    tokio::spawn(async move {
+      let written = async {
+        let mut file = File::create(&path).await.with_context(|| format!("failed to create {}", path.display()))?;
+        while let Some(record) = pending.recv().await {
+          file.write_all(&record).await?;
+          while let Ok(record) = pending.try_recv() {
+            file.write_all(&record).await?;
+          }
+          file.flush().await?;
+        }
+        anyhow::Ok(())
+      };
+      if let Err(e) = written.await {
+        warn!(conn_id; "qlog disabled for this connection: {:#}", e);
+      }
+    });
+    Self { records, start: Instant::now() }
+  }
+
+  fn event(&self, name: &str, data: Value) {
+    let time = self.start.elapsed().as_secs_f64() * 1000.0;
+    self.record(&json!({ "time": time, "name": name, "data": data }));
+  }
+
+  // JSON-SEQ (RFC 7464): every record starts with a record separator
+  fn record(&self, value: &Value) {
+    let mut record = vec![0x1e];
+    // a Value always serializes
+    let _ = serde_json::to_writer(&mut record, value);
+    record.push(b'\n');
+    // the file could not be written, the failure was logged
+    let _ = self.records.send(record);
+  }
+
+  fn is_closed(&self) -> bool {
+    self.records.is_closed()
+  }
+}
+
+#[derive(Debug)]
+struct Tracing {
+  writer: Writer,
+  // stops the sampler when the last handle to the trace goes
+  _stop: oneshot::Sender<()>,
+}
+
+/// The trace of one connection. Cloning shares the file.
+///
+/// Records are flushed as soon as the writer task gets them, so a process that
+/// exits without waiting for the connection to drain still leaves a readable
+/// trace, missing at most the last records.
+#[derive(Debug, Clone)]
+pub struct QlogTrace {
+  inner: Option<Arc<Tracing>>,
+}
+
+impl QlogTrace {
+  /// Adds an event, `name` being `<category>:<event>`.
+  pub fn event(&self, name: &str, data: Value) {
+    if let Some(inner) = &self.inner {
+      inner.writer.event(name, data);
+    }
+  }
+
+  /// Runs `task` for stream `id`, recording when it opened and when it closed.
+  /// The returned future owns a handle to the trace, so it can be spawned.
+  pub fn stream<F, T>(&self, id: StreamId, task: F) -> impl Future<Output = Result<T>>
+  where
+    F: Future<Output = Result<T>>,
+  {
+    let trace = self.clone();
+    async move {
+      let stream_type = match id.dir() {
+        Dir::Bi => "bidirectional",
+        Dir::Uni => "unidirectional",
+      };
+      let stream_id = u64::from(id);
+      trace.event("transport:stream_state_updated", json!({
+        "stream_id": stream_id,
+        "stream_type": stream_type,
+        "new": "open",
+      }));
+      let result = task.await;
+      let mut data = json!({ "stream_id": stream_id, "stream_type": stream_type, "new": "closed" });
+      if let Err(e) = &result {
+        data["trigger"] = json!("error");
+        data["reason"] = json!(format!("{:#}", e));
+      }
+      trace.event("transport:stream_state_updated", data);
+      result
+    }
+  }
+}
+
+// samples until the connection closes or the trace is dropped, holding on to
+// the connection only until then
+fn sample(trace: Writer, conn: Connection, interval: Duration, mut stopped: oneshot::Receiver<()>) {
+  tokio::spawn(async move {
+    let mut last = ConnectionStats::default();
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+      tokio::select! {
+        // a trace dropped because its connection closed still records the close
+        biased;
+        reason = conn.closed() => {
+          emit_changes(&trace, &last, conn.stats());
+          trace.event("connectivity:connection_closed", closed(&reason));
+          return;
+        }
+        _ = &mut stopped => {
+          emit_changes(&trace, &last, conn.stats());
+          return;
+        }
+        _ = ticker.tick() => {
+          if trace.is_closed() {
+            return;
+          }
+          last = emit_changes(&trace, &last, conn.stats());
+        }
+      }
+    }
+  });
+}
+
+fn emit_changes(trace: &Writer, last: &ConnectionStats, now: ConnectionStats) -> ConnectionStats {
+  let sent = now.udp_tx.datagrams - last.udp_tx.datagrams;
+  if sent > 0 {
+    trace.event("transport:datagrams_sent", json!({
+      "count": sent,
+      "byte_length": now.udp_tx.bytes - last.udp_tx.bytes,
+      "packets_sent": now.path.sent_packets,
+    }));
+  }
+  let received = now.udp_rx.datagrams - last.udp_rx.datagrams;
+  if received > 0 {
+    trace.event("transport:datagrams_received", json!({
+      "count": received,
+      "byte_length": now.udp_rx.bytes - last.udp_rx.bytes,
+    }));
+  }
+  for _ in last.path.lost_packets..now.path.lost_packets {
+    trace.event("recovery:packet_lost", json!({}));
+  }
+  if now.path.congestion_events > last.path.congestion_events {
+    trace.event("recovery:congestion_state_updated", json!({
+      "new": "recovery",
+      "congestion_events": now.path.congestion_events,
+    }));
+  }
+  if now.path.rtt != last.path.rtt || now.path.cwnd != last.path.cwnd {
+    trace.event("recovery:metrics_updated", json!({
+      "smoothed_rtt": now.path.rtt.as_secs_f64() * 1000.0,
+      "congestion_window": now.path.cwnd,
+      "mtu": now.path.current_mtu,
+    }));
+  }
+  now
+}
+
+fn closed(reason: &ConnectionError) -> Value {
+  let (owner, trigger) = match reason {
+    ConnectionError::LocallyClosed => ("local", "clean"),
+    ConnectionError::ApplicationClosed(_) | ConnectionError::ConnectionClosed(_) => ("remote", "clean"),
+    ConnectionError::TimedOut => ("local", "idle_timeout"),
+    ConnectionError::Reset => ("remote", "stateless_reset"),
+    _ => ("local", "error"),
+  };
+  let mut data = json!({ "owner": owner, "trigger": trigger, "reason": reason.to_string() });
+  if let ConnectionError::ApplicationClosed(close) = reason {
+    data["application_code"] = json!(close.error_code.into_inner());
+  }
+  data
+}
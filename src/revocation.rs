@@ -0,0 +1,232 @@
+use std::{
+  fmt, fs, path::{Path, PathBuf}, str::FromStr, sync::{Arc, Mutex, RwLock}, time::{Duration, SystemTime},
+};
+use anyhow::{Context, Result, bail};
+use log::{info, warn};
+use rustls::{
+  client::danger::HandshakeSignatureValid,
+  pki_types::{pem::PemObject, CertificateDer, CertificateRevocationListDer, UnixTime},
+  server::{
+    danger::{ClientCertVerified, ClientCertVerifier},
+    WebPkiClientVerifier,
+  },
+  DigitallySignedStruct, DistinguishedName, RootCertStore, SignatureScheme,
+};
+use tokio::task::JoinHandle;
+
+/// What to do with a client certificate none of the loaded CRLs speaks about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevocationPolicy {
+  /// only reject certificates a CRL lists as revoked
+  RevokedOnly,
+  /// also reject certificates whose issuer has no loaded CRL
+  FailIfUnknown,
+}
+
+impl FromStr for RevocationPolicy {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s {
+      "revoked-only" => Ok(RevocationPolicy::RevokedOnly),
+      "fail-if-unknown" => Ok(RevocationPolicy::FailIfUnknown),
+      _ => bail!("unknown revocation policy {:?}, expected revoked-only or fail-if-unknown", s),
+    }
+  }
+}
+
+impl fmt::Display for RevocationPolicy {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      RevocationPolicy::RevokedOnly => write!(f, "revoked-only"),
+      RevocationPolicy::FailIfUnknown => write!(f, "fail-if-unknown"),
+    }
+  }
+}
+
+/// Reads every CRL at `path`, a single file or a directory of them, each
+/// either DER or PEM. A missing path holds no CRLs.
+pub fn load_crls(path: &Path) -> Result<Vec<CertificateRevocationListDer<'static>>> {
+  let mut crls = Vec::new();
+  for file in crl_files(path)? {
+    let bytes = fs::read(&file).with_context(|| format!("failed to read CRL {}", file.display()))?;
+    if bytes.starts_with(b"-----BEGIN") {
+      for crl in CertificateRevocationListDer::pem_slice_iter(&bytes) {
+        crls.push(crl.with_context(|| format!("malformed PEM in {}", file.display()))?);
+      }
+    } else {
+      crls.push(CertificateRevocationListDer::from(bytes));
+    }
+  }
+  Ok(crls)
+}
+
+fn crl_files(path: &Path) -> Result<Vec<PathBuf>> {
+  if !path.exists() {
+    return Ok(Vec::new());
+  }
+  if !path.is_dir() {
+    return Ok(vec![path.to_path_buf()]);
+  }
+  let mut files = Vec::new();
+  for entry in fs::read_dir(path).context("failed to list CRL dir")? {
+    let entry = entry?;
+    if entry.file_type()?.is_file() && !entry.file_name().to_string_lossy().starts_with('.') {
+      files.push(entry.path());
+    }
+  }
+  files.sort();
+  Ok(files)
+}
+
+// the files and their mtimes, a CRL counts as changed when this does
+fn snapshot(path: &Path) -> Vec<(PathBuf, Option<SystemTime>)> {
+  crl_files(path)
+    .unwrap_or_default()
+    .into_iter()
+    .map(|file| {
+      let modified = fs::metadata(&file).and_then(|m| m.modified()).ok();
+      (file, modified)
+    })
+    .collect()
+}
+
+/// Verifies client certificates against `roots` and the CRLs at `crl_path`,
+/// swapping in a fresh [`WebPkiClientVerifier`] whenever the CRLs change.
+#[derive(Debug)]
+pub struct ReloadingClientVerifier {
+  roots: Arc<RootCertStore>,
+  crl_path: PathBuf,
+  policy: RevocationPolicy,
+  allow_unauthenticated: bool,
+  root_hints: Vec<DistinguishedName>,
+  inner: RwLock<Arc<dyn ClientCertVerifier>>,
+  loaded: Mutex<Vec<(PathBuf, Option<SystemTime>)>>,
+}
+
+impl ReloadingClientVerifier {
+  pub fn new(roots: Arc<RootCertStore>, crl_path: impl Into<PathBuf>, policy: RevocationPolicy) -> Result<Self> {
+    let crl_path = crl_path.into();
+    let loaded = snapshot(&crl_path);
+    let crls = load_crls(&crl_path)?;
+    let inner = build(&roots, crls, policy)?;
+    Ok(Self {
+      root_hints: roots.subjects(),
+      roots,
+      crl_path,
+      policy,
+      allow_unauthenticated: false,
+      inner: RwLock::new(inner),
+      loaded: Mutex::new(loaded),
+    })
+  }
+
+  /// Lets clients without a certificate complete the handshake, a
+  /// certificate that is presented is still verified.
+  pub fn allow_unauthenticated(mut self) -> Self {
+    self.allow_unauthenticated = true;
+    self
+  }
+
+  pub fn policy(&self) -> RevocationPolicy {
+    self.policy
+  }
+
+  pub fn allows_unauthenticated(&self) -> bool {
+    self.allow_unauthenticated
+  }
+
+  /// Re-reads the CRLs and returns how many were loaded. On error the
+  /// previous CRLs stay in effect until the files change again.
+  pub fn reload(&self) -> Result<usize> {
+    *self.loaded.lock().unwrap() = snapshot(&self.crl_path);
+    let crls = load_crls(&self.crl_path)?;
+    let count = crls.len();
+    *self.inner.write().unwrap() = build(&self.roots, crls, self.policy)?;
+    Ok(count)
+  }
+
+  /// Polls the CRLs every `interval` and reloads them when they change,
+  /// until the returned task is aborted.
+  pub fn watch(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+    let verifier = self.clone();
+    tokio::spawn(async move {
+      let mut ticker = tokio::time::interval(interval);
+      loop {
+        ticker.tick().await;
+        if snapshot(&verifier.crl_path) == *verifier.loaded.lock().unwrap() {
+          continue;
+        }
+        match verifier.reload() {
+          Ok(count) => info!(count, crl:% = verifier.crl_path.display(); "🔄 reloaded CRLs"),
+          Err(e) => warn!(crl:% = verifier.crl_path.display(); "failed to reload CRLs, keeping the previous ones: {:#}", e),
+        }
+      }
+    })
+  }
+
+  fn current(&self) -> Arc<dyn ClientCertVerifier> {
+    self.inner.read().unwrap().clone()
+  }
+}
+
+fn build(
+  roots: &Arc<RootCertStore>,
+  crls: Vec<CertificateRevocationListDer<'static>>,
+  policy: RevocationPolicy,
+) -> Result<Arc<dyn ClientCertVerifier>> {
+  // without any CRL webpki skips revocation checking altogether
+  if crls.is_empty() && policy == RevocationPolicy::FailIfUnknown {
+    bail!("no CRLs loaded, every client would have an unknown revocation status");
+  }
+  let mut builder = WebPkiClientVerifier::builder(roots.clone()).with_crls(crls);
+  if policy == RevocationPolicy::RevokedOnly {
+    builder = builder.allow_unknown_revocation_status();
+  }
+  builder.build().context("failed to build client verifier")
+}
+
+impl ClientCertVerifier for ReloadingClientVerifier {
+  fn offer_client_auth(&self) -> bool {
+    self.current().offer_client_auth()
+  }
+
+  fn client_auth_mandatory(&self) -> bool {
+    !self.allow_unauthenticated && self.current().client_auth_mandatory()
+  }
+
+  fn root_hint_subjects(&self) -> &[DistinguishedName] {
+    &self.root_hints
+  }
+
+  fn verify_client_cert(
+    &self,
+    end_entity: &CertificateDer<'_>,
+    intermediates: &[CertificateDer<'_>],
+    now: UnixTime,
+  ) -> Result<ClientCertVerified, rustls::Error> {
+    self.current().verify_client_cert(end_entity, intermediates, now)
+  }
+
+  fn verify_tls12_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    self.current().verify_tls12_signature(message, cert, dss)
+  }
+
+  fn verify_tls13_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    self.current().verify_tls13_signature(message, cert, dss)
+  }
+
+  fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+    self.current().supported_verify_schemes()
+  }
+}
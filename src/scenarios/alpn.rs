@@ -0,0 +1,27 @@
+use std::path::Path;
+use anyhow::Result;
+use quinn::{crypto::rustls::HandshakeData, Connection};
+
+/// The protocols the server speaks. A client offering none of them, or no
+/// protocol at all, fails the handshake with `no_application_protocol`.
+pub const SERVER_PROTOCOLS: &[&[u8]] = &[b"h1", b"h2"];
+
+/// The basic server's TLS config, negotiating [`SERVER_PROTOCOLS`]. It serves
+/// files with [`super::basic::serve`].
+pub fn server_tls(cert_dir: &Path) -> Result<rustls::ServerConfig> {
+  let mut tls_config = super::server_tls(cert_dir)?;
+  tls_config.alpn_protocols = SERVER_PROTOCOLS.iter().map(|&p| p.into()).collect();
+  Ok(tls_config)
+}
+
+/// Offers `protocols` in the handshake, nothing when it is empty.
+pub fn client_tls(cert_dir: &Path, protocols: &[&[u8]]) -> Result<rustls::ClientConfig> {
+  let mut tls_config = super::client_tls(cert_dir)?;
+  tls_config.alpn_protocols = protocols.iter().map(|&p| p.into()).collect();
+  Ok(tls_config)
+}
+
+/// The protocol both ends agreed on.
+pub fn protocol(conn: &Connection) -> Option<Vec<u8>> {
+  conn.handshake_data()?.downcast::<HandshakeData>().ok()?.protocol
+}
@@ -0,0 +1,49 @@
+use std::{path::{Path, PathBuf}, sync::Arc};
+use anyhow::Result;
+use quinn::{Endpoint, Incoming};
+
+use crate::{
+  admission::Admission,
+  certs::ReloadingCertResolver,
+  files,
+  keylog,
+  logging::spawn_logged,
+  metrics::Metrics,
+  qlog::Qlog,
+};
+
+/// Serves `cert.der` and `key.der` from `cert_dir`. Once the resolver is
+/// watched, new handshakes pick up files that were replaced.
+pub fn server_tls(cert_dir: &Path) -> Result<(rustls::ServerConfig, Arc<ReloadingCertResolver>)> {
+  let resolver = Arc::new(ReloadingCertResolver::new(cert_dir.join("cert.der"), cert_dir.join("key.der"))?);
+  let mut tls_config = rustls::ServerConfig::builder()
+    .with_no_client_auth()
+    .with_cert_resolver(resolver.clone());
+  tls_config.key_log = keylog::from_env();
+  Ok((tls_config, resolver))
+}
+
+/// Answers `GET <filename>\r\n` with the file from `root`, one request per
+/// bidirectional stream, until the endpoint closes.
+pub async fn serve(endpoint: Endpoint, admission: Arc<Admission>, root: PathBuf, qlog: Qlog, metrics: Arc<Metrics>) {
+  let root: Arc<Path> = root.into();
+  super::serve(&endpoint, &admission, &metrics, |incoming| {
+    handle_conn(incoming, root.clone(), qlog.clone(), metrics.clone())
+  }).await
+}
+
+async fn handle_conn(incoming: Incoming, root: Arc<Path>, qlog: Qlog, metrics: Arc<Metrics>) -> Result<()> {
+  let (conn, trace) = super::accept(incoming, &qlog, &metrics).await?;
+  let conn_id = conn.stable_id();
+  loop {
+    let stream = match super::accept_bi(&conn, &metrics).await {
+      Ok(stream) => stream,
+      Err(e) => return super::closed(conn_id, e),
+    };
+    let root = root.clone();
+    let task = trace.stream(stream.0.id(), async move {
+      files::respond(conn_id, stream, |req| files::read(&root, req)).await
+    });
+    spawn_logged(format!("stream on connection {}", conn_id), metrics.stream(task));
+  }
+}
@@ -0,0 +1,47 @@
+use std::{str, sync::Arc};
+use anyhow::{Context, Result};
+use log::info;
+use quinn::{Connection, ConnectionError, Endpoint, Incoming};
+
+use crate::{admission::Admission, metrics::Metrics, qlog::Qlog};
+
+/// Answers the first datagram of each connection with a hello and every
+/// later one with `ack`, until the endpoint closes.
+pub async fn serve(endpoint: Endpoint, admission: Arc<Admission>, qlog: Qlog, metrics: Arc<Metrics>) {
+  super::serve(&endpoint, &admission, &metrics, |incoming| handle_conn(incoming, qlog.clone(), metrics.clone())).await
+}
+
+async fn handle_conn(incoming: Incoming, qlog: Qlog, metrics: Arc<Metrics>) -> Result<()> {
+  let (conn, _trace) = super::accept(incoming, &qlog, &metrics).await?;
+  let conn_id = conn.stable_id();
+  let msg = conn.read_datagram().await.context("failed to read datagram")?;
+  info!(conn_id; "receive msg: {}", str::from_utf8(&msg)?);
+  info!(conn_id; "responding to client...");
+  conn.send_datagram(b"Hello from server"[..].into()).context("failed to send response")?;
+  // wait for client termination
+  loop {
+    match conn.read_datagram().await {
+      Ok(msg) => {
+        info!(conn_id; "received: {}", str::from_utf8(&msg)?);
+        conn.send_datagram(b"ack"[..].into())?;
+      }
+      Err(ConnectionError::ApplicationClosed { .. }) => {
+        info!(conn_id; "client terminated");
+        break;
+      }
+      Err(e) => return Err(e.into()),
+    }
+  }
+  info!(conn_id; "Done handle conn");
+  Ok(())
+}
+
+/// Sends `msg` as a datagram and waits for the next one from the server.
+///
+/// Datagrams are unreliable, a lost one leaves this waiting until the
+/// connection closes.
+pub async fn send(conn: &Connection, msg: &str) -> Result<String> {
+  conn.send_datagram(msg.as_bytes().to_vec().into()).context("failed sending datagram")?;
+  let resp = conn.read_datagram().await.context("failed to receive datagram response")?;
+  Ok(str::from_utf8(&resp)?.to_string())
+}
@@ -0,0 +1,154 @@
+//! The scenario behind each example, server and client side. Certificate
+//! directories, document roots and addresses are arguments, so the examples
+//! pass their fixed ones and the tests temporary directories and port 0.
+
+use std::{future::Future, net::SocketAddr, path::Path, sync::Arc, time::Instant};
+use anyhow::{Context, Result};
+use log::{debug, info};
+use quinn::{
+  crypto::rustls::{QuicClientConfig, QuicServerConfig},
+  ClientConfig,
+  Connection,
+  ConnectionError,
+  Endpoint,
+  Incoming,
+  RecvStream,
+  SendStream,
+  ServerConfig,
+};
+
+use crate::{
+  admission::Admission,
+  cancellation::AppError,
+  certs,
+  keylog,
+  logging::spawn_logged,
+  metrics::Metrics,
+  qlog::{Qlog, QlogTrace},
+};
+
+pub mod alpn;
+pub mod basic;
+pub mod datagram;
+pub mod mtls;
+pub mod multiplexing;
+pub mod session;
+pub mod throughput;
+pub mod zero_rtt;
+
+/// Serves `cert.der` and `key.der` from `cert_dir`, without client auth.
+pub fn server_tls(cert_dir: &Path) -> Result<rustls::ServerConfig> {
+  let chain = certs::load_chain(&cert_dir.join("cert.der"))?;
+  let key = certs::load_key(&cert_dir.join("key.der"))?;
+  let mut tls_config = rustls::ServerConfig::builder()
+    .with_no_client_auth()
+    .with_single_cert(chain, key)?;
+  tls_config.key_log = keylog::from_env();
+  Ok(tls_config)
+}
+
+/// Trusts the self-signed `cert.der` in `cert_dir`.
+pub fn client_tls(cert_dir: &Path) -> Result<rustls::ClientConfig> {
+  let roots = certs::load_roots(&cert_dir.join("cert.der"))?;
+  let mut tls_config = rustls::ClientConfig::builder()
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+  tls_config.key_log = keylog::from_env();
+  Ok(tls_config)
+}
+
+pub fn server_config(tls_config: rustls::ServerConfig) -> Result<ServerConfig> {
+  let quic_config = QuicServerConfig::try_from(tls_config)?;
+  Ok(ServerConfig::with_crypto(Arc::new(quic_config)))
+}
+
+pub fn client_config(tls_config: rustls::ClientConfig) -> Result<ClientConfig> {
+  let quic_config = QuicClientConfig::try_from(tls_config)?;
+  Ok(ClientConfig::new(Arc::new(quic_config)))
+}
+
+pub fn server_endpoint(tls_config: rustls::ServerConfig, addr: SocketAddr) -> Result<Endpoint> {
+  Ok(Endpoint::server(server_config(tls_config)?, addr)?)
+}
+
+pub fn client_endpoint(tls_config: rustls::ClientConfig, addr: SocketAddr) -> Result<Endpoint> {
+  let mut endpoint = Endpoint::client(addr)?;
+  endpoint.set_default_client_config(client_config(tls_config)?);
+  Ok(endpoint)
+}
+
+/// Connects to the server at `addr`, which has a certificate for `localhost`.
+pub async fn connect(endpoint: &Endpoint, addr: SocketAddr) -> Result<Connection> {
+  let conn = endpoint
+    .connect(addr, "localhost")?
+    .await
+    .context("failed to connect to server")?;
+  info!(conn_id = conn.stable_id(), remote:% = addr; "connected to server");
+  Ok(conn)
+}
+
+/// Completes the handshake of `incoming`, counting the connection in
+/// `metrics` and tracing it to `qlog`.
+pub async fn accept(incoming: Incoming, qlog: &Qlog, metrics: &Arc<Metrics>) -> Result<(Connection, QlogTrace)> {
+  let started = Instant::now();
+  let conn = incoming.await.inspect_err(|e| metrics.refused(Some(e)))?;
+  metrics.accepted(&conn, started.elapsed());
+  info!(conn_id = conn.stable_id(), remote:% = conn.remote_address(); "established connection");
+  let trace = qlog.trace(&conn);
+  Ok((conn, trace))
+}
+
+/// The next bidirectional stream the client opens on `conn`. Once `metrics`
+/// is draining, new streams are turned away with [`AppError::ShuttingDown`].
+pub async fn accept_bi(conn: &Connection, metrics: &Metrics) -> Result<(SendStream, RecvStream), ConnectionError> {
+  loop {
+    let (mut send, mut recv) = conn.accept_bi().await?;
+    if !metrics.draining() {
+      return Ok((send, recv));
+    }
+    let _ = send.reset(AppError::ShuttingDown.code());
+    refuse_stream(conn, &mut recv);
+  }
+}
+
+/// The next unidirectional stream the client opens on `conn`, see [`accept_bi`].
+pub async fn accept_uni(conn: &Connection, metrics: &Metrics) -> Result<RecvStream, ConnectionError> {
+  loop {
+    let mut recv = conn.accept_uni().await?;
+    if !metrics.draining() {
+      return Ok(recv);
+    }
+    refuse_stream(conn, &mut recv);
+  }
+}
+
+fn refuse_stream(conn: &Connection, recv: &mut RecvStream) {
+  let _ = recv.stop(AppError::ShuttingDown.code());
+  // one per stream a client opens, too many for info
+  debug!(conn_id = conn.stable_id(), stream:% = recv.id(); "🚫 refusing stream, shutting down");
+}
+
+// hands every connection `admission` lets in to `handle_conn` in its own task,
+// until the endpoint closes
+async fn serve<F, Fut>(endpoint: &Endpoint, admission: &Arc<Admission>, metrics: &Metrics, handle_conn: F)
+where
+  F: Fn(Incoming) -> Fut,
+  Fut: Future<Output = Result<()>> + Send + 'static,
+{
+  while let Some((incoming, slot)) = admission.accept(endpoint, metrics).await {
+    let remote = incoming.remote_address();
+    info!(remote:%; "accepting connection");
+    spawn_logged(format!("connection from {}", remote), slot.hold(handle_conn(incoming)));
+  }
+}
+
+// a client closing the connection ends it normally
+fn closed(conn_id: usize, e: ConnectionError) -> Result<()> {
+  match e {
+    ConnectionError::ApplicationClosed { .. } => {
+      info!(conn_id; "connection closed");
+      Ok(())
+    }
+    e => Err(e.into()),
+  }
+}
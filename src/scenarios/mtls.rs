@@ -0,0 +1,265 @@
+use std::{fs, path::{Path, PathBuf}, sync::Arc};
+use anyhow::{Context, Result, anyhow};
+use log::info;
+use quinn::{Endpoint, Incoming};
+use rcgen::{
+  BasicConstraints, Certificate, CertificateParams, CertificateRevocationListParams, DnType, IsCa,
+  KeyIdMethod, KeyPair, KeyUsagePurpose, RevocationReason, RevokedCertParams, SerialNumber,
+};
+use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
+use time::{Duration, OffsetDateTime};
+use x509_parser::{
+  certificate::X509Certificate, prelude::FromDer, revocation_list::CertificateRevocationList,
+};
+
+use crate::{
+  admission::Admission,
+  acl::Acl,
+  certs,
+  files,
+  identity::ClientIdentity,
+  keylog,
+  logging::spawn_logged,
+  metrics::Metrics,
+  qlog::Qlog,
+  revocation::{ReloadingClientVerifier, RevocationPolicy},
+};
+
+pub const SERVER_CERT: &str = "server_cert.der";
+pub const SERVER_KEY: &str = "server_key.der";
+pub const CA_CERT: &str = "ca_cert.der";
+pub const CA_KEY: &str = "ca_key.der";
+pub const CLIENT_CERT: &str = "client_cert.der";
+pub const CLIENT_KEY: &str = "client_key.der";
+pub const CRL: &str = "client.crl";
+const CA_CN: &str = "quinn-client-ca";
+const CLIENT_CN: &str = "quinn-client";
+const CLIENT_SAN: &str = "client.localhost";
+const CRL_VALIDITY_DAYS: i64 = 7;
+
+/// Writes a self-signed server certificate, a client CA, a client certificate
+/// it issued and an empty CRL into `cert_dir`, returning the client's identity.
+pub fn generate(cert_dir: &Path) -> Result<ClientIdentity> {
+  fs::create_dir_all(cert_dir).context("failed to create cert_dir")?;
+
+  let cert_key = rcgen::generate_simple_self_signed(vec!["localhost".into()])?;
+  let priv_key = PrivatePkcs8KeyDer::from(cert_key.key_pair.serialize_der());
+  let cert: CertificateDer<'_> = cert_key.cert.into();
+
+  fs::write(cert_dir.join(SERVER_CERT), &cert).context("failed to write certificate")?;
+  fs::write(cert_dir.join(SERVER_KEY), priv_key.secret_pkcs8_der()).context("failed to write private key")?;
+
+  // client certs are issued by a CA so they can be revoked without
+  // touching the server's trust roots
+  let ca_key = KeyPair::generate()?;
+  let ca_cert = ca_params()?.self_signed(&ca_key)?;
+  let ca_priv_key = PrivatePkcs8KeyDer::from(ca_key.serialize_der());
+
+  fs::write(cert_dir.join(CA_CERT), ca_cert.der()).context("failed to write certificate")?;
+  fs::write(cert_dir.join(CA_KEY), ca_priv_key.secret_pkcs8_der()).context("failed to write private key")?;
+
+  // the subject and SANs are what the server's ACL matches on
+  let mut params = CertificateParams::new(vec![CLIENT_SAN.into()])?;
+  params.distinguished_name.push(DnType::CommonName, CLIENT_CN);
+  let key_pair = KeyPair::generate()?;
+  let cert: CertificateDer<'_> = params.signed_by(&key_pair, &ca_cert, &ca_key)?.into();
+  let priv_key = PrivatePkcs8KeyDer::from(key_pair.serialize_der());
+  let identity = ClientIdentity::from_der(&cert)?;
+
+  fs::write(cert_dir.join(CLIENT_CERT), &cert).context("failed to write certificate")?;
+  fs::write(cert_dir.join(CLIENT_KEY), priv_key.secret_pkcs8_der()).context("failed to write private key")?;
+
+  write_crl(&cert_dir.join(CRL), &ca_cert, &ca_key, 1, Vec::new())?;
+  Ok(identity)
+}
+
+/// What [`revoke`] did to the CRL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Revocation {
+  /// the certificate was on the CRL already, which is left as it is
+  AlreadyRevoked { subject: String },
+  /// the CRL was replaced by one with a new number, listing `count` certificates
+  Revoked { subject: String, serial: String, crl_number: u64, count: usize },
+}
+
+/// Adds the certificate at `cert_path` to the CRL in `cert_dir`, keeping
+/// everything the CRL already revokes.
+pub fn revoke(cert_dir: &Path, cert_path: &Path) -> Result<Revocation> {
+  let cert = fs::read(cert_path).context("failed to read certificate to revoke")?;
+  let (_, cert) = X509Certificate::from_der(&cert).map_err(|e| anyhow!("invalid certificate: {}", e))?;
+
+  // signing a CRL only needs the CA's name and key, so the CA cert is rebuilt from them
+  let ca_key = fs::read(cert_dir.join(CA_KEY)).context("failed to read CA key")?;
+  let ca_key = KeyPair::try_from(ca_key.as_slice())?;
+  let ca_cert = ca_params()?.self_signed(&ca_key)?;
+
+  let crl_path = cert_dir.join(CRL);
+  let crl = fs::read(&crl_path).context("failed to read CRL")?;
+  let (_, crl) = CertificateRevocationList::from_der(&crl).map_err(|e| anyhow!("invalid CRL: {}", e))?;
+  if crl.iter_revoked_certificates().any(|entry| entry.raw_serial() == cert.raw_serial()) {
+    return Ok(Revocation::AlreadyRevoked { subject: cert.subject().to_string() });
+  }
+  let crl_number = crl.crl_number().and_then(|n| u64::try_from(n).ok()).unwrap_or(0) + 1;
+  let mut revoked: Vec<RevokedCertParams> = crl
+    .iter_revoked_certificates()
+    .map(|entry| RevokedCertParams {
+      serial_number: SerialNumber::from_slice(entry.raw_serial()),
+      revocation_time: entry.revocation_date.to_datetime(),
+      reason_code: entry.reason_code().map(|(_, code)| reason(code.0)),
+      invalidity_date: None,
+    })
+    .collect();
+  revoked.push(RevokedCertParams {
+    serial_number: SerialNumber::from_slice(cert.raw_serial()),
+    revocation_time: OffsetDateTime::now_utc(),
+    reason_code: Some(RevocationReason::KeyCompromise),
+    invalidity_date: None,
+  });
+  let count = revoked.len();
+  write_crl(&crl_path, &ca_cert, &ca_key, crl_number, revoked)?;
+  Ok(Revocation::Revoked {
+    subject: cert.subject().to_string(),
+    serial: cert.raw_serial_as_string(),
+    crl_number,
+    count,
+  })
+}
+
+fn reason(code: u8) -> RevocationReason {
+  match code {
+    1 => RevocationReason::KeyCompromise,
+    2 => RevocationReason::CaCompromise,
+    3 => RevocationReason::AffiliationChanged,
+    4 => RevocationReason::Superseded,
+    5 => RevocationReason::CessationOfOperation,
+    6 => RevocationReason::CertificateHold,
+    8 => RevocationReason::RemoveFromCrl,
+    9 => RevocationReason::PrivilegeWithdrawn,
+    10 => RevocationReason::AaCompromise,
+    _ => RevocationReason::Unspecified,
+  }
+}
+
+fn ca_params() -> Result<CertificateParams> {
+  let mut params = CertificateParams::new(Vec::new())?;
+  params.distinguished_name.push(DnType::CommonName, CA_CN);
+  params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+  params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+  params.key_identifier_method = KeyIdMethod::Sha256;
+  Ok(params)
+}
+
+fn write_crl(
+  path: &Path,
+  ca_cert: &Certificate,
+  ca_key: &KeyPair,
+  crl_number: u64,
+  revoked_certs: Vec<RevokedCertParams>,
+) -> Result<()> {
+  let now = OffsetDateTime::now_utc();
+  let crl = CertificateRevocationListParams {
+    this_update: now,
+    next_update: now + Duration::days(CRL_VALIDITY_DAYS),
+    crl_number: SerialNumber::from(crl_number),
+    issuing_distribution_point: None,
+    revoked_certs,
+    key_identifier_method: KeyIdMethod::Sha256,
+  }.signed_by(ca_cert, ca_key)?;
+  // write then rename so a server polling the file never reads half a CRL
+  let part = path.with_file_name(format!(".{}.part", path.file_name().unwrap().to_string_lossy()));
+  fs::write(&part, crl.der()).context("failed to write CRL")?;
+  fs::rename(&part, path).context("failed to write CRL")?;
+  Ok(())
+}
+
+/// Requires client certificates issued by the CA in `cert_dir` and not revoked
+/// by the CRLs at `crl_path`, unless `allow_unauthenticated` lets clients
+/// without a certificate in. Watch the verifier to pick up new CRLs.
+pub fn server_tls(
+  cert_dir: &Path,
+  crl_path: &Path,
+  policy: RevocationPolicy,
+  allow_unauthenticated: bool,
+) -> Result<(rustls::ServerConfig, Arc<ReloadingClientVerifier>)> {
+  let client_cert_roots = certs::load_roots(&cert_dir.join(CA_CERT))?;
+  let chain = certs::load_chain(&cert_dir.join(SERVER_CERT))?;
+  let key = certs::load_key(&cert_dir.join(SERVER_KEY))?;
+
+  let mut verifier = ReloadingClientVerifier::new(client_cert_roots.into(), crl_path, policy)?;
+  if allow_unauthenticated {
+    verifier = verifier.allow_unauthenticated();
+  }
+  let verifier = Arc::new(verifier);
+
+  let mut tls_config = rustls::ServerConfig::builder()
+    .with_client_cert_verifier(verifier.clone())
+    .with_single_cert(chain, key)?;
+  tls_config.key_log = keylog::from_env();
+  Ok((tls_config, verifier))
+}
+
+/// Trusts the server certificate in `cert_dir` and, when `authenticated`,
+/// presents the client certificate from it.
+pub fn client_tls(cert_dir: &Path, authenticated: bool) -> Result<rustls::ClientConfig> {
+  let roots = certs::load_roots(&cert_dir.join(SERVER_CERT))?;
+  let builder = rustls::ClientConfig::builder().with_root_certificates(roots);
+  let mut tls_config = if authenticated {
+    let chain = certs::load_chain(&cert_dir.join(CLIENT_CERT))?;
+    let key = certs::load_key(&cert_dir.join(CLIENT_KEY))?;
+    builder.with_client_auth_cert(chain, key)?
+  } else {
+    builder.with_no_client_auth()
+  };
+  tls_config.key_log = keylog::from_env();
+  Ok(tls_config)
+}
+
+/// Answers `GET <filename>\r\n` from `root` for the files `acl` allows the
+/// client's identity, and with `🔒 ACCESS DENIED` for the others, until the
+/// endpoint closes.
+pub async fn serve(endpoint: Endpoint, admission: Arc<Admission>, root: PathBuf, acl: Arc<Acl>, qlog: Qlog, metrics: Arc<Metrics>) {
+  let root: Arc<Path> = root.into();
+  super::serve(&endpoint, &admission, &metrics, |incoming| {
+    handle_conn(incoming, root.clone(), acl.clone(), qlog.clone(), metrics.clone())
+  }).await
+}
+
+async fn handle_conn(
+  incoming: Incoming,
+  root: Arc<Path>,
+  acl: Arc<Acl>,
+  qlog: Qlog,
+  metrics: Arc<Metrics>,
+) -> Result<()> {
+  let (conn, trace) = super::accept(incoming, &qlog, &metrics)
+    .await
+    .context("failed to accept incoming connection")?;
+  let conn_id = conn.stable_id();
+  let identity = ClientIdentity::from_connection(&conn)?;
+  match &identity {
+    Some(identity) => info!(conn_id, identity:%; "🪪 client identity"),
+    None => info!(conn_id; "👤 anonymous client"),
+  }
+  let identity = Arc::new(identity);
+  loop {
+    let stream = match super::accept_bi(&conn, &metrics).await {
+      Ok(stream) => stream,
+      Err(e) => return super::closed(conn_id, e),
+    };
+    let (identity, acl, root) = (identity.clone(), acl.clone(), root.clone());
+    let id = stream.0.id();
+    spawn_logged(format!("stream on connection {}", conn_id), metrics.stream(trace.stream(id, async move {
+      files::respond(conn_id, stream, |req| handle_req(req, identity.as_ref().as_ref(), &acl, &root)).await
+    })));
+  }
+}
+
+fn handle_req(req: &[u8], identity: Option<&ClientIdentity>, acl: &Acl, root: &Path) -> Result<Vec<u8>> {
+  let filename = files::parse_get(req)?;
+  if !acl.allows(identity, filename) {
+    let who = identity.map_or("anonymous client", |identity| &identity.subject);
+    info!(who, filename; "🚫 access denied");
+    return Ok("🔒 ACCESS DENIED\n".as_bytes().to_vec());
+  }
+  files::read(root, req)
+}
@@ -0,0 +1,179 @@
+use std::{fs, path::{Path, PathBuf}, str, sync::Arc, time::Duration};
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use log::{debug, info, warn};
+use quinn::{Connection, ConnectionError, Endpoint, Incoming, RecvStream};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+use crate::{
+  admission::Admission,
+  files,
+  logging::spawn_logged,
+  metrics::Metrics,
+  push::{FileWatcher, Notification, PushListener},
+  qlog::Qlog,
+  upload::{Confirmation, UploadService},
+};
+
+/// Handles bidirectional streams, unidirectional streams and datagrams of
+/// each connection at once, until the endpoint closes:
+///
+/// - `GET <filename>\r\n` on a bidirectional stream is answered from `root`,
+///   and the client is told with a `CHANGED` notification when the file changes
+/// - a unidirectional stream uploads a file to `uploads`, confirmed with
+///   `UPLOADED` or `REJECTED`
+/// - a datagram is answered with a hello
+pub async fn serve(
+  endpoint: Endpoint,
+  admission: Arc<Admission>,
+  root: PathBuf,
+  uploads: Arc<UploadService>,
+  watcher: Arc<FileWatcher>,
+  qlog: Qlog,
+  metrics: Arc<Metrics>,
+) {
+  let root: Arc<Path> = root.into();
+  super::serve(&endpoint, &admission, &metrics, |incoming| {
+    handle_conn(incoming, root.clone(), uploads.clone(), watcher.clone(), qlog.clone(), metrics.clone())
+  }).await
+}
+
+async fn handle_conn(
+  incoming: Incoming,
+  root: Arc<Path>,
+  uploads: Arc<UploadService>,
+  watcher: Arc<FileWatcher>,
+  qlog: Qlog,
+  metrics: Arc<Metrics>,
+) -> Result<()> {
+  let (conn, trace) = super::accept(incoming, &qlog, &metrics).await?;
+  let conn_id = conn.stable_id();
+  loop {
+    tokio::select! {
+      result = super::accept_bi(&conn, &metrics) => {
+        match result {
+          Err(e) => {
+            return handle_error(conn_id, e);
+          }
+          Ok(stream) => {
+            let id = stream.0.id();
+            debug!(conn_id, stream:% = id; "accepting bidirectional stream...");
+            let (conn, root, watcher) = (conn.clone(), root.clone(), watcher.clone());
+            let task = trace.stream(id, async move {
+              files::respond(conn_id, stream, |req| {
+                let path = files::resolve(&root, req)?;
+                let bytes = fs::read(&path).context("failed reading file")?;
+                watcher.subscribe(&conn, &path);
+                Ok(bytes)
+              }).await
+            });
+            spawn_logged(format!("bidirectional stream on connection {}", conn_id), metrics.stream(task));
+          }
+        }
+      }
+
+      result = super::accept_uni(&conn, &metrics) => {
+        match result {
+          Err(e) => {
+            return handle_error(conn_id, e);
+          }
+          Ok(recv) => {
+            debug!(conn_id, stream:% = recv.id(); "accepting unidirectional stream...");
+            let conn = conn.clone();
+            let uploads = uploads.clone();
+            let id = recv.id();
+            let task = trace.stream(id, async move {
+              handle_uni_stream(&conn, recv, &uploads).await
+            });
+            spawn_logged(format!("unidirectional stream on connection {}", conn_id), metrics.stream(task));
+          }
+        }
+      }
+
+      result = conn.read_datagram() => {
+        match result {
+          Err(e) => {
+            return handle_error(conn_id, e);
+          }
+          Ok(msg) => {
+            debug!(conn_id; "accepting datagram from client...");
+            if let Err(e) = handle_datagram(&conn, msg) {
+              warn!(conn_id; "datagram error: {:#}", e);
+            }
+          }
+        }
+      }
+    }
+  }
+}
+
+async fn handle_uni_stream(conn: &Connection, recv: RecvStream, uploads: &UploadService) -> Result<()> {
+  let (conn_id, stream) = (conn.stable_id(), recv.id());
+  let confirmation = uploads
+    .handle_uni_stream(conn, recv)
+    .await
+    .context("failed handling upload")?;
+  match confirmation {
+    Confirmation::Uploaded { .. } => info!(conn_id, stream:%; "✅ {}", confirmation),
+    Confirmation::Rejected { .. } => warn!(conn_id, stream:%; "🚫 {}", confirmation),
+  }
+  debug!(conn_id, stream:%; "Done handle uni_stream!");
+  Ok(())
+}
+
+fn handle_error(conn_id: usize, e: ConnectionError) -> Result<()> {
+  if let ConnectionError::TimedOut = e {
+    info!(conn_id; "timeout waiting, drop connection");
+    return Ok(());
+  }
+  super::closed(conn_id, e)
+}
+
+fn handle_datagram(conn: &Connection, msg: Bytes) -> Result<()> {
+  let msg = str::from_utf8(&msg)?;
+  let conn_id = conn.stable_id();
+  info!(conn_id; "received datagram: {}", msg);
+  debug!(conn_id; "sending datagram to client...");
+  conn.send_datagram(b"Hello from server"[..].into()).context("failed to send datagram response")?;
+  debug!(conn_id; "Done respond to datagram!");
+  Ok(())
+}
+
+/// The notifications the server pushes to a client, on streams it opens.
+#[derive(Debug)]
+pub struct Pushed {
+  confirmations: UnboundedReceiver<Notification>,
+  changes: UnboundedReceiver<Notification>,
+}
+
+impl Pushed {
+  /// Listens for notifications on `conn` until it closes.
+  pub fn listen(conn: &Connection) -> Self {
+    // every stream the server opens is a notification, confirmations included
+    let (confirmations_tx, confirmations) = mpsc::unbounded_channel();
+    let (changes_tx, changes) = mpsc::unbounded_channel();
+    let listener = PushListener::new()
+      .on("UPLOADED", {
+        let tx = confirmations_tx.clone();
+        move |n| { let _ = tx.send(n.clone()); }
+      })
+      .on("REJECTED", move |n| { let _ = confirmations_tx.send(n.clone()); })
+      .on("CHANGED", move |n| { let _ = changes_tx.send(n.clone()); });
+    spawn_logged("push listener", listener.run(conn.clone()));
+    Self { confirmations, changes }
+  }
+
+  /// The next upload confirmation, in the order the uploads finished.
+  pub async fn confirmation(&mut self) -> Result<Confirmation> {
+    let notification = self.confirmations.recv().await.context("connection closed before confirmation")?;
+    Confirmation::try_from(&notification)
+  }
+
+  /// Waits up to `timeout` for a file the client fetched to change.
+  pub async fn change(&mut self, timeout: Duration) -> Result<Notification> {
+    tokio::time::timeout(timeout, self.changes.recv())
+      .await
+      .context("no change notification pushed by server")?
+      .context("connection closed before change notification")
+  }
+}
@@ -0,0 +1,259 @@
+use std::{path::{Path, PathBuf}, str, sync::Arc};
+use anyhow::{Context, Result, anyhow, bail};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use log::{debug, info, warn};
+use quinn::{Connection, Endpoint, Incoming, ReadError, RecvStream, SendStream};
+use rand::{rngs::OsRng, TryRngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+  admission::Admission,
+  files,
+  logging::spawn_logged,
+  metrics::Metrics,
+  qlog::Qlog,
+  wire::{self, escape, read_line},
+};
+
+pub const ADMIN_USERNAME: &str = "admin";
+const ADMIN_PWD_HASH: &str = "bUUlwqIfm+HMqeQfOqQC4HZe5fzD5/6jShabFzCuOG4=";
+const AUTH_BEARER_HEADER: &[u8] = b"Authentication Bearer ";
+const MAX_LINE: usize = 64 * 1024;
+const MAX_RESPONSE_SIZE: usize = 64 * 1024;
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Login {
+  pub username: String,
+  pub password: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+  pub token: String,
+  pub signature: String,
+}
+
+/// Logs clients in on each new stream, then answers `GET <filename>` from
+/// `root` for every request carrying the session token, until the endpoint
+/// closes.
+///
+/// Tokens are signed with keying material exported from the connection's TLS
+/// session, so a token only verifies on the connection it was issued on.
+pub async fn serve(endpoint: Endpoint, admission: Arc<Admission>, root: PathBuf, qlog: Qlog, metrics: Arc<Metrics>) {
+  let root: Arc<Path> = root.into();
+  super::serve(&endpoint, &admission, &metrics, |incoming| {
+    handle_conn(incoming, root.clone(), qlog.clone(), metrics.clone())
+  }).await
+}
+
+async fn handle_conn(incoming: Incoming, root: Arc<Path>, qlog: Qlog, metrics: Arc<Metrics>) -> Result<()> {
+  let (conn, trace) = super::accept(incoming, &qlog, &metrics).await?;
+  let conn_id = conn.stable_id();
+  let mut key: [u8; 32] = [0u8; 32];
+  conn
+    .export_keying_material(&mut key, b"token-binding", b"")
+    .map_err(|_| anyhow!("failed to export keying material"))?;
+
+  loop {
+    let (mut send, mut recv) = match super::accept_bi(&conn, &metrics).await {
+      Ok(stream) => stream,
+      Err(e) => return super::closed(conn_id, e),
+    };
+    let id = send.id();
+    let (root, counters) = (root.clone(), metrics.clone());
+    spawn_logged(format!("stream on connection {}", conn_id), metrics.stream(trace.stream(id, async move {
+      handle_stream(conn_id, &mut send, &mut recv, &key, &root, &counters).await
+    })));
+  }
+}
+
+async fn handle_stream(
+  conn_id: usize,
+  send: &mut SendStream,
+  recv: &mut RecvStream,
+  key: &[u8],
+  root: &Path,
+  metrics: &Metrics,
+) -> Result<()> {
+  let stream = send.id();
+  match auth(send, recv, key).await {
+    Ok(_) => info!(conn_id, stream:%; "✅ AUTH SUCCESS, session established and token sent to client"),
+    Err(e) => {
+      warn!(conn_id, stream:%; "🚫 authentication failed: {:#}", e);
+      metrics.auth_failure();
+      send.write_all("🔒 AUTH ERROR: authentication failed\n".as_bytes()).await?;
+      return Err(e);
+    }
+  }
+
+  // check session token for every new request
+  while let Some(line) = next_line(recv).await? {
+    if let Err(e) = verify_session(line, key) {
+      warn!(conn_id, stream:%; "❌ session verification failed: {:#}", e);
+      metrics.auth_failure();
+      return Err(e);
+    }
+    info!(conn_id, stream:%; "✅ session verified");
+
+    let mut req = read_line(recv, MAX_LINE).await.context("failed reading request")?;
+    let escaped = escape(&req);
+    info!(conn_id, stream:%; "req {}", escaped);
+    req.extend_from_slice(b"\r\n");
+    let resp = files::read(root, &req).unwrap_or_else(|e| {
+      warn!(conn_id, stream:%; "handle request failed: {}", e);
+      String::from("failed to handle request").into_bytes()
+    });
+    send.write_all(&resp).await.context("failed to send response")?;
+    debug!(conn_id, stream:%; "done respond to request {}", escaped);
+  }
+  info!(conn_id, stream:%; "🛑 client closed, exiting...");
+  wire::finish(send).await?;
+  debug!(conn_id, stream:%; "complete stream handling!");
+  Ok(())
+}
+
+async fn auth(
+  send: &mut SendStream,
+  recv: &mut RecvStream,
+  key: &[u8],
+) -> Result<()> {
+  let req = read_line(recv, MAX_LINE).await.context("failed reading request")?;
+  // parse login request
+  let Some(path) = req.strip_prefix(b"POST ") else {
+    bail!("missing POST ");
+  };
+  if path != b"/login" {
+    bail!("missing /login");
+  }
+
+  // parse content length
+  let content_length = read_line(recv, MAX_LINE).await.context("failed reading content-length")?;
+  let content_length = str::from_utf8(&content_length)?.parse::<usize>()?;
+  if content_length > MAX_LINE {
+    bail!("login of {} bytes is too large", content_length);
+  }
+  // read login data
+  let mut body = vec![0; content_length];
+  recv.read_exact(&mut body).await.context("failed reading body")?;
+
+  // check login data
+  let login: Login = serde_json::from_slice(&body).context("failed to deserialize login")?;
+  if login.username != ADMIN_USERNAME || hash_pwd(&login.password) != ADMIN_PWD_HASH {
+    bail!("wrong username or password");
+  }
+  // generate session token and send to client
+  let session_bytes = gen_session(key)?;
+  send.write_all(&session_bytes).await.context("failed to send session bytes")?;
+  Ok(())
+}
+
+// `None` once the client finished the stream or closed the connection
+async fn next_line(recv: &mut RecvStream) -> Result<Option<Vec<u8>>> {
+  let mut first = [0u8; 1];
+  match recv.read(&mut first).await {
+    Ok(Some(_)) => {}
+    Ok(None) | Err(ReadError::ConnectionLost(_)) => return Ok(None),
+    Err(e) => return Err(e.into()),
+  }
+  let mut line = first.to_vec();
+  line.extend(read_line(recv, MAX_LINE).await?);
+  Ok(Some(line))
+}
+
+fn verify_session(line: Vec<u8>, key: &[u8]) -> Result<()> {
+  // parse session header
+  let Some(session) = line.strip_prefix(AUTH_BEARER_HEADER) else {
+    bail!("missing Authentication Bearer ");
+  };
+  // deserialize session data
+  let session: Session = serde_json::from_slice(session)?;
+  // check session signature, in constant time
+  let sig = BASE64_STANDARD.decode(&session.signature).context("malformed signature")?;
+  let mut m = HmacSha256::new_from_slice(key).context("failed to create hmac from key")?;
+  m.update(session.token.as_bytes());
+  m.verify_slice(&sig).map_err(|_| anyhow!("wrong signature"))
+}
+
+fn hash_pwd(pwd: &str) -> String {
+  let mut hasher = Sha256::new();
+  hasher.update(pwd.as_bytes());
+  let checksum = hasher.finalize();
+  BASE64_STANDARD.encode(checksum)
+}
+
+fn sign_token(key: &[u8], token: &str) -> Result<String> {
+  let mut m = HmacSha256::new_from_slice(key).context("failed to create hmac from key")?;
+  m.update(token.as_bytes());
+  Ok(BASE64_STANDARD.encode(m.finalize().into_bytes()))
+}
+
+fn gen_session(key: &[u8]) -> Result<Vec<u8>> {
+  let mut token = [0u8; 32];
+  OsRng.try_fill_bytes(&mut token).context("failed to generate session token")?;
+
+  let token = BASE64_STANDARD.encode(token);
+  let signature = sign_token(key, &token)?;
+
+  let session = Session{token, signature};
+  let json_bytes = serde_json::to_vec(&session)?;
+  Ok(json_bytes)
+}
+
+/// The client end of a stream to the session server: log in once, then send
+/// any number of requests presenting the session.
+#[derive(Debug)]
+pub struct SessionStream {
+  send: SendStream,
+  recv: RecvStream,
+}
+
+impl SessionStream {
+  pub async fn open(conn: &Connection) -> Result<Self> {
+    let (send, recv) = conn.open_bi().await.context("failed to open stream")?;
+    Ok(Self { send, recv })
+  }
+
+  /// Logs in and returns the session the server issued.
+  pub async fn login(&mut self, login: &Login) -> Result<Session> {
+    let login = serde_json::to_string(login)?;
+    let req = format!("POST /login\r\n{}\r\n{}", login.len(), login);
+    self.send.write_all(req.as_bytes())
+      .await
+      .context("failed to send request")?;
+    let resp = self.read_response().await?;
+    serde_json::from_slice(&resp)
+      .with_context(|| format!("login refused: {}", String::from_utf8_lossy(&resp).trim_end()))
+  }
+
+  /// Requests `filename`, presenting `session` as the bearer token.
+  pub async fn get(&mut self, session: &Session, filename: &str) -> Result<Vec<u8>> {
+    let req = format!(
+      "Authentication Bearer {}\r\nGET {}\r\n",
+      serde_json::to_string(session)?,
+      filename,
+    );
+    self.send.write_all(req.as_bytes())
+      .await
+      .context("failed to send request")?;
+    self.read_response().await
+  }
+
+  pub fn finish(&mut self) -> Result<()> {
+    Ok(self.send.finish()?)
+  }
+
+  // responses are not framed, one read returns what the server wrote for a small file
+  async fn read_response(&mut self) -> Result<Vec<u8>> {
+    let mut buf = vec![0u8; MAX_RESPONSE_SIZE];
+    let n = self.recv
+      .read(&mut buf)
+      .await
+      .context("failed reading response")?
+      .context("server closed the stream")?;
+    buf.truncate(n);
+    Ok(buf)
+  }
+}
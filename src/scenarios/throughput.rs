@@ -0,0 +1,353 @@
+//! Bulk transfer for measuring goodput, in the spirit of iperf. Each
+//! bidirectional stream carries one transfer:
+//! - `UPLOAD\r\n` and data until the client finishes, answered with
+//!   `<bytes received>\r\n`
+//! - `DOWNLOAD <millis>\r\n`, answered with data for that long
+//! - `STATS\r\n`, answered with the server's path statistics, so the client
+//!   can report on the sender when it downloads
+
+use std::{
+  convert::Infallible,
+  fmt,
+  str::{self, FromStr},
+  sync::{atomic::{AtomicU64, Ordering}, Arc},
+  time::{Duration, Instant},
+};
+use anyhow::{Context, Result, anyhow, bail};
+use log::info;
+use quinn::{Connection, Endpoint, Incoming, RecvStream, SendStream, WriteError};
+use tokio::{task::JoinSet, time::timeout_at};
+
+use crate::{admission::Admission, logging::spawn_logged, metrics::Metrics, qlog::Qlog, wire::{self, read_line}};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const MAX_LINE_SIZE: usize = 256;
+static ZEROS: [u8; CHUNK_SIZE] = [0; CHUNK_SIZE];
+
+/// Answers transfers on every bidirectional stream until the endpoint closes.
+pub async fn serve(endpoint: Endpoint, admission: Arc<Admission>, qlog: Qlog, metrics: Arc<Metrics>) {
+  super::serve(&endpoint, &admission, &metrics, |incoming| handle_conn(incoming, qlog.clone(), metrics.clone())).await
+}
+
+async fn handle_conn(incoming: Incoming, qlog: Qlog, metrics: Arc<Metrics>) -> Result<()> {
+  let (conn, trace) = super::accept(incoming, &qlog, &metrics).await?;
+  let conn_id = conn.stable_id();
+  loop {
+    let (send, recv) = match super::accept_bi(&conn, &metrics).await {
+      Ok(stream) => stream,
+      Err(e) => return super::closed(conn_id, e),
+    };
+    let task = trace.stream(send.id(), handle_stream(conn.clone(), send, recv));
+    spawn_logged(format!("stream on connection {}", conn_id), metrics.stream(task));
+  }
+}
+
+async fn handle_stream(conn: Connection, mut send: SendStream, mut recv: RecvStream) -> Result<()> {
+  let (conn_id, stream) = (conn.stable_id(), send.id());
+  let line = read_line(&mut recv, MAX_LINE_SIZE).await.context("failed reading request")?;
+  let line = str::from_utf8(&line).context("request is malformed UTF-8")?;
+  match line.split_once(' ').unwrap_or((line, "")) {
+    ("UPLOAD", "") => {
+      let mut received = 0u64;
+      while let Some(chunk) = recv.read_chunk(usize::MAX, false).await? {
+        received += chunk.bytes.len() as u64;
+      }
+      info!(conn_id, stream:%, bytes = received; "⬆️  upload done");
+      send.write_all(format!("{}\r\n", received).as_bytes()).await?;
+    }
+    ("DOWNLOAD", millis) => {
+      let duration = Duration::from_millis(millis.parse().context("invalid duration")?);
+      let sent = send_for(&mut send, duration, &AtomicU64::new(0)).await?;
+      info!(conn_id, stream:%, bytes = sent; "⬇️  download done");
+    }
+    ("STATS", "") => {
+      send.write_all(format!("{}\r\n", SenderStats::of(&conn)).as_bytes()).await?;
+    }
+    _ => bail!("unknown request {:?}", line),
+  }
+  wire::finish(&mut send).await?;
+  Ok(())
+}
+
+// writes zeros to `send` for `duration`, counting them in `moved` as they go
+async fn send_for(send: &mut SendStream, duration: Duration, moved: &AtomicU64) -> Result<u64> {
+  let deadline = Instant::now() + duration;
+  let mut sent = 0u64;
+  // writes are cancel-safe, a write cut short by the deadline wrote nothing
+  if let Ok(Err(e)) = timeout_at(deadline.into(), write_zeros(send, &mut sent, moved)).await {
+    return Err(e.into());
+  }
+  Ok(sent)
+}
+
+async fn write_zeros(send: &mut SendStream, sent: &mut u64, moved: &AtomicU64) -> Result<Infallible, WriteError> {
+  loop {
+    let written = send.write(&ZEROS).await? as u64;
+    *sent += written;
+    moved.fetch_add(written, Ordering::Relaxed);
+  }
+}
+
+/// Which way the data flows, seen from the client.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Direction {
+  #[default]
+  Upload,
+  Download,
+}
+
+impl FromStr for Direction {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s.to_ascii_lowercase().as_str() {
+      "upload" => Ok(Self::Upload),
+      "download" => Ok(Self::Download),
+      _ => Err(anyhow!("expected upload or download")),
+    }
+  }
+}
+
+impl fmt::Display for Direction {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Upload => "upload",
+      Self::Download => "download",
+    })
+  }
+}
+
+/// A transfer on `streams` streams at once for `duration`, reported every `interval`.
+#[derive(Debug, Clone)]
+pub struct Test {
+  pub direction: Direction,
+  pub streams: usize,
+  pub duration: Duration,
+  pub interval: Duration,
+}
+
+/// Path statistics of the side sending the data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SenderStats {
+  pub sent_packets: u64,
+  /// lost packets, their data is sent again
+  pub lost_packets: u64,
+  pub lost_bytes: u64,
+  pub congestion_events: u64,
+  pub rtt: Duration,
+  pub cwnd: u64,
+}
+
+impl SenderStats {
+  pub fn of(conn: &Connection) -> Self {
+    let path = conn.stats().path;
+    Self {
+      sent_packets: path.sent_packets,
+      lost_packets: path.lost_packets,
+      lost_bytes: path.lost_bytes,
+      congestion_events: path.congestion_events,
+      rtt: path.rtt,
+      cwnd: path.cwnd,
+    }
+  }
+}
+
+// the line the server answers STATS with
+impl fmt::Display for SenderStats {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{} {} {} {} {} {}",
+      self.sent_packets,
+      self.lost_packets,
+      self.lost_bytes,
+      self.congestion_events,
+      self.rtt.as_micros(),
+      self.cwnd,
+    )
+  }
+}
+
+impl FromStr for SenderStats {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let fields = s.split(' ').map(str::parse).collect::<Result<Vec<u64>, _>>().context("malformed stats")?;
+    let [sent_packets, lost_packets, lost_bytes, congestion_events, rtt_us, cwnd] = fields[..] else {
+      bail!("expected 6 stats, got {}", fields.len());
+    };
+    Ok(Self {
+      sent_packets,
+      lost_packets,
+      lost_bytes,
+      congestion_events,
+      rtt: Duration::from_micros(rtt_us),
+      cwnd,
+    })
+  }
+}
+
+/// Data moved during one interval, seen from the client. Loss and
+/// congestion window are only known when the client sends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+  pub direction: Direction,
+  pub start: Duration,
+  pub end: Duration,
+  /// bytes received when downloading. When uploading, bytes written into the
+  /// send buffer, some of which may not have reached the server yet
+  pub bytes: u64,
+  pub rtt: Duration,
+  pub lost_packets: Option<u64>,
+  pub cwnd: Option<u64>,
+}
+
+impl Interval {
+  pub fn goodput(&self) -> f64 {
+    bits_per_sec(self.bytes, self.end - self.start)
+  }
+}
+
+impl fmt::Display for Interval {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "[{:5.1}-{:5.1}s] {:9.2} MiB {:8} {:9.2} Mbit/s  rtt {:.2}ms",
+      self.start.as_secs_f64(),
+      self.end.as_secs_f64(),
+      mib(self.bytes),
+      match self.direction {
+        Direction::Upload => "sent",
+        Direction::Download => "received",
+      },
+      self.goodput() / 1e6,
+      self.rtt.as_secs_f64() * 1000.0,
+    )?;
+    if let (Some(lost_packets), Some(cwnd)) = (self.lost_packets, self.cwnd) {
+      write!(f, "  lost {}  cwnd {:.1} KiB", lost_packets, cwnd as f64 / 1024.0)?;
+    }
+    Ok(())
+  }
+}
+
+/// The whole transfer. Bytes are counted by the receiver.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+  pub direction: Direction,
+  pub bytes: u64,
+  pub elapsed: Duration,
+  pub sender: SenderStats,
+}
+
+impl Summary {
+  pub fn goodput(&self) -> f64 {
+    bits_per_sec(self.bytes, self.elapsed)
+  }
+}
+
+impl fmt::Display for Summary {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(
+      f,
+      "📊 {} {:.2} MiB in {:.2}s, {:.2} Mbit/s goodput",
+      self.direction,
+      mib(self.bytes),
+      self.elapsed.as_secs_f64(),
+      self.goodput() / 1e6,
+    )?;
+    write!(
+      f,
+      "🔁 sender lost {} of {} packets ({:.2} MiB) to retransmit, {} congestion events, rtt {:.2}ms, cwnd {:.1} KiB",
+      self.sender.lost_packets,
+      self.sender.sent_packets,
+      mib(self.sender.lost_bytes),
+      self.sender.congestion_events,
+      self.sender.rtt.as_secs_f64() * 1000.0,
+      self.sender.cwnd as f64 / 1024.0,
+    )
+  }
+}
+
+fn bits_per_sec(bytes: u64, elapsed: Duration) -> f64 {
+  bytes as f64 * 8.0 / elapsed.as_secs_f64().max(f64::EPSILON)
+}
+
+fn mib(bytes: u64) -> f64 {
+  bytes as f64 / (1024.0 * 1024.0)
+}
+
+/// Runs `test` on `conn`, handing each interval to `report` as it ends.
+pub async fn run(conn: &Connection, test: &Test, mut report: impl FnMut(&Interval)) -> Result<Summary> {
+  let moved = Arc::new(AtomicU64::new(0));
+  let started = Instant::now();
+  let mut transfers = JoinSet::new();
+  for _ in 0..test.streams {
+    transfers.spawn(transfer(conn.clone(), test.direction, test.duration, moved.clone()));
+  }
+
+  let mut ticker = tokio::time::interval_at((started + test.interval).into(), test.interval);
+  let (mut bytes, mut last_moved, mut last_end) = (0, 0, Duration::ZERO);
+  let mut last_lost = conn.stats().path.lost_packets;
+  loop {
+    tokio::select! {
+      joined = transfers.join_next() => match joined {
+        Some(joined) => bytes += joined??,
+        None => break,
+      },
+      _ = ticker.tick() => {
+        let (path, now_moved, end) = (conn.stats().path, moved.load(Ordering::Relaxed), started.elapsed());
+        let sending = test.direction == Direction::Upload;
+        report(&Interval {
+          direction: test.direction,
+          start: last_end,
+          end,
+          bytes: now_moved - last_moved,
+          rtt: path.rtt,
+          lost_packets: sending.then_some(path.lost_packets - last_lost),
+          cwnd: sending.then_some(path.cwnd),
+        });
+        (last_moved, last_end, last_lost) = (now_moved, end, path.lost_packets);
+      }
+    }
+  }
+  let elapsed = started.elapsed();
+
+  let sender = match test.direction {
+    Direction::Upload => SenderStats::of(conn),
+    Direction::Download => server_stats(conn).await?,
+  };
+  Ok(Summary { direction: test.direction, bytes, elapsed, sender })
+}
+
+// one stream's transfer, returning the bytes the receiver got
+async fn transfer(conn: Connection, direction: Direction, duration: Duration, moved: Arc<AtomicU64>) -> Result<u64> {
+  let (mut send, mut recv) = conn.open_bi().await.context("failed to open stream")?;
+  match direction {
+    Direction::Upload => {
+      send.write_all(b"UPLOAD\r\n").await?;
+      send_for(&mut send, duration, &moved).await?;
+      send.finish()?;
+      let line = read_line(&mut recv, MAX_LINE_SIZE).await.context("failed reading upload result")?;
+      Ok(str::from_utf8(&line)?.parse().context("malformed upload result")?)
+    }
+    Direction::Download => {
+      send.write_all(format!("DOWNLOAD {}\r\n", duration.as_millis()).as_bytes()).await?;
+      send.finish()?;
+      let mut received = 0u64;
+      while let Some(chunk) = recv.read_chunk(usize::MAX, false).await? {
+        received += chunk.bytes.len() as u64;
+        moved.fetch_add(chunk.bytes.len() as u64, Ordering::Relaxed);
+      }
+      Ok(received)
+    }
+  }
+}
+
+/// The server's path statistics, the sender's when downloading.
+pub async fn server_stats(conn: &Connection) -> Result<SenderStats> {
+  let (mut send, mut recv) = conn.open_bi().await.context("failed to open stream")?;
+  send.write_all(b"STATS\r\n").await?;
+  send.finish()?;
+  let line = read_line(&mut recv, MAX_LINE_SIZE).await.context("failed reading stats")?;
+  str::from_utf8(&line)?.parse()
+}
@@ -0,0 +1,344 @@
+use std::{
+  collections::{HashMap, VecDeque}, net::SocketAddr, path::{Path, PathBuf}, sync::{Arc, Mutex}, time::{Duration, Instant},
+};
+use anyhow::{Context, Result, bail, anyhow};
+use log::{info, warn};
+use quinn::{Connection, Endpoint, Incoming, RecvStream, SendStream};
+use rustls::server::StoresServerSessions;
+use tokio::net::UdpSocket;
+
+use crate::{
+  admission::Admission,
+  anti_replay::{AntiReplayStore, MemoryStrikeRegister},
+  early_data::{EarlyClient, EarlyData, Routes, ServerZeroRtt, ZeroRtt, ZeroRttCounters},
+  files,
+  logging::spawn_logged,
+  metrics::Metrics,
+  qlog::{Qlog, QlogTrace},
+  tickets::KeyFileTicketer,
+  wire::{self, escape},
+};
+
+const STRIKE_REGISTER_CAPACITY: usize = 10_000;
+const SESSION_CACHE_CAPACITY: usize = 10_000;
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+const TICKET_KEY_RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How the server issues session tickets and whether it takes early data on them.
+#[derive(Debug, Clone)]
+pub struct Tickets {
+  /// stateless tickets encrypted with keys from this file, instead of a session cache
+  pub key_file: Option<PathBuf>,
+  /// how long tickets stay valid. rustls tells clients a stateful ticket
+  /// lasts a day whatever this is, the server forgets it after `lifetime`
+  pub lifetime: Duration,
+  pub early_data: bool,
+  /// redeem each stateful ticket once, see [`AntiReplayStore`]
+  pub anti_replay: bool,
+  /// keep a stateful ticket after it is redeemed, like a cache shared by
+  /// several servers that cannot delete it everywhere at once
+  pub replicated: bool,
+}
+
+/// The server's TLS config, serving `cert.der` and `key.der` from `cert_dir`
+/// and issuing tickets as `tickets` says.
+pub fn server_tls(cert_dir: &Path, tickets: &Tickets) -> Result<rustls::ServerConfig> {
+  let mut tls_config = super::server_tls(cert_dir)?;
+  // QUIC only allows 0 or u32::MAX
+  tls_config.max_early_data_size = if tickets.early_data { u32::MAX } else { 0 };
+  if let Some(key_file) = &tickets.key_file {
+    let ticketer = Arc::new(KeyFileTicketer::new(key_file, tickets.lifetime)?);
+    ticketer.watch(TICKET_KEY_RELOAD_INTERVAL);
+    tls_config.ticketer = ticketer;
+  } else {
+    let cache: Arc<dyn StoresServerSessions> =
+      Arc::new(SessionCache::new(SESSION_CACHE_CAPACITY, tickets.lifetime, tickets.replicated));
+    tls_config.session_storage = if tickets.anti_replay {
+      let register = Arc::new(MemoryStrikeRegister::new(STRIKE_REGISTER_CAPACITY));
+      Arc::new(AntiReplayStore::new(cache, register, tickets.lifetime))
+    } else {
+      cache
+    };
+  }
+  Ok(tls_config)
+}
+
+/// Trusts `cert.der` in `cert_dir` and sends early data when resuming.
+///
+/// Session tickets live in the config's in-memory store, so only clients
+/// sharing the config, e.g. through one endpoint, resume with 0-RTT.
+pub fn client_tls(cert_dir: &Path) -> Result<rustls::ClientConfig> {
+  let mut tls_config = super::client_tls(cert_dir)?;
+  tls_config.enable_early_data = true;
+  Ok(tls_config)
+}
+
+// holds up to `capacity` sessions for `lifetime`, dropping the oldest first.
+// A replicated cache stands in for one shared by several servers: taking a
+// ticket here does not remove the copies other servers already hold
+#[derive(Debug)]
+struct SessionCache {
+  capacity: usize,
+  lifetime: Duration,
+  replicated: bool,
+  sessions: Mutex<Sessions>,
+}
+
+#[derive(Debug, Default)]
+struct Sessions {
+  // when each session was stored, and the session
+  stored: HashMap<Vec<u8>, Stored>,
+  // keys in the order they were stored. Every session lives as long, so the
+  // front expires first
+  order: VecDeque<(Instant, Vec<u8>)>,
+}
+
+type Stored = (Instant, Vec<u8>);
+
+impl SessionCache {
+  fn new(capacity: usize, lifetime: Duration, replicated: bool) -> Self {
+    Self { capacity, lifetime, replicated, sessions: Mutex::default() }
+  }
+}
+
+impl StoresServerSessions for SessionCache {
+  fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+    let now = Instant::now();
+    let mut sessions = self.sessions.lock().unwrap();
+    while let Some((at, _)) = sessions.order.front() {
+      if sessions.order.len() < self.capacity && now.duration_since(*at) < self.lifetime {
+        break;
+      }
+      let (at, old) = sessions.order.pop_front().unwrap();
+      // the key may have been taken or stored again since
+      if sessions.stored.get(&old).is_some_and(|(stored, _)| *stored == at) {
+        sessions.stored.remove(&old);
+      }
+    }
+    sessions.order.push_back((now, key.clone()));
+    sessions.stored.insert(key, (now, value));
+    true
+  }
+
+  fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+    let sessions = self.sessions.lock().unwrap();
+    let (stored, value) = sessions.stored.get(key)?;
+    (stored.elapsed() < self.lifetime).then(|| value.clone())
+  }
+
+  fn take(&self, key: &[u8]) -> Option<Vec<u8>> {
+    if self.replicated {
+      return self.get(key);
+    }
+    let (stored, value) = self.sessions.lock().unwrap().stored.remove(key)?;
+    (stored.elapsed() < self.lifetime).then_some(value)
+  }
+
+  fn can_cache(&self) -> bool {
+    true
+  }
+}
+
+/// Reading a file can be replayed safely, posting cannot.
+pub fn routes() -> Routes {
+  Routes::new()
+    .route("GET ", EarlyData::Safe)
+    .route("POST ", EarlyData::Unsafe)
+}
+
+/// Answers `GET <filename>\r\n` from `root` and `POST /something <body>\r\n`,
+/// the first also in 0-RTT data, until the endpoint closes. The 0-RTT outcome
+/// of each connection is recorded in `counters` when it closes.
+pub async fn serve(
+  endpoint: Endpoint,
+  admission: Arc<Admission>,
+  root: PathBuf,
+  counters: Arc<ZeroRttCounters>,
+  qlog: Qlog,
+  metrics: Arc<Metrics>,
+) {
+  let root: Arc<Path> = root.into();
+  let routes = Arc::new(routes());
+  super::serve(&endpoint, &admission, &metrics, |incoming| {
+    handle_incoming(incoming, root.clone(), routes.clone(), counters.clone(), qlog.clone(), metrics.clone())
+  }).await
+}
+
+async fn handle_incoming(
+  incoming: Incoming,
+  root: Arc<Path>,
+  routes: Arc<Routes>,
+  counters: Arc<ZeroRttCounters>,
+  qlog: Qlog,
+  metrics: Arc<Metrics>,
+) -> Result<()> {
+  let started = Instant::now();
+  let connecting = incoming.accept().inspect_err(|e| metrics.refused(Some(e)))?;
+  // always succeeds on the server, letting it answer 0-RTT requests right away.
+  // The ZeroRttAccepted it returns says nothing about early data, see ServerZeroRtt,
+  // but it does resolve once the handshake is done
+  let Ok((conn, handshake)) = connecting.into_0rtt() else {
+    return Err(anyhow!("failed establishing connection"));
+  };
+  let conn_id = conn.stable_id();
+  info!(conn_id, remote:% = conn.remote_address(); "established connection");
+  let trace = qlog.trace(&conn);
+  let (established, handshake_metrics) = (conn.clone(), metrics.clone());
+  tokio::spawn(async move {
+    handshake.await;
+    match established.close_reason() {
+      None => handshake_metrics.accepted(&established, started.elapsed()),
+      Some(reason) => handshake_metrics.refused(Some(&reason)),
+    }
+  });
+
+  let zero_rtt = ServerZeroRtt::new();
+  let result = handle_conn(conn, root, routes, &zero_rtt, trace, &metrics).await;
+  counters.record(zero_rtt.outcome());
+  if zero_rtt.outcome() == ZeroRtt::Accepted {
+    metrics.zero_rtt_accepted();
+  }
+  info!(
+    conn_id,
+    zero_rtt:% = zero_rtt.outcome(),
+    early_streams = zero_rtt.early_streams();
+    "📊 0-RTT {}, so far: {}", zero_rtt.outcome(), counters,
+  );
+  result
+}
+
+async fn handle_conn(
+  conn: Connection,
+  root: Arc<Path>,
+  routes: Arc<Routes>,
+  zero_rtt: &ServerZeroRtt,
+  trace: QlogTrace,
+  metrics: &Arc<Metrics>,
+) -> Result<()> {
+  let conn_id = conn.stable_id();
+  loop {
+    let (send, recv) = match super::accept_bi(&conn, metrics).await {
+      Ok(stream) => stream,
+      Err(e) => return super::closed(conn_id, e),
+    };
+    zero_rtt.stream(recv.is_0rtt());
+    let (root, routes) = (root.clone(), routes.clone());
+    let id = send.id();
+    spawn_logged(format!("stream on connection {}", conn_id), metrics.stream(trace.stream(id, async move {
+      handle_stream(conn_id, send, recv, &root, &routes).await
+    })));
+  }
+}
+
+async fn handle_stream(
+  conn_id: usize,
+  mut send: SendStream,
+  mut recv: RecvStream,
+  root: &Path,
+  routes: &Routes,
+) -> Result<()> {
+  let (stream, is_0rtt) = (send.id(), recv.is_0rtt());
+  let req = recv
+    .read_to_end(MAX_REQUEST_SIZE)
+    .await
+    .context("failed reading request")?;
+  info!(conn_id, stream:%, is_0rtt; "req {}", escape(&req));
+
+  if !routes.admit(&req, is_0rtt, &mut send) {
+    info!(conn_id, stream:%; "⏳ not safe in 0-RTT, asked the client to resend after the handshake");
+    return Ok(());
+  }
+  let resp = handle_req(&req, root).unwrap_or_else(|e| {
+    warn!(conn_id, stream:%; "handle request failed: {}", e);
+    String::from("failed to handle request").into_bytes()
+  });
+  send.write_all(&resp).await.context("failed to send response")?;
+  wire::finish(&mut send).await.context("failed to finish stream")?;
+  Ok(())
+}
+
+fn handle_req(req: &[u8], root: &Path) -> Result<Vec<u8>> {
+  if req.starts_with(b"GET ") {
+    return files::read(root, req);
+  }
+  if let Some(req) = req.strip_prefix(b"POST ") {
+    return handle_post(req);
+  }
+  bail!("not a GET or POST request");
+}
+
+fn handle_post(req: &[u8]) -> Result<Vec<u8>> {
+  let Some(req) = req.strip_suffix(b"\r\n") else {
+    bail!("missing \\r\\n");
+  };
+  let Some(body) = req.strip_prefix(b"/something ") else {
+    bail!("something is missing");
+  };
+  info!("client post: {}", String::from_utf8(body.to_vec())?);
+  Ok(b"successfully post".to_vec())
+}
+
+/// Connects with 0-RTT when the endpoint holds a session ticket for the
+/// server, waiting for the full handshake otherwise.
+pub async fn resume(endpoint: &Endpoint, addr: SocketAddr) -> Result<EarlyClient> {
+  let conn = EarlyClient::connect(endpoint.connect(addr, "localhost")?).await?;
+  let conn_id = conn.connection().stable_id();
+  if conn.is_handshake_done() {
+    info!(conn_id, remote:% = addr; "connected server without 0-RTT");
+  } else {
+    info!(conn_id, remote:% = addr; "0-RTT connected server");
+  }
+  Ok(conn)
+}
+
+pub async fn get(conn: &EarlyClient, filename: &str) -> Result<Vec<u8>> {
+  conn.request(format!("GET {}\r\n", filename).as_bytes()).await
+}
+
+/// Not safe in 0-RTT, the server makes it wait for the handshake.
+pub async fn post(conn: &EarlyClient, body: &str) -> Result<Vec<u8>> {
+  conn.request(format!("POST /something {}\r\n", body).as_bytes()).await
+}
+
+/// The datagrams a [`recording_proxy`] saw from the client.
+pub type Captured = Arc<Mutex<Vec<Vec<u8>>>>;
+
+/// Forwards datagrams between a client and `server_addr` from `listen`, the
+/// way an on-path attacker would, recording what the client sends. Returns
+/// the address clients connect to.
+pub async fn recording_proxy(listen: SocketAddr, server_addr: SocketAddr, captured: Captured) -> Result<SocketAddr> {
+  let downstream = Arc::new(UdpSocket::bind(listen).await?);
+  let upstream = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+  let addr = downstream.local_addr()?;
+  let client_addr = Arc::new(Mutex::new(None));
+
+  let (down, up, client) = (downstream.clone(), upstream.clone(), client_addr.clone());
+  tokio::spawn(async move {
+    let mut buf = vec![0u8; 65535];
+    while let Ok((len, from)) = down.recv_from(&mut buf).await {
+      *client.lock().unwrap() = Some(from);
+      captured.lock().unwrap().push(buf[..len].to_vec());
+      let _ = up.send_to(&buf[..len], server_addr).await;
+    }
+  });
+  tokio::spawn(async move {
+    let mut buf = vec![0u8; 65535];
+    while let Ok((len, _)) = upstream.recv_from(&mut buf).await {
+      let client = *client_addr.lock().unwrap();
+      if let Some(client) = client {
+        let _ = downstream.send_to(&buf[..len], client).await;
+      }
+    }
+  });
+  Ok(addr)
+}
+
+/// Sends captured `datagrams` to `server_addr` again from a new address. The
+/// server answers that address, keep the returned socket open for a moment.
+pub async fn replay(datagrams: &[Vec<u8>], server_addr: SocketAddr) -> Result<UdpSocket> {
+  let attacker = UdpSocket::bind("127.0.0.1:0").await?;
+  for datagram in datagrams {
+    attacker.send_to(datagram, server_addr).await?;
+  }
+  Ok(attacker)
+}
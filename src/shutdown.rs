@@ -0,0 +1,117 @@
+//! Graceful shutdown for the servers. Told to stop, a server turns new
+//! connections and streams away, lets the streams in flight finish until the
+//! drain deadline, then closes the connections left with
+//! [`AppError::ShuttingDown`] and waits for the endpoint to go idle.
+
+use std::{future::Future, sync::Arc, time::Duration};
+use anyhow::Result;
+use log::{info, warn};
+use quinn::Endpoint;
+use tokio::time;
+
+use crate::{cancellation::AppError, config::env_or, metrics::Metrics};
+
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a stopping server waits for the streams in flight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Drain {
+  pub timeout: Duration,
+}
+
+impl Default for Drain {
+  fn default() -> Self {
+    Self { timeout: DEFAULT_DRAIN_TIMEOUT }
+  }
+}
+
+impl Drain {
+  /// Reads `DRAIN_SECS`, 10 by default.
+  pub fn from_env() -> Result<Self> {
+    Ok(Self { timeout: Duration::from_secs(env_or("DRAIN_SECS", DEFAULT_DRAIN_TIMEOUT.as_secs())?) })
+  }
+
+  /// Runs `serving`, the server's accept loop on `endpoint`, until `stop`
+  /// resolves, then drains the endpoint. Streams in flight are the ones
+  /// `metrics` counts. A second `stop` cuts the drain short.
+  pub async fn serve_until<S, F>(&self, endpoint: &Endpoint, metrics: &Arc<Metrics>, serving: S, stop: impl Fn() -> F)
+  where
+    S: Future<Output = ()>,
+    F: Future<Output = ()>,
+  {
+    tokio::select! {
+      _ = serving => return,
+      _ = stop() => {}
+    }
+    // the accept loop is gone, connections already accepted carry on but
+    // their new streams are turned away
+    metrics.drain();
+    let refusing = tokio::spawn(refuse(endpoint.clone(), metrics.clone()));
+    info!(
+      connections = endpoint.open_connections(),
+      streams = metrics.active_streams();
+      "🛑 shutting down, draining streams in flight for up to {:?}", self.timeout,
+    );
+
+    let drained = async {
+      while metrics.active_streams() > 0 {
+        time::sleep(DRAIN_POLL_INTERVAL).await;
+      }
+    };
+    tokio::select! {
+      result = time::timeout(self.timeout, drained) => if result.is_err() {
+        warn!(streams = metrics.active_streams(); "⏰ drain deadline passed, closing streams still in flight");
+      },
+      _ = stop() => warn!(streams = metrics.active_streams(); "⏩ told to stop again, closing streams still in flight"),
+    }
+
+    let reason = AppError::ShuttingDown;
+    endpoint.close(reason.code(), reason.to_string().as_bytes());
+    endpoint.wait_idle().await;
+    let _ = refusing.await;
+    info!("👋 shut down");
+  }
+}
+
+// turns away connections until the endpoint closes
+async fn refuse(endpoint: Endpoint, metrics: Arc<Metrics>) {
+  while let Some(incoming) = endpoint.accept().await {
+    info!(remote:% = incoming.remote_address(); "🚫 refusing connection, shutting down");
+    incoming.refuse();
+    metrics.refused(None);
+  }
+}
+
+/// Resolves on Ctrl-C, or SIGTERM on Unix, the way deploys stop a server.
+pub async fn signal() {
+  #[cfg(unix)]
+  let terminate = async {
+    match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+      Ok(mut terminate) => {
+        terminate.recv().await;
+      }
+      Err(e) => {
+        warn!("failed listening for SIGTERM: {}", e);
+        std::future::pending::<()>().await;
+      }
+    }
+  };
+  #[cfg(not(unix))]
+  let terminate = std::future::pending::<()>();
+
+  tokio::select! {
+    result = tokio::signal::ctrl_c() => if let Err(e) = result {
+      warn!("failed listening for Ctrl-C: {}", e);
+      std::future::pending::<()>().await;
+    },
+    _ = terminate => {}
+  }
+}
+
+/// Runs `serving`, the server's accept loop on `endpoint`, until Ctrl-C or
+/// SIGTERM, then drains the endpoint for `DRAIN_SECS`.
+pub async fn serve(endpoint: &Endpoint, metrics: &Arc<Metrics>, serving: impl Future<Output = ()>) -> Result<()> {
+  Drain::from_env()?.serve_until(endpoint, metrics, serving, signal).await;
+  Ok(())
+}
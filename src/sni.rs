@@ -0,0 +1,89 @@
+use std::{collections::HashMap, sync::Arc};
+use anyhow::{Result, bail};
+use quinn::{crypto::rustls::HandshakeData, Connection};
+use rustls::{
+  server::{ClientHello, ResolvesServerCert},
+  sign::CertifiedKey,
+};
+
+/// Values looked up by server name: exact names like `a.localhost`,
+/// wildcards like `*.wild.localhost` matching exactly one more label, and a
+/// default for anything else, including clients that sent no SNI.
+#[derive(Debug, Clone)]
+pub struct HostMap<T> {
+  exact: HashMap<String, T>,
+  // keyed by the suffix after `*.`
+  wildcard: HashMap<String, T>,
+  default: Option<T>,
+}
+
+impl<T> Default for HostMap<T> {
+  fn default() -> Self {
+    Self { exact: HashMap::new(), wildcard: HashMap::new(), default: None }
+  }
+}
+
+impl<T> HostMap<T> {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn insert(&mut self, pattern: &str, value: T) -> Result<()> {
+    let pattern = pattern.to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+      Some(suffix) if !suffix.is_empty() && !suffix.contains('*') => {
+        self.wildcard.insert(suffix.to_string(), value);
+      }
+      None if !pattern.is_empty() && !pattern.contains('*') => {
+        self.exact.insert(pattern, value);
+      }
+      _ => bail!("invalid host pattern {:?}", pattern),
+    }
+    Ok(())
+  }
+
+  pub fn set_default(&mut self, value: T) {
+    self.default = Some(value);
+  }
+
+  /// An exact match wins over a wildcard, which wins over the default.
+  pub fn get(&self, server_name: Option<&str>) -> Option<&T> {
+    let Some(name) = server_name.map(str::to_ascii_lowercase) else {
+      return self.default.as_ref();
+    };
+    self.exact
+      .get(&name)
+      .or_else(|| {
+        let (_, parent) = name.split_once('.')?;
+        self.wildcard.get(parent)
+      })
+      .or(self.default.as_ref())
+  }
+}
+
+/// Picks the certificate for a handshake from the SNI in the ClientHello.
+#[derive(Debug, Default)]
+pub struct SniResolver {
+  certs: HostMap<Arc<CertifiedKey>>,
+}
+
+impl SniResolver {
+  pub fn new(certs: HostMap<Arc<CertifiedKey>>) -> Self {
+    Self { certs }
+  }
+}
+
+impl ResolvesServerCert for SniResolver {
+  fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+    // no certificate aborts the handshake
+    self.certs.get(client_hello.server_name()).cloned()
+  }
+}
+
+/// The server name the client asked for, `None` if it sent no SNI.
+pub fn server_name(conn: &Connection) -> Option<String> {
+  conn.handshake_data()?
+    .downcast::<HandshakeData>()
+    .ok()?
+    .server_name
+}
@@ -0,0 +1,219 @@
+use std::{
+  fs, path::{Path, PathBuf}, sync::{Arc, Mutex, RwLock}, time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use anyhow::{Context, Result, anyhow, bail};
+use log::{debug, info, warn};
+use aws_lc_rs::{
+  aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+  rand,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rustls::server::ProducesTickets;
+use sha2::{Digest, Sha256};
+use tokio::task::JoinHandle;
+
+const KEY_ID_LEN: usize = 4;
+
+/// A session ticket encryption key and when it was created.
+#[derive(Clone)]
+pub struct TicketKey {
+  pub created: u64,
+  id: [u8; KEY_ID_LEN],
+  key: Arc<LessSafeKey>,
+  secret: [u8; 32],
+}
+
+impl TicketKey {
+  pub fn generate(created: u64) -> Result<Self> {
+    let mut secret = [0u8; 32];
+    rand::fill(&mut secret).map_err(|_| anyhow!("failed to generate a ticket key"))?;
+    Self::from_secret(created, secret)
+  }
+
+  fn from_secret(created: u64, secret: [u8; 32]) -> Result<Self> {
+    let key = UnboundKey::new(&AES_256_GCM, &secret).map_err(|_| anyhow!("invalid ticket key"))?;
+    let digest = Sha256::digest(secret);
+    let id = digest[..KEY_ID_LEN].try_into().unwrap();
+    Ok(Self { created, id, key: Arc::new(LessSafeKey::new(key)), secret })
+  }
+}
+
+impl std::fmt::Debug for TicketKey {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    // never print the secret
+    f.debug_struct("TicketKey").field("created", &self.created).field("id", &self.id).finish()
+  }
+}
+
+/// Reads a ticket key file: one `<created unix secs> <base64 key>` per line,
+/// newest first. Blank lines and lines starting with `#` are skipped.
+pub fn read_key_file(path: &Path) -> Result<Vec<TicketKey>> {
+  let text = fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+  let mut keys = Vec::new();
+  for (n, line) in text.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let parse = || -> Result<TicketKey> {
+      let (created, secret) = line.split_once(' ').context("expected `<created> <key>`")?;
+      let created = created.parse().context("invalid creation time")?;
+      let secret = STANDARD.decode(secret.trim()).context("key is not base64")?;
+      let secret = secret.try_into().map_err(|_| anyhow!("key must be 32 bytes"))?;
+      TicketKey::from_secret(created, secret)
+    };
+    keys.push(parse().with_context(|| format!("{}:{}", path.display(), n + 1))?);
+  }
+  if keys.is_empty() {
+    bail!("no ticket keys in {}", path.display());
+  }
+  keys.sort_by_key(|key| std::cmp::Reverse(key.created));
+  Ok(keys)
+}
+
+/// Adds a new key to the key file and erases the keys no ticket can still
+/// use, returning the keys now in the file. Creates the file if it is missing.
+///
+/// The file is replaced by a rename, so servers never read a partial file.
+pub fn rotate_key_file(path: &Path, lifetime: Duration) -> Result<Vec<TicketKey>> {
+  let now = unix_now();
+  let mut keys = if path.exists() { read_key_file(path)? } else { Vec::new() };
+  keys.insert(0, TicketKey::generate(now)?);
+  let keys = usable(&keys, lifetime, now);
+
+  let mut text = String::from("# session ticket keys, newest first: <created unix secs> <base64 key>\n");
+  for key in &keys {
+    text.push_str(&format!("{} {}\n", key.created, STANDARD.encode(key.secret)));
+  }
+  let part = path.with_file_name(format!(".{}.part", path.file_name().unwrap().to_string_lossy()));
+  fs::write(&part, text).context("failed to write ticket keys")?;
+  fs::rename(&part, path).context("failed to replace ticket keys")?;
+  Ok(keys)
+}
+
+// a key stops encrypting when the next one is created, and its last tickets
+// expire `lifetime` after that
+fn usable(keys: &[TicketKey], lifetime: Duration, now: u64) -> Vec<TicketKey> {
+  let mut kept = Vec::new();
+  for (i, key) in keys.iter().enumerate() {
+    let retired = i > 0 && keys[i - 1].created.saturating_add(lifetime.as_secs()) < now;
+    if retired {
+      break;
+    }
+    kept.push(key.clone());
+  }
+  kept
+}
+
+fn unix_now() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Encrypts session tickets with keys shared through a file, so that any
+/// server reading the same file can resume a session another one started.
+///
+/// The newest key encrypts, older keys only decrypt until the tickets they
+/// encrypted have expired, and are then forgotten. Rotating the file often
+/// bounds how much traffic a leaked key exposes. The file is polled with
+/// [`KeyFileTicketer::watch`], a file that fails to load keeps the previous keys.
+///
+/// rustls accepts 0-RTT only with stateful tickets: with this ticketer clients
+/// resume without a full handshake but send no early data.
+#[derive(Debug)]
+pub struct KeyFileTicketer {
+  path: PathBuf,
+  lifetime: Duration,
+  keys: RwLock<Vec<TicketKey>>,
+  seen: Mutex<Option<SystemTime>>,
+}
+
+impl KeyFileTicketer {
+  pub fn new(path: impl Into<PathBuf>, lifetime: Duration) -> Result<Self> {
+    let path = path.into();
+    if lifetime.as_secs() == 0 || lifetime.as_secs() > u32::MAX.into() {
+      bail!("ticket lifetime must be between 1s and {}s", u32::MAX);
+    }
+    let seen = modified(&path);
+    let keys = read_key_file(&path)?;
+    Ok(Self { path, lifetime, keys: RwLock::new(keys), seen: Mutex::new(seen) })
+  }
+
+  /// The keys tickets are currently accepted with, newest first.
+  pub fn keys(&self) -> Vec<TicketKey> {
+    usable(&self.keys.read().unwrap(), self.lifetime, unix_now())
+  }
+
+  /// Reads the key file again, returning how many keys it holds.
+  pub fn reload(&self) -> Result<usize> {
+    *self.seen.lock().unwrap() = modified(&self.path);
+    let keys = read_key_file(&self.path)?;
+    let count = keys.len();
+    *self.keys.write().unwrap() = keys;
+    Ok(count)
+  }
+
+  /// Polls the key file every `interval` and reloads it when it changes,
+  /// until the returned task is aborted.
+  pub fn watch(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+    let ticketer = self.clone();
+    tokio::spawn(async move {
+      let mut ticker = tokio::time::interval(interval);
+      loop {
+        ticker.tick().await;
+        if modified(&ticketer.path) == *ticketer.seen.lock().unwrap() {
+          continue;
+        }
+        match ticketer.reload() {
+          Ok(count) => info!(count, path:% = ticketer.path.display(); "🔄 reloaded session ticket keys"),
+          Err(e) => warn!("failed to reload ticket keys, keeping the previous ones: {:#}", e),
+        }
+      }
+    })
+  }
+}
+
+impl ProducesTickets for KeyFileTicketer {
+  fn enabled(&self) -> bool {
+    true
+  }
+
+  fn lifetime(&self) -> u32 {
+    self.lifetime.as_secs() as u32
+  }
+
+  fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+    let key = self.keys().into_iter().next()?;
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::fill(&mut nonce).ok()?;
+
+    let mut sealed = plain.to_vec();
+    key.key
+      .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(key.id), &mut sealed)
+      .ok()?;
+    let mut ticket = Vec::with_capacity(KEY_ID_LEN + NONCE_LEN + sealed.len());
+    ticket.extend_from_slice(&key.id);
+    ticket.extend_from_slice(&nonce);
+    ticket.extend_from_slice(&sealed);
+    Some(ticket)
+  }
+
+  fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+    if cipher.len() < KEY_ID_LEN + NONCE_LEN {
+      return None;
+    }
+    let (id, rest) = cipher.split_at(KEY_ID_LEN);
+    let (nonce, sealed) = rest.split_at(NONCE_LEN);
+    let key = self.keys().into_iter().find(|key| key.id == id)?;
+
+    let mut plain = sealed.to_vec();
+    let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+    let len = key.key.open_in_place(nonce, Aad::from(key.id), &mut plain).ok()?.len();
+    plain.truncate(len);
+    debug!(key_created = key.created; "🎫 resumed a session from a shared ticket key");
+    Some(plain)
+  }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+  fs::metadata(path).and_then(|m| m.modified()).ok()
+}
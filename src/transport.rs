@@ -0,0 +1,122 @@
+use std::{fmt, str::FromStr, sync::Arc};
+use anyhow::{Result, anyhow};
+use quinn::{
+  congestion::{BbrConfig, CubicConfig, NewRenoConfig},
+  TransportConfig,
+  VarInt,
+};
+
+use crate::config::env_or;
+
+/// Stream concurrency and flow control windows, defaulting to Quinn's own values.
+///
+/// Worst-case memory per connection is roughly
+/// `max_concurrent_bidi_streams * stream_receive_window`, capped by `receive_window`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowControl {
+  pub max_concurrent_bidi_streams: u32,
+  pub max_concurrent_uni_streams: u32,
+  pub stream_receive_window: u32,
+  pub receive_window: u64,
+  pub send_window: u64,
+}
+
+impl Default for FlowControl {
+  fn default() -> Self {
+    // 100ms at 100 Mbit/s, the same window Quinn picks
+    const STREAM_RWND: u32 = 12500 * 100;
+    Self {
+      max_concurrent_bidi_streams: 100,
+      max_concurrent_uni_streams: 100,
+      stream_receive_window: STREAM_RWND,
+      receive_window: VarInt::MAX.into_inner(),
+      send_window: 8 * STREAM_RWND as u64,
+    }
+  }
+}
+
+impl FlowControl {
+  /// Overrides the defaults with `MAX_BIDI_STREAMS`, `MAX_UNI_STREAMS`,
+  /// `STREAM_RECEIVE_WINDOW`, `RECEIVE_WINDOW` and `SEND_WINDOW`.
+  pub fn from_env() -> Result<Self> {
+    let default = Self::default();
+    Ok(Self {
+      max_concurrent_bidi_streams: env_or("MAX_BIDI_STREAMS", default.max_concurrent_bidi_streams)?,
+      max_concurrent_uni_streams: env_or("MAX_UNI_STREAMS", default.max_concurrent_uni_streams)?,
+      stream_receive_window: env_or("STREAM_RECEIVE_WINDOW", default.stream_receive_window)?,
+      receive_window: env_or("RECEIVE_WINDOW", default.receive_window)?,
+      send_window: env_or("SEND_WINDOW", default.send_window)?,
+    })
+  }
+
+  pub fn apply(&self, transport_config: &mut TransportConfig) -> Result<()> {
+    transport_config
+      .max_concurrent_bidi_streams(self.max_concurrent_bidi_streams.into())
+      .max_concurrent_uni_streams(self.max_concurrent_uni_streams.into())
+      .stream_receive_window(self.stream_receive_window.into())
+      .receive_window(VarInt::from_u64(self.receive_window)?)
+      .send_window(self.send_window);
+    Ok(())
+  }
+}
+
+impl fmt::Display for FlowControl {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "bidi streams {}, uni streams {}, stream window {}B, connection window {}B, send window {}B",
+      self.max_concurrent_bidi_streams,
+      self.max_concurrent_uni_streams,
+      self.stream_receive_window,
+      self.receive_window,
+      self.send_window,
+    )
+  }
+}
+
+/// The congestion controller, Cubic by default like Quinn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Congestion {
+  #[default]
+  Cubic,
+  NewReno,
+  Bbr,
+}
+
+impl Congestion {
+  /// Reads `CONGESTION`, one of `cubic`, `newreno` or `bbr`.
+  pub fn from_env() -> Result<Self> {
+    env_or("CONGESTION", Self::default())
+  }
+
+  pub fn apply(&self, transport_config: &mut TransportConfig) {
+    match self {
+      Self::Cubic => transport_config.congestion_controller_factory(Arc::new(CubicConfig::default())),
+      Self::NewReno => transport_config.congestion_controller_factory(Arc::new(NewRenoConfig::default())),
+      Self::Bbr => transport_config.congestion_controller_factory(Arc::new(BbrConfig::default())),
+    };
+  }
+}
+
+impl FromStr for Congestion {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s.to_ascii_lowercase().as_str() {
+      "cubic" => Ok(Self::Cubic),
+      "newreno" => Ok(Self::NewReno),
+      "bbr" => Ok(Self::Bbr),
+      _ => Err(anyhow!("unknown congestion controller, expected cubic, newreno or bbr")),
+    }
+  }
+}
+
+impl fmt::Display for Congestion {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Cubic => "cubic",
+      Self::NewReno => "newreno",
+      Self::Bbr => "bbr",
+    })
+  }
+}
@@ -0,0 +1,249 @@
+use std::{
+  fmt, fs, path::{Path, PathBuf}, str,
+  sync::{atomic::{AtomicU64, Ordering}, Mutex},
+};
+use anyhow::{Context, Result, anyhow, bail};
+use quinn::{Connection, RecvStream, WriteError};
+use sha2::{Digest, Sha256};
+use tokio::{fs::File, io::AsyncWriteExt, sync::Mutex as AsyncMutex};
+
+use crate::{
+  cancellation::AppError,
+  files,
+  push::{notify, Notification},
+  wire::{from_hex, read_line, to_hex},
+};
+
+const MAX_HEADER_SIZE: usize = 1024;
+
+/// First line of an upload stream: `PUT <filename> <size> <sha256 hex>\r\n`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadHeader {
+  pub filename: String,
+  pub size: u64,
+  pub sha256: [u8; 32],
+}
+
+impl UploadHeader {
+  pub fn for_data(filename: &str, data: &[u8]) -> Self {
+    Self {
+      filename: filename.to_string(),
+      size: data.len() as u64,
+      sha256: Sha256::digest(data).into(),
+    }
+  }
+
+  pub fn encode(&self) -> Vec<u8> {
+    format!("PUT {} {} {}\r\n", self.filename, self.size, to_hex(&self.sha256)).into_bytes()
+  }
+
+  pub fn parse(line: &[u8]) -> Result<Self> {
+    let line = str::from_utf8(line).context("header is malformed UTF-8")?;
+    let mut parts = line.split(' ');
+    if parts.next() != Some("PUT") {
+      bail!("missing PUT");
+    }
+    let (Some(filename), Some(size), Some(sha256), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+      bail!("expected PUT <filename> <size> <sha256>");
+    };
+    if !files::valid_filename(filename) {
+      bail!("invalid filename {:?}", filename);
+    }
+    let sha256 = from_hex(sha256)?
+      .try_into()
+      .map_err(|_| anyhow!("sha256 must be 32 bytes"))?;
+    Ok(Self {
+      filename: filename.to_string(),
+      size: size.parse().context("invalid size")?,
+      sha256,
+    })
+  }
+}
+
+/// Pushed back by the server once an upload is settled, as an `UPLOADED` or
+/// `REJECTED` notification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Confirmation {
+  Uploaded { filename: String, size: u64 },
+  Rejected { filename: String, reason: String },
+}
+
+impl From<&Confirmation> for Notification {
+  fn from(confirmation: &Confirmation) -> Self {
+    match confirmation {
+      Confirmation::Uploaded { filename, size } => Notification::new("UPLOADED", &format!("{} {}", filename, size)),
+      Confirmation::Rejected { filename, reason } => Notification::new("REJECTED", &format!("{} {}", filename, reason)),
+    }
+  }
+}
+
+impl TryFrom<&Notification> for Confirmation {
+  type Error = anyhow::Error;
+
+  fn try_from(notification: &Notification) -> Result<Self> {
+    let Some((filename, rest)) = notification.body.split_once(' ') else {
+      bail!("malformed confirmation {:?}", notification.line());
+    };
+    match notification.kind.as_str() {
+      "UPLOADED" => Ok(Confirmation::Uploaded {
+        filename: filename.to_string(),
+        size: rest.parse().context("invalid size")?,
+      }),
+      "REJECTED" => Ok(Confirmation::Rejected {
+        filename: filename.to_string(),
+        reason: rest.to_string(),
+      }),
+      _ => bail!("not a confirmation: {:?}", notification.line()),
+    }
+  }
+}
+
+impl fmt::Display for Confirmation {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Confirmation::Uploaded { filename, size } => write!(f, "{} uploaded ({} bytes)", filename, size),
+      Confirmation::Rejected { filename, reason } => write!(f, "{} rejected: {}", filename, reason),
+    }
+  }
+}
+
+/// Stores uploads in a directory, never letting its total size grow past `quota` bytes.
+///
+/// Data is streamed to a hidden `.part` file and only renamed into place once
+/// its size and SHA-256 match the header, so readers never see partial files.
+/// Each upload gets its own part file, concurrent uploads of one name never
+/// share one.
+#[derive(Debug)]
+pub struct UploadService {
+  dir: PathBuf,
+  quota: u64,
+  used: Mutex<u64>,
+  next_part: AtomicU64,
+  // held from reading the size of the file an upload replaces until it is replaced
+  storing: AsyncMutex<()>,
+}
+
+impl UploadService {
+  pub fn new(dir: impl Into<PathBuf>, quota: u64) -> Result<Self> {
+    let dir = dir.into();
+    fs::create_dir_all(&dir).context("failed to create upload dir")?;
+    let mut used = 0;
+    for entry in fs::read_dir(&dir)? {
+      let entry = entry?;
+      if entry.file_type()?.is_file() && !entry.file_name().to_string_lossy().starts_with('.') {
+        used += entry.metadata()?.len();
+      }
+    }
+    Ok(Self { dir, quota, used: Mutex::new(used), next_part: AtomicU64::new(0), storing: AsyncMutex::new(()) })
+  }
+
+  /// Receives one upload and reports the result on a new unidirectional stream.
+  pub async fn handle_uni_stream(&self, conn: &Connection, mut recv: RecvStream) -> Result<Confirmation> {
+    let line = read_line(&mut recv, MAX_HEADER_SIZE).await.context("failed reading upload header")?;
+    let header = UploadHeader::parse(&line)?;
+
+    let confirmation = match self.receive(&header, &mut recv).await {
+      Ok(()) => Confirmation::Uploaded { filename: header.filename, size: header.size },
+      Err(e) => {
+        let _ = recv.stop(AppError::Rejected.code());
+        Confirmation::Rejected { filename: header.filename, reason: format!("{:#}", e) }
+      }
+    };
+
+    notify(conn, &Notification::from(&confirmation)).await.context("failed to send confirmation")?;
+    Ok(confirmation)
+  }
+
+  async fn receive(&self, header: &UploadHeader, recv: &mut RecvStream) -> Result<()> {
+    self.reserve(header.size)?;
+    let n = self.next_part.fetch_add(1, Ordering::Relaxed);
+    let part = self.dir.join(format!(".{}.{}.part", header.filename, n));
+    let result = self.write_part(header, recv, &part).await;
+    let result = match result {
+      Ok(()) => self.store(&part, &header.filename).await,
+      Err(e) => Err(e),
+    };
+    if result.is_err() {
+      let _ = tokio::fs::remove_file(&part).await;
+      self.release(header.size);
+    }
+    result
+  }
+
+  // a replaced file gives its space back to the quota. Stores run one at a
+  // time, or two uploads of one name would both give back the old file's size
+  async fn store(&self, part: &Path, filename: &str) -> Result<()> {
+    let dest = self.dir.join(filename);
+    let _storing = self.storing.lock().await;
+    let replaced = tokio::fs::metadata(&dest).await.map(|m| m.len()).unwrap_or(0);
+    tokio::fs::rename(part, &dest).await.context("failed to store upload")?;
+    self.release(replaced);
+    Ok(())
+  }
+
+  async fn write_part(&self, header: &UploadHeader, recv: &mut RecvStream, part: &Path) -> Result<()> {
+    let mut file = File::create(part).await.context("failed to create upload file")?;
+    let mut hasher = Sha256::new();
+    let mut received = 0u64;
+    while let Some(chunk) = recv.read_chunk(usize::MAX, true).await? {
+      received += chunk.bytes.len() as u64;
+      if received > header.size {
+        bail!("more data than the announced {} bytes", header.size);
+      }
+      hasher.update(&chunk.bytes);
+      file.write_all(&chunk.bytes).await.context("failed to write upload file")?;
+    }
+    if received != header.size {
+      bail!("received {} of {} bytes", received, header.size);
+    }
+    if hasher.finalize()[..] != header.sha256 {
+      bail!("checksum mismatch");
+    }
+    file.sync_all().await.context("failed to flush upload file")?;
+    Ok(())
+  }
+
+  fn reserve(&self, size: u64) -> Result<()> {
+    let mut used = self.used.lock().unwrap();
+    // the client picks `size`, it can be anything up to u64::MAX
+    let Some(total) = used.checked_add(size).filter(|total| *total <= self.quota) else {
+      bail!("quota exceeded ({} of {} bytes used)", *used, self.quota);
+    };
+    *used = total;
+    Ok(())
+  }
+
+  fn release(&self, size: u64) {
+    *self.used.lock().unwrap() -= size;
+  }
+}
+
+/// Sends `data` as `filename` on a new unidirectional stream.
+///
+/// A server that refuses the upload stops the stream early, the reason then
+/// arrives with the confirmation rather than as an error here.
+pub async fn upload(conn: &Connection, filename: &str, data: &[u8]) -> Result<()> {
+  let mut send = conn.open_uni().await.context("failed to open uni_stream")?;
+  let header = UploadHeader::for_data(filename, data).encode();
+  let written = async {
+    send.write_all(&header).await?;
+    send.write_all(data).await
+  }.await;
+  match written {
+    Ok(()) => {}
+    Err(WriteError::Stopped(_)) => return Ok(()),
+    Err(e) => return Err(e).context("failed to send upload data"),
+  }
+  send.finish()?;
+  Ok(())
+}
+
+/// Waits for the next server-opened stream and reads a confirmation from it.
+///
+/// Only for clients that do not run a [`crate::push::PushListener`], which
+/// would otherwise compete for the same streams.
+pub async fn read_confirmation(conn: &Connection) -> Result<Confirmation> {
+  let mut recv = conn.accept_uni().await.context("failed to accept confirmation stream")?;
+  let line = read_line(&mut recv, MAX_HEADER_SIZE).await.context("failed reading confirmation")?;
+  Confirmation::try_from(&Notification::parse(&line)?)
+}
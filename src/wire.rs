@@ -0,0 +1,51 @@
+use core::ascii;
+use anyhow::{Result, bail};
+use quinn::{RecvStream, SendStream};
+
+/// Reads up to and including `\r\n`, returning the line without the terminator.
+///
+/// Reads one byte at a time so that whatever follows the line stays in the stream.
+pub async fn read_line(recv: &mut RecvStream, limit: usize) -> Result<Vec<u8>> {
+  let mut buf = Vec::new();
+  let mut byte = [0u8; 1];
+
+  while recv.read(&mut byte).await? == Some(1) {
+    buf.push(byte[0]);
+    if buf.ends_with(b"\r\n") {
+      buf.truncate(buf.len() - 2);
+      return Ok(buf);
+    }
+    if buf.len() > limit {
+      bail!("line longer than {} bytes", limit);
+    }
+  }
+  bail!("stream ended before \\r\\n");
+}
+
+/// Finishes `send` and waits until the peer acknowledged all of it, so a
+/// stream handler returns only once its response is delivered. A peer that
+/// stops the stream or closes the connection ends the wait as well.
+pub async fn finish(send: &mut SendStream) -> Result<()> {
+  send.finish()?;
+  let _ = send.stopped().await;
+  Ok(())
+}
+
+/// `bytes` with everything but printable ASCII escaped, for logging requests.
+pub fn escape(bytes: &[u8]) -> String {
+  bytes.iter().flat_map(|&b| ascii::escape_default(b)).map(char::from).collect()
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(s: &str) -> Result<Vec<u8>> {
+  if !s.len().is_multiple_of(2) || !s.is_ascii() {
+    bail!("malformed hex string");
+  }
+  (0..s.len())
+    .step_by(2)
+    .map(|i| Ok(u8::from_str_radix(&s[i..i + 2], 16)?))
+    .collect()
+}
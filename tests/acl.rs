@@ -0,0 +1,63 @@
+use quic_rs_demo::{acl::Acl, identity::ClientIdentity};
+
+fn acl(rules: &str) -> Acl {
+  serde_json::from_str(&format!(r#"{{ "rules": {} }}"#, rules)).unwrap()
+}
+
+fn identity(common_name: &str, san: &str, spki: &str) -> ClientIdentity {
+  ClientIdentity {
+    subject: format!("CN={}", common_name),
+    common_name: Some(common_name.to_string()),
+    sans: vec![san.to_string()],
+    spki_sha256: spki.to_string(),
+  }
+}
+
+#[test]
+fn identities_match_by_cn_san_spki_or_any() {
+  let acl = acl(r#"[
+    { "identity": "cn:alice", "paths": ["alice.json"] },
+    { "identity": "san:bob.localhost", "paths": ["bob.json"] },
+    { "identity": "spki:c2hh", "paths": ["carol.json"] },
+    { "identity": "*", "paths": ["shared.json"] },
+    { "identity": "anonymous", "paths": ["sample.json"] }
+  ]"#);
+  let alice = identity("alice", "alice.localhost", "YWxpY2U=");
+  let bob = identity("bob", "bob.localhost", "Ym9i");
+  let carol = identity("carol", "carol.localhost", "c2hh");
+
+  assert!(acl.allows(Some(&alice), "alice.json"));
+  assert!(!acl.allows(Some(&bob), "alice.json"));
+  assert!(acl.allows(Some(&bob), "bob.json"));
+  assert!(acl.allows(Some(&carol), "carol.json"));
+  assert!([&alice, &bob, &carol].iter().all(|id| acl.allows(Some(id), "shared.json")));
+  assert!(!acl.allows(None, "shared.json"));
+  assert!(acl.allows(None, "sample.json"));
+  assert!(!acl.allows(Some(&alice), "sample.json"));
+}
+
+#[test]
+fn paths_match_exactly_by_prefix_or_all() {
+  let acl = acl(r#"[
+    { "identity": "cn:alice", "paths": ["sample.json", "public-*"] },
+    { "identity": "cn:root", "paths": ["*"] }
+  ]"#);
+  let alice = identity("alice", "alice.localhost", "YWxpY2U=");
+  let root = identity("root", "root.localhost", "cm9vdA==");
+
+  assert!(acl.allows(Some(&alice), "sample.json"));
+  assert!(!acl.allows(Some(&alice), "sample.json.bak"));
+  assert!(acl.allows(Some(&alice), "public-notes.txt"));
+  assert!(!acl.allows(Some(&alice), "secret.json"));
+  assert!(acl.allows(Some(&root), "secret.json"));
+}
+
+#[test]
+fn only_plain_file_names_match() {
+  let acl = acl(r#"[{ "identity": "cn:alice", "paths": ["public*", "*"] }]"#);
+  let alice = identity("alice", "alice.localhost", "YWxpY2U=");
+  for path in ["public/../secret.json", "public/x", "/etc/passwd", "..", ".hidden", "", "public\\x"] {
+    assert!(!acl.allows(Some(&alice), path), "{:?}", path);
+  }
+  assert!(acl.allows(Some(&alice), "public.json"));
+}
@@ -0,0 +1,181 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use quic_rs_demo::{
+  admission::{Admission, Decision, Limits, MAX_TRACKED_SOURCES},
+  metrics::Metrics,
+  qlog::{Qlog, Vantage},
+  scenarios::datagram,
+};
+use quinn::ConnectionError;
+use rustls::pki_types::CertificateDer;
+use tokio::time::timeout;
+
+mod common;
+
+fn from(addr: &str) -> SocketAddr {
+  addr.parse().unwrap()
+}
+
+fn limits(max_connections: usize, max_per_ip: usize, retry_above: usize, max_attempts_per_sec: u32) -> Limits {
+  Limits { max_connections, max_per_ip, retry_above, max_attempts_per_sec }
+}
+
+// the decision, keeping the slot of an accepted connection open
+fn decide(admission: &Arc<Admission>, remote: &str, validated: bool, slots: &mut Vec<Decision>) -> String {
+  let decision = admission.decide(from(remote), validated);
+  let name = decision.to_string();
+  slots.push(decision);
+  name
+}
+
+#[test]
+fn connections_past_the_caps_are_refused() {
+  let admission = Admission::new(limits(3, 2, usize::MAX, 100));
+  let mut slots = Vec::new();
+  assert_eq!(decide(&admission, "10.0.0.1:1000", true, &mut slots), "accept");
+  assert_eq!(decide(&admission, "10.0.0.1:1001", true, &mut slots), "accept");
+  assert_eq!(decide(&admission, "10.0.0.1:1002", true, &mut slots), "refuse");
+  assert_eq!(decide(&admission, "10.0.0.2:1000", true, &mut slots), "accept");
+  assert_eq!(decide(&admission, "10.0.0.3:1000", true, &mut slots), "refuse");
+  assert_eq!(admission.open(), 3);
+
+  // closing a connection of 10.0.0.1 makes room for it
+  slots.remove(0);
+  assert_eq!(admission.open(), 2);
+  assert_eq!(decide(&admission, "10.0.0.1:1003", true, &mut slots), "accept");
+}
+
+#[test]
+fn unvalidated_addresses_are_asked_to_retry_under_load() {
+  let admission = Admission::new(limits(10, 10, 1, 100));
+  let mut slots = Vec::new();
+  assert_eq!(decide(&admission, "10.0.0.1:1000", false, &mut slots), "accept");
+  assert_eq!(decide(&admission, "10.0.0.2:1000", false, &mut slots), "retry");
+  assert_eq!(decide(&admission, "10.0.0.2:1000", true, &mut slots), "accept");
+
+  let always = Admission::new(limits(10, 10, 0, 100));
+  assert_eq!(decide(&always, "10.0.0.1:1000", false, &mut slots), "retry");
+  assert_eq!(always.open(), 0);
+}
+
+#[test]
+fn sources_flooding_attempts_are_ignored() {
+  let admission = Admission::new(limits(10, 10, usize::MAX, 3));
+  let mut slots = Vec::new();
+  for port in 0..3 {
+    assert_eq!(decide(&admission, &format!("10.0.0.1:{}", port), true, &mut slots), "accept");
+  }
+  slots.clear();
+  assert_eq!(decide(&admission, "10.0.0.1:3", true, &mut slots), "ignore");
+  assert_eq!(decide(&admission, "10.0.0.1:4", true, &mut slots), "ignore");
+  assert_eq!(decide(&admission, "10.0.0.2:0", true, &mut slots), "accept");
+}
+
+#[test]
+fn unvalidated_floods_are_asked_to_retry_and_lock_nobody_out() {
+  let admission = Admission::new(limits(10, 10, usize::MAX, 3));
+  let mut slots = Vec::new();
+  for port in 0..3 {
+    assert_eq!(decide(&admission, &format!("10.0.0.1:{}", port), false, &mut slots), "accept");
+  }
+  slots.clear();
+  // what could be a flood forged from 10.0.0.1
+  for port in 3..100 {
+    assert_eq!(decide(&admission, &format!("10.0.0.1:{}", port), false, &mut slots), "retry");
+  }
+  // the real 10.0.0.1 answers the Retry and gets in
+  assert_eq!(decide(&admission, "10.0.0.1:100", true, &mut slots), "accept");
+}
+
+#[test]
+fn sources_past_the_tracking_bound_must_validate() {
+  let admission = Admission::new(limits(usize::MAX, 10, usize::MAX, 100));
+  let mut slots = Vec::new();
+  track_sources(&admission, false);
+  assert_eq!(decide(&admission, "11.0.0.1:1000", false, &mut slots), "retry");
+  // sources tracked already still are
+  assert_eq!(decide(&admission, "10.0.0.1:1001", false, &mut slots), "accept");
+  assert_eq!(decide(&admission, "11.0.0.1:1000", true, &mut slots), "accept");
+}
+
+// fills the table with sources 10.0.0.0 and up
+fn track_sources(admission: &Arc<Admission>, validated: bool) {
+  for i in 0..MAX_TRACKED_SOURCES as u32 {
+    let remote = SocketAddr::from((std::net::Ipv4Addr::from(0x0a00_0000 + i), 1000));
+    assert!(matches!(admission.decide(remote, validated), Decision::Accept(_)));
+  }
+}
+
+#[test]
+fn validated_floods_past_the_tracking_bound_are_ignored() {
+  let admission = Admission::new(limits(usize::MAX, usize::MAX, usize::MAX, 3));
+  track_sources(&admission, false);
+  // a validated source takes the place of one that never validated
+  for _ in 0..3 {
+    assert!(matches!(admission.decide(from("11.0.0.1:1000"), true), Decision::Accept(_)));
+  }
+  for _ in 0..10 {
+    assert!(matches!(admission.decide(from("11.0.0.1:1000"), true), Decision::Ignore));
+  }
+
+  // with no such source left, a new validated one cannot be rate limited
+  let admission = Admission::new(limits(usize::MAX, usize::MAX, usize::MAX, 3));
+  track_sources(&admission, true);
+  assert!(matches!(admission.decide(from("11.0.0.1:1000"), true), Decision::Ignore));
+}
+
+fn server(limits: Limits) -> (SocketAddr, CertificateDer<'static>, Arc<Metrics>) {
+  let (endpoint, cert) = common::basic_server();
+  let addr = endpoint.local_addr().unwrap();
+  let metrics = Metrics::new("test");
+  let qlog = Qlog::disabled(Vantage::Server);
+  tokio::spawn(datagram::serve(endpoint, Admission::new(limits), qlog, metrics.clone()));
+  (addr, cert, metrics)
+}
+
+fn decisions(metrics: &Metrics, decision: &str) -> u64 {
+  let prefix = format!("quic_admission_decisions_total{{endpoint=\"test\",decision=\"{}\"}} ", decision);
+  let text = metrics.render();
+  let line = text.lines().find(|line| line.starts_with(&prefix)).unwrap();
+  line[prefix.len()..].parse().unwrap()
+}
+
+#[tokio::test]
+async fn clients_validate_their_address_and_connect() {
+  let (addr, cert, metrics) = server(limits(10, 10, 0, 100));
+  let conn = common::connect(&common::basic_client(&cert), addr).await;
+  conn.send_datagram("hello".into()).unwrap();
+  assert!(timeout(Duration::from_secs(5), conn.read_datagram()).await.unwrap().is_ok());
+  assert_eq!(decisions(&metrics, "retry"), 1);
+  assert_eq!(decisions(&metrics, "accept"), 1);
+}
+
+#[tokio::test]
+async fn a_second_connection_from_the_same_ip_is_refused() {
+  let (addr, cert, metrics) = server(limits(10, 1, usize::MAX, 100));
+  let client = common::basic_client(&cert);
+  let first = common::connect(&client, addr).await;
+
+  let second = client.connect(addr, "localhost").unwrap().await;
+  assert!(matches!(second, Err(ConnectionError::ConnectionClosed(_))), "{:?}", second.map(|_| ()));
+  assert_eq!(decisions(&metrics, "refuse"), 1);
+
+  // the server lets the slot go once the connection ends
+  first.close(0u32.into(), b"done");
+  let reconnected = timeout(Duration::from_secs(5), async {
+    loop {
+      if let Ok(conn) = client.connect(addr, "localhost").unwrap().await {
+        return conn;
+      }
+      tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+  }).await;
+  assert!(reconnected.is_ok(), "never reconnected:\n{}", metrics.render());
+}
+
+#[test]
+fn limits_display() {
+  assert_eq!(
+    Limits::default().to_string(),
+    "1024 connections, 32 per IP, retry above 256, 100 attempts/s per IP",
+  );
+}
@@ -0,0 +1,98 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}, thread, time::Duration};
+use quic_rs_demo::anti_replay::{AntiReplayStore, MemoryStrikeRegister, StrikeRegister};
+use rustls::server::StoresServerSessions;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+// like a cache replicated across servers, taking a session leaves it in place
+#[derive(Debug, Default)]
+struct Replicated(Mutex<HashMap<Vec<u8>, Vec<u8>>>);
+
+impl StoresServerSessions for Replicated {
+  fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+    self.0.lock().unwrap().insert(key, value);
+    true
+  }
+
+  fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+    self.0.lock().unwrap().get(key).cloned()
+  }
+
+  fn take(&self, key: &[u8]) -> Option<Vec<u8>> {
+    self.get(key)
+  }
+
+  fn can_cache(&self) -> bool {
+    true
+  }
+}
+
+#[test]
+fn register_refuses_duplicates() {
+  let register = MemoryStrikeRegister::new(10);
+  assert!(register.insert(&[1; 32], WINDOW));
+  assert!(register.insert(&[2; 32], WINDOW));
+  assert!(!register.insert(&[1; 32], WINDOW));
+  assert_eq!(register.len(), 2);
+}
+
+#[test]
+fn register_forgets_after_window() {
+  let register = MemoryStrikeRegister::new(10);
+  assert!(register.insert(&[1; 32], Duration::from_millis(20)));
+  thread::sleep(Duration::from_millis(50));
+  assert!(register.insert(&[1; 32], WINDOW));
+}
+
+#[test]
+fn full_register_fails_closed() {
+  let register = MemoryStrikeRegister::new(2);
+  assert!(register.insert(&[1; 32], WINDOW));
+  assert!(register.insert(&[2; 32], Duration::from_millis(20)));
+  assert!(!register.insert(&[3; 32], WINDOW));
+
+  // expired entries make room again
+  thread::sleep(Duration::from_millis(50));
+  assert!(register.insert(&[3; 32], WINDOW));
+  assert_eq!(register.len(), 2);
+}
+
+#[test]
+fn store_redeems_each_ticket_once() {
+  let cache = Arc::new(Replicated::default());
+  let store = AntiReplayStore::new(cache, Arc::new(MemoryStrikeRegister::new(10)), WINDOW);
+  assert!(store.put(b"ticket".to_vec(), b"session".to_vec()));
+
+  assert_eq!(store.take(b"ticket").as_deref(), Some(&b"session"[..]));
+  assert_eq!(store.take(b"ticket"), None);
+  assert_eq!(store.refused(), 1);
+  // plain lookups are not redemptions
+  assert!(store.get(b"ticket").is_some());
+}
+
+#[test]
+fn unknown_tickets_do_not_fill_the_register() {
+  let register = Arc::new(MemoryStrikeRegister::new(10));
+  let store = AntiReplayStore::new(Arc::new(Replicated::default()), register.clone(), WINDOW);
+
+  assert_eq!(store.take(b"forged"), None);
+  assert!(register.is_empty());
+  assert_eq!(store.refused(), 0);
+}
+
+#[test]
+fn store_refuses_tickets_while_the_register_is_full() {
+  let register = Arc::new(MemoryStrikeRegister::new(1));
+  let store = AntiReplayStore::new(Arc::new(Replicated::default()), register.clone(), Duration::from_millis(20));
+  store.put(b"first".to_vec(), b"session".to_vec());
+  store.put(b"second".to_vec(), b"session".to_vec());
+
+  assert!(store.take(b"first").is_some());
+  assert_eq!(store.take(b"second"), None);
+  assert_eq!(store.refused(), 1);
+
+  // the register forgets redeemed tickets after the configured lifetime
+  thread::sleep(Duration::from_millis(50));
+  assert!(store.take(b"second").is_some());
+  assert_eq!(register.len(), 1);
+}
@@ -0,0 +1,104 @@
+use std::{fs, sync::Arc, time::Duration};
+use quic_rs_demo::cancellation::{fetch, AppError, FileServer, Outcome};
+use quinn::{Endpoint, ReadError, ReadToEndError};
+use tokio::{sync::mpsc, time::timeout};
+
+mod common;
+
+const LARGE_FILE_SIZE: usize = 32 * 1024 * 1024;
+
+struct Fixture {
+  _dir: tempfile::TempDir,
+  _client: Endpoint,
+  conn: quinn::Connection,
+  outcomes: mpsc::UnboundedReceiver<anyhow::Result<Outcome>>,
+}
+
+async fn fixture() -> Fixture {
+  let dir = tempfile::tempdir().unwrap();
+  fs::write(dir.path().join("small.txt"), b"hello").unwrap();
+  fs::write(dir.path().join("large.bin"), vec![0u8; LARGE_FILE_SIZE]).unwrap();
+  let file_server = Arc::new(
+    FileServer::new(dir.path()).request_timeout(Duration::from_millis(200))
+  );
+
+  let (server, cert) = common::basic_server();
+  let server_addr = server.local_addr().unwrap();
+  let (tx, outcomes) = mpsc::unbounded_channel();
+  tokio::spawn(async move {
+    let conn = server.accept().await.unwrap().await.unwrap();
+    while let Ok((send, recv)) = conn.accept_bi().await {
+      let file_server = file_server.clone();
+      let tx = tx.clone();
+      tokio::spawn(async move {
+        let _ = tx.send(file_server.handle_stream(send, recv).await);
+      });
+    }
+  });
+
+  let client = common::basic_client(&cert);
+  let conn = common::connect(&client, server_addr).await;
+  Fixture { _dir: dir, _client: client, conn, outcomes }
+}
+
+async fn next_outcome(fixture: &mut Fixture) -> anyhow::Result<Outcome> {
+  timeout(Duration::from_secs(5), fixture.outcomes.recv())
+    .await
+    .expect("server did not finish the stream in time")
+    .unwrap()
+}
+
+#[tokio::test]
+async fn completed_request_reports_bytes_sent() {
+  let mut fixture = fixture().await;
+  let resp = fetch(&fixture.conn, "small.txt", std::future::pending()).await.unwrap();
+  assert_eq!(resp, b"hello");
+  assert_eq!(next_outcome(&mut fixture).await.unwrap(), Outcome::Completed(5));
+}
+
+#[tokio::test]
+async fn cancelled_download_stops_the_server() {
+  let mut fixture = fixture().await;
+  let err = fetch(&fixture.conn, "large.bin", tokio::time::sleep(Duration::from_millis(20)))
+    .await
+    .unwrap_err();
+  assert_eq!(err.downcast_ref::<AppError>(), Some(&AppError::Cancelled));
+  assert_eq!(next_outcome(&mut fixture).await.unwrap(), Outcome::Cancelled);
+
+  // the connection survives the cancelled request
+  let resp = fetch(&fixture.conn, "small.txt", std::future::pending()).await.unwrap();
+  assert_eq!(resp, b"hello");
+}
+
+#[tokio::test]
+async fn incomplete_request_times_out() {
+  let mut fixture = fixture().await;
+  let (mut send, mut recv) = fixture.conn.open_bi().await.unwrap();
+  send.write_all(b"GET small").await.unwrap();
+  let err = recv.read_to_end(usize::MAX).await.unwrap_err();
+  let ReadToEndError::Read(ReadError::Reset(code)) = err else {
+    panic!("expected a reset, got {:?}", err);
+  };
+  assert_eq!(AppError::from_code(code), Some(AppError::Timeout));
+  assert_eq!(next_outcome(&mut fixture).await.unwrap(), Outcome::TimedOut);
+}
+
+#[tokio::test]
+async fn paths_outside_the_root_are_refused() {
+  let mut fixture = fixture().await;
+  let outside = tempfile::NamedTempFile::new().unwrap();
+  fs::write(outside.path(), b"secret").unwrap();
+  for filename in [outside.path().to_str().unwrap(), "../small.txt", "sub/small.txt"] {
+    let err = fetch(&fixture.conn, filename, std::future::pending()).await.unwrap_err();
+    assert_eq!(err.downcast_ref::<AppError>(), Some(&AppError::ServerError), "{}", filename);
+    assert!(next_outcome(&mut fixture).await.is_err());
+  }
+}
+
+#[tokio::test]
+async fn missing_file_resets_with_server_error() {
+  let mut fixture = fixture().await;
+  let err = fetch(&fixture.conn, "missing.txt", std::future::pending()).await.unwrap_err();
+  assert_eq!(err.downcast_ref::<AppError>(), Some(&AppError::ServerError));
+  assert!(next_outcome(&mut fixture).await.is_err());
+}
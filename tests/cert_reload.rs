@@ -0,0 +1,91 @@
+use std::{fs, path::Path, sync::Arc};
+use quic_rs_demo::certs::ReloadingCertResolver;
+use quinn::Endpoint;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+mod common;
+
+fn write_pair(dir: &Path, (cert, key): &(CertificateDer<'static>, PrivateKeyDer<'static>)) {
+  fs::write(dir.join("cert.der"), cert).unwrap();
+  fs::write(dir.join("key.der"), key.secret_der()).unwrap();
+}
+
+fn server(resolver: Arc<ReloadingCertResolver>) -> Endpoint {
+  let tls_config = rustls::ServerConfig::builder()
+    .with_no_client_auth()
+    .with_cert_resolver(resolver);
+  let endpoint = common::server(tls_config);
+  let acceptor = endpoint.clone();
+  tokio::spawn(async move {
+    while let Some(incoming) = acceptor.accept().await {
+      tokio::spawn(async move {
+        if let Ok(conn) = incoming.await {
+          // keep the connection open until the client closes it
+          let _ = conn.accept_bi().await;
+        }
+      });
+    }
+  });
+  endpoint
+}
+
+// trusts both certificates so the test sees which one it was served
+fn client(certs: &[&CertificateDer<'static>]) -> Endpoint {
+  let mut roots = rustls::RootCertStore::empty();
+  for cert in certs {
+    roots.add((*cert).clone()).unwrap();
+  }
+  let tls_config = rustls::ClientConfig::builder()
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+  common::client(tls_config)
+}
+
+fn served_cert(conn: &quinn::Connection) -> CertificateDer<'static> {
+  let chain = conn.peer_identity().unwrap().downcast::<Vec<CertificateDer<'static>>>().unwrap();
+  chain[0].clone()
+}
+
+#[tokio::test]
+async fn new_handshakes_get_the_new_cert_while_old_connections_stay() {
+  common::install_provider();
+  let dir = tempfile::tempdir().unwrap();
+  let old = common::self_signed("localhost");
+  let new = common::self_signed("localhost");
+  write_pair(dir.path(), &old);
+  let resolver = Arc::new(ReloadingCertResolver::new(dir.path().join("cert.der"), dir.path().join("key.der")).unwrap());
+  let server = server(resolver.clone());
+  let client = client(&[&old.0, &new.0]);
+  let addr = server.local_addr().unwrap();
+
+  let before = common::connect(&client, addr).await;
+  assert_eq!(served_cert(&before), old.0);
+
+  write_pair(dir.path(), &new);
+  resolver.reload().unwrap();
+  let after = common::connect(&client, addr).await;
+  assert_eq!(served_cert(&after), new.0);
+
+  assert!(before.close_reason().is_none());
+  before.open_bi().await.unwrap();
+}
+
+#[tokio::test]
+async fn mismatched_key_is_not_swapped_in() {
+  common::install_provider();
+  let dir = tempfile::tempdir().unwrap();
+  let old = common::self_signed("localhost");
+  let new = common::self_signed("localhost");
+  write_pair(dir.path(), &old);
+  let resolver = ReloadingCertResolver::new(dir.path().join("cert.der"), dir.path().join("key.der")).unwrap();
+
+  // only the certificate has been replaced so far
+  fs::write(dir.path().join("cert.der"), &new.0).unwrap();
+  let err = resolver.reload().unwrap_err();
+  assert!(format!("{:#}", err).contains("does not match"), "unexpected error: {:#}", err);
+  assert_eq!(resolver.current().cert[0], old.0);
+
+  fs::write(dir.path().join("key.der"), new.1.secret_der()).unwrap();
+  resolver.reload().unwrap();
+  assert_eq!(resolver.current().cert[0], new.0);
+}
@@ -0,0 +1,202 @@
+#![allow(dead_code)]
+
+use std::{net::SocketAddr, path::Path, sync::Arc};
+use quic_rs_demo::{
+  admission::{Admission, Limits},
+  certs,
+  metrics::Metrics,
+  qlog::{Qlog, Vantage},
+  scenarios::{self, basic},
+};
+use quinn::{
+  crypto::rustls::{QuicClientConfig, QuicServerConfig},
+  ClientConfig,
+  Connection,
+  Endpoint,
+  ServerConfig,
+};
+use rcgen::{
+  BasicConstraints, CertificateParams, CertificateRevocationListParams, DnType, IsCa, KeyIdMethod,
+  KeyPair, KeyUsagePurpose, RevocationReason, RevokedCertParams, SerialNumber,
+};
+use rustls::{
+  crypto::{CryptoProvider, aws_lc_rs},
+  pki_types::{CertificateDer, CertificateRevocationListDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+};
+use tempfile::TempDir;
+use time::{Duration, OffsetDateTime};
+
+pub fn any_port() -> SocketAddr {
+  "127.0.0.1:0".parse().unwrap()
+}
+
+/// A cert dir with a self-signed certificate for localhost, as the examples use.
+pub fn cert_dir() -> TempDir {
+  install_provider();
+  let cert_dir = tempfile::tempdir().unwrap();
+  certs::generate_self_signed(cert_dir.path()).unwrap();
+  cert_dir
+}
+
+/// A basic file server for the certificate in `cert_dir`, serving `root`.
+pub fn file_server(cert_dir: &Path, root: &Path) -> SocketAddr {
+  let (tls_config, _) = basic::server_tls(cert_dir).unwrap();
+  let endpoint = scenarios::server_endpoint(tls_config, any_port()).unwrap();
+  let addr = endpoint.local_addr().unwrap();
+  let qlog = Qlog::disabled(Vantage::Server);
+  tokio::spawn(basic::serve(endpoint, admission(), root.to_path_buf(), qlog, Metrics::new("test")));
+  addr
+}
+
+/// A client trusting the certificate in `cert_dir`.
+pub fn file_client(cert_dir: &Path) -> Endpoint {
+  scenarios::client_endpoint(scenarios::client_tls(cert_dir).unwrap(), any_port()).unwrap()
+}
+
+pub fn install_provider() {
+  // every test in the binary races to install it, only the first one wins
+  let _ = CryptoProvider::install_default(aws_lc_rs::default_provider());
+}
+
+pub fn self_signed(name: &str) -> (CertificateDer<'static>, PrivateKeyDer<'static>) {
+  let cert_key = rcgen::generate_simple_self_signed(vec![name.into()]).unwrap();
+  let key = PrivatePkcs8KeyDer::from(cert_key.key_pair.serialize_der());
+  (cert_key.cert.into(), key.into())
+}
+
+/// A CA that issues client certificates and CRLs for them.
+pub struct TestCa {
+  cert: rcgen::Certificate,
+  key: KeyPair,
+}
+
+impl TestCa {
+  pub fn new(name: &str) -> Self {
+    let mut params = CertificateParams::new(Vec::new()).unwrap();
+    params.distinguished_name.push(DnType::CommonName, name);
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    let key = KeyPair::generate().unwrap();
+    let cert = params.self_signed(&key).unwrap();
+    Self { cert, key }
+  }
+
+  pub fn cert(&self) -> CertificateDer<'static> {
+    self.cert.der().clone()
+  }
+
+  pub fn roots(&self) -> Arc<rustls::RootCertStore> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(self.cert()).unwrap();
+    Arc::new(roots)
+  }
+
+  pub fn issue(&self, common_name: &str, serial: u64) -> (CertificateDer<'static>, PrivateKeyDer<'static>) {
+    let mut params = CertificateParams::new(vec![format!("{}.localhost", common_name)]).unwrap();
+    params.distinguished_name.push(DnType::CommonName, common_name);
+    params.serial_number = Some(SerialNumber::from(serial));
+    let key = KeyPair::generate().unwrap();
+    let cert = params.signed_by(&key, &self.cert, &self.key).unwrap();
+    (cert.into(), PrivatePkcs8KeyDer::from(key.serialize_der()).into())
+  }
+
+  pub fn crl(&self, revoked_serials: &[u64]) -> rcgen::CertificateRevocationList {
+    let now = OffsetDateTime::now_utc();
+    CertificateRevocationListParams {
+      this_update: now,
+      next_update: now + Duration::days(1),
+      crl_number: SerialNumber::from(1),
+      issuing_distribution_point: None,
+      revoked_certs: revoked_serials
+        .iter()
+        .map(|serial| RevokedCertParams {
+          serial_number: SerialNumber::from(*serial),
+          revocation_time: now,
+          reason_code: Some(RevocationReason::KeyCompromise),
+          invalidity_date: None,
+        })
+        .collect(),
+      key_identifier_method: KeyIdMethod::Sha256,
+    }.signed_by(&self.cert, &self.key).unwrap()
+  }
+
+  pub fn crl_der(&self, revoked_serials: &[u64]) -> CertificateRevocationListDer<'static> {
+    self.crl(revoked_serials).der().clone()
+  }
+}
+
+pub fn server(tls_config: rustls::ServerConfig) -> Endpoint {
+  let quic_config = QuicServerConfig::try_from(tls_config).unwrap();
+  let server_config = ServerConfig::with_crypto(Arc::new(quic_config));
+  Endpoint::server(server_config, "127.0.0.1:0".parse().unwrap()).unwrap()
+}
+
+pub fn basic_server() -> (Endpoint, CertificateDer<'static>) {
+  install_provider();
+  let (cert, key) = self_signed("localhost");
+  let tls_config = rustls::ServerConfig::builder()
+    .with_no_client_auth()
+    .with_single_cert(vec![cert.clone()], key)
+    .unwrap();
+  (server(tls_config), cert)
+}
+
+/// A server that accepts 0-RTT data from clients resuming a session.
+pub fn zero_rtt_server() -> (Endpoint, CertificateDer<'static>) {
+  install_provider();
+  let (cert, key) = self_signed("localhost");
+  let mut tls_config = rustls::ServerConfig::builder()
+    .with_no_client_auth()
+    .with_single_cert(vec![cert.clone()], key)
+    .unwrap();
+  tls_config.max_early_data_size = u32::MAX;
+  (server(tls_config), cert)
+}
+
+pub fn client(tls_config: rustls::ClientConfig) -> Endpoint {
+  let quic_config = QuicClientConfig::try_from(tls_config).unwrap();
+  let mut endpoint = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+  endpoint.set_default_client_config(ClientConfig::new(Arc::new(quic_config)));
+  endpoint
+}
+
+pub fn basic_client(server_cert: &CertificateDer<'static>) -> Endpoint {
+  let mut roots = rustls::RootCertStore::empty();
+  roots.add(server_cert.clone()).unwrap();
+  let tls_config = rustls::ClientConfig::builder()
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+  client(tls_config)
+}
+
+pub fn zero_rtt_client(server_cert: &CertificateDer<'static>) -> Endpoint {
+  let mut roots = rustls::RootCertStore::empty();
+  roots.add(server_cert.clone()).unwrap();
+  let mut tls_config = rustls::ClientConfig::builder()
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+  tls_config.enable_early_data = true;
+  client(tls_config)
+}
+
+pub fn mtls_client(
+  server_cert: &CertificateDer<'static>,
+  (cert, key): (CertificateDer<'static>, PrivateKeyDer<'static>),
+) -> Endpoint {
+  let mut roots = rustls::RootCertStore::empty();
+  roots.add(server_cert.clone()).unwrap();
+  let tls_config = rustls::ClientConfig::builder()
+    .with_root_certificates(roots)
+    .with_client_auth_cert(vec![cert], key)
+    .unwrap();
+  client(tls_config)
+}
+
+pub async fn connect(client: &Endpoint, server_addr: SocketAddr) -> Connection {
+  client.connect(server_addr, "localhost").unwrap().await.unwrap()
+}
+
+/// Admits connections with the default limits.
+pub fn admission() -> Arc<Admission> {
+  Admission::new(Limits::default())
+}
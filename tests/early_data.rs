@@ -0,0 +1,214 @@
+use std::{
+  net::SocketAddr, sync::{atomic::{AtomicU64, Ordering}, Arc}, time::{Duration, SystemTime},
+};
+use quic_rs_demo::early_data::{EarlyClient, EarlyData, Routes, ServerZeroRtt, ZeroRtt};
+use quinn::Endpoint;
+use rustls::{
+  crypto::aws_lc_rs,
+  pki_types::{CertificateDer, PrivateKeyDer, UnixTime},
+  time_provider::TimeProvider,
+};
+use tokio::sync::mpsc;
+
+mod common;
+
+fn routes() -> Routes {
+  Routes::new()
+    .route("GET ", EarlyData::Safe)
+    .route("POST ", EarlyData::Unsafe)
+    .route("POST /search", EarlyData::Safe)
+}
+
+// answers every admitted request with "ok", reporting (request, is_0rtt, admitted)
+fn serve(server: Endpoint) -> mpsc::UnboundedReceiver<(Vec<u8>, bool, bool)> {
+  let routes = Arc::new(routes());
+  let (tx, seen) = mpsc::unbounded_channel();
+  tokio::spawn(async move {
+    while let Some(incoming) = server.accept().await {
+      let (routes, tx) = (routes.clone(), tx.clone());
+      tokio::spawn(async move {
+        let Ok((conn, _)) = incoming.accept().unwrap().into_0rtt() else { return };
+        while let Ok((mut send, mut recv)) = conn.accept_bi().await {
+          let is_0rtt = recv.is_0rtt();
+          let req = recv.read_to_end(1024).await.unwrap();
+          let admitted = routes.admit(&req, is_0rtt, &mut send);
+          let _ = tx.send((req, is_0rtt, admitted));
+          if admitted {
+            send.write_all(b"ok").await.unwrap();
+            send.finish().unwrap();
+          }
+        }
+      });
+    }
+  });
+  seen
+}
+
+// connects once for a session ticket, then resumes with 0-RTT
+async fn resume(client: &Endpoint, server_addr: SocketAddr) -> EarlyClient {
+  let conn = common::connect(client, server_addr).await;
+  EarlyClient::established(conn.clone()).request(b"GET ticket\r\n").await.unwrap();
+  conn.close(0u32.into(), b"done");
+
+  let (conn, accepted) = client
+    .connect(server_addr, "localhost")
+    .unwrap()
+    .into_0rtt()
+    .unwrap_or_else(|_| panic!("no session ticket to resume with"));
+  EarlyClient::new(conn, accepted)
+}
+
+#[test]
+fn longest_prefix_wins() {
+  let routes = routes();
+  assert_eq!(routes.early_data(b"GET sample.json\r\n"), EarlyData::Safe);
+  assert_eq!(routes.early_data(b"POST /something x\r\n"), EarlyData::Unsafe);
+  assert_eq!(routes.early_data(b"POST /search x\r\n"), EarlyData::Safe);
+  assert_eq!(routes.early_data(b"DELETE x\r\n"), EarlyData::Unsafe);
+}
+
+#[tokio::test]
+async fn unsafe_request_is_resent_after_handshake() {
+  let (server, cert) = common::zero_rtt_server();
+  let server_addr = server.local_addr().unwrap();
+  let mut seen = serve(server);
+  let client = common::zero_rtt_client(&cert);
+
+  let conn = resume(&client, server_addr).await;
+  assert!(!conn.is_handshake_done());
+  let resp = conn.request(b"POST /something x\r\n").await.unwrap();
+  assert_eq!(resp, b"ok");
+  assert!(conn.is_handshake_done());
+
+  let _ticket = seen.recv().await.unwrap();
+  assert_eq!(seen.recv().await.unwrap(), (b"POST /something x\r\n".to_vec(), true, false));
+  assert_eq!(seen.recv().await.unwrap(), (b"POST /something x\r\n".to_vec(), false, true));
+}
+
+#[tokio::test]
+async fn safe_request_runs_in_0rtt() {
+  let (server, cert) = common::zero_rtt_server();
+  let server_addr = server.local_addr().unwrap();
+  let mut seen = serve(server);
+  let client = common::zero_rtt_client(&cert);
+
+  let conn = resume(&client, server_addr).await;
+  assert_eq!(conn.request(b"GET sample.json\r\n").await.unwrap(), b"ok");
+
+  let _ticket = seen.recv().await.unwrap();
+  assert_eq!(seen.recv().await.unwrap(), (b"GET sample.json\r\n".to_vec(), true, true));
+}
+
+// a clock the test can move forward to let session tickets expire
+#[derive(Debug, Default)]
+struct Clock {
+  offset_secs: AtomicU64,
+}
+
+impl Clock {
+  fn advance(&self, by: Duration) {
+    self.offset_secs.fetch_add(by.as_secs(), Ordering::Relaxed);
+  }
+}
+
+impl TimeProvider for Clock {
+  fn current_time(&self) -> Option<UnixTime> {
+    let now = SystemTime::now() + Duration::from_secs(self.offset_secs.load(Ordering::Relaxed));
+    Some(UnixTime::since_unix_epoch(now.duration_since(SystemTime::UNIX_EPOCH).ok()?))
+  }
+}
+
+fn zero_rtt_server_with(cert: &CertificateDer<'static>, key: &PrivateKeyDer<'static>) -> Endpoint {
+  let mut tls_config = rustls::ServerConfig::builder()
+    .with_no_client_auth()
+    .with_single_cert(vec![cert.clone()], key.clone_key())
+    .unwrap();
+  tls_config.max_early_data_size = u32::MAX;
+  common::server(tls_config)
+}
+
+fn client_with_clock(server_cert: &CertificateDer<'static>, clock: Arc<Clock>) -> Endpoint {
+  let mut roots = rustls::RootCertStore::empty();
+  roots.add(server_cert.clone()).unwrap();
+  let mut tls_config = rustls::ClientConfig::builder_with_details(
+    Arc::new(aws_lc_rs::default_provider()),
+    clock,
+  )
+    .with_safe_default_protocol_versions()
+    .unwrap()
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+  tls_config.enable_early_data = true;
+  common::client(tls_config)
+}
+
+// reports what the server saw of 0-RTT on each connection once it closes
+fn serve_outcomes(server: Endpoint) -> mpsc::UnboundedReceiver<ZeroRtt> {
+  let (tx, outcomes) = mpsc::unbounded_channel();
+  tokio::spawn(async move {
+    while let Some(incoming) = server.accept().await {
+      let tx = tx.clone();
+      tokio::spawn(async move {
+        let Ok((conn, _)) = incoming.accept().unwrap().into_0rtt() else { return };
+        let zero_rtt = ServerZeroRtt::new();
+        while let Ok((mut send, mut recv)) = conn.accept_bi().await {
+          zero_rtt.stream(recv.is_0rtt());
+          recv.read_to_end(1024).await.unwrap();
+          send.write_all(b"ok").await.unwrap();
+          send.finish().unwrap();
+        }
+        let _ = tx.send(zero_rtt.outcome());
+      });
+    }
+  });
+  outcomes
+}
+
+async fn get_once(client: &Endpoint, server_addr: SocketAddr) -> ZeroRtt {
+  let conn = EarlyClient::connect(client.connect(server_addr, "localhost").unwrap()).await.unwrap();
+  assert_eq!(conn.request(b"GET sample.json\r\n").await.unwrap(), b"ok");
+  let outcome = conn.zero_rtt().await;
+  conn.connection().close(0u32.into(), b"done");
+  outcome
+}
+
+#[tokio::test]
+async fn reports_0rtt_on_first_connect_resumption_and_expiry() {
+  common::install_provider();
+  let (cert, key) = common::self_signed("localhost");
+  let server = zero_rtt_server_with(&cert, &key);
+  let server_addr = server.local_addr().unwrap();
+  let mut server_outcomes = serve_outcomes(server);
+  let clock = Arc::new(Clock::default());
+  let client = client_with_clock(&cert, clock.clone());
+
+  assert_eq!(get_once(&client, server_addr).await, ZeroRtt::NotOffered);
+  assert_eq!(server_outcomes.recv().await.unwrap(), ZeroRtt::NotOffered);
+
+  assert_eq!(get_once(&client, server_addr).await, ZeroRtt::Accepted);
+  assert_eq!(server_outcomes.recv().await.unwrap(), ZeroRtt::Accepted);
+
+  // rustls gives stateful tickets a lifetime of a day
+  clock.advance(Duration::from_secs(25 * 60 * 60));
+  assert_eq!(get_once(&client, server_addr).await, ZeroRtt::NotOffered);
+  assert_eq!(server_outcomes.recv().await.unwrap(), ZeroRtt::NotOffered);
+}
+
+#[tokio::test]
+async fn server_without_the_session_rejects_0rtt() {
+  common::install_provider();
+  let (cert, key) = common::self_signed("localhost");
+  let first = zero_rtt_server_with(&cert, &key);
+  let first_addr = first.local_addr().unwrap();
+  let _first_outcomes = serve_outcomes(first);
+  // same name and certificate, but it never issued the client's ticket
+  let second = zero_rtt_server_with(&cert, &key);
+  let second_addr = second.local_addr().unwrap();
+  let mut second_outcomes = serve_outcomes(second);
+  let client = common::zero_rtt_client(&cert);
+
+  assert_eq!(get_once(&client, first_addr).await, ZeroRtt::NotOffered);
+  // the request lost with the early data is resent after the handshake
+  assert_eq!(get_once(&client, second_addr).await, ZeroRtt::Rejected);
+  assert_eq!(second_outcomes.recv().await.unwrap(), ZeroRtt::NotOffered);
+}
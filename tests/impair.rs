@@ -0,0 +1,167 @@
+use std::{fs, net::SocketAddr, path::Path, sync::Arc, time::{Duration, Instant}};
+use quic_rs_demo::{
+  files,
+  impair::{self, ImpairedSocket, Impairment},
+  metrics::Metrics,
+  qlog::{Qlog, Vantage},
+  scenarios::{self, basic},
+};
+use quinn::{udp::Transmit, AsyncUdpSocket, Endpoint};
+use tokio::{net::UdpSocket, time::timeout};
+
+mod common;
+
+fn send(socket: &ImpairedSocket, destination: SocketAddr, contents: &[u8]) {
+  let transmit = Transmit { destination, ecn: None, contents, segment_size: None, src_ip: None };
+  socket.try_send(&transmit).unwrap();
+}
+
+// what arrives at `receiver` until nothing has for a while
+async fn received(receiver: &UdpSocket) -> Vec<Vec<u8>> {
+  let mut datagrams = Vec::new();
+  let mut buf = [0u8; 2048];
+  while let Ok(Ok(len)) = timeout(Duration::from_millis(200), receiver.recv(&mut buf)).await {
+    datagrams.push(buf[..len].to_vec());
+  }
+  datagrams
+}
+
+// sends datagrams numbered 0 to 99 through a socket impaired with `seed`
+async fn survivors(impairment: Impairment, seed: u64) -> Vec<u32> {
+  let receiver = UdpSocket::bind(common::any_port()).await.unwrap();
+  let socket = ImpairedSocket::bind(common::any_port(), impairment, seed).unwrap();
+  for i in 0u32..100 {
+    send(&socket, receiver.local_addr().unwrap(), &i.to_be_bytes());
+  }
+  received(&receiver).await
+    .into_iter()
+    .map(|datagram| u32::from_be_bytes(datagram.try_into().unwrap()))
+    .collect()
+}
+
+#[tokio::test]
+async fn same_seed_loses_the_same_datagrams() {
+  let lossy = Impairment { loss: 0.3, ..Default::default() };
+  let first = survivors(lossy.clone(), 42).await;
+  assert!((40..95).contains(&first.len()), "{} of 100 arrived", first.len());
+  assert_eq!(survivors(lossy.clone(), 42).await, first);
+  assert_ne!(survivors(lossy, 43).await, first);
+}
+
+#[tokio::test]
+async fn reordering_and_duplication_keep_every_datagram() {
+  let impairment = Impairment { reorder: 0.3, duplicate: 0.2, ..Default::default() };
+  let arrived = survivors(impairment, 7).await;
+  let mut sorted = arrived.clone();
+  sorted.sort();
+  assert_ne!(arrived, sorted, "nothing was reordered");
+  sorted.dedup();
+  assert_eq!(sorted, (0..100).collect::<Vec<_>>());
+  assert!(arrived.len() > 100, "nothing was duplicated");
+}
+
+#[tokio::test]
+async fn latency_and_bandwidth_delay_datagrams() {
+  let receiver = UdpSocket::bind(common::any_port()).await.unwrap();
+  let impairment = Impairment { latency: Duration::from_millis(50), bandwidth: Some(10_000), ..Default::default() };
+  let socket = ImpairedSocket::bind(common::any_port(), impairment, 0).unwrap();
+  let started = Instant::now();
+  // 1000 bytes each take 100ms on a 10kB/s link
+  send(&socket, receiver.local_addr().unwrap(), &[0u8; 1000]);
+  send(&socket, receiver.local_addr().unwrap(), &[1u8; 1000]);
+
+  let mut buf = [0u8; 2048];
+  receiver.recv(&mut buf).await.unwrap();
+  assert!(started.elapsed() >= Duration::from_millis(150), "first arrived after {:?}", started.elapsed());
+  receiver.recv(&mut buf).await.unwrap();
+  assert!(started.elapsed() >= Duration::from_millis(250), "second arrived after {:?}", started.elapsed());
+}
+
+#[tokio::test]
+async fn a_full_queue_drops_what_is_sent() {
+  let receiver = UdpSocket::bind(common::any_port()).await.unwrap();
+  let impairment = Impairment { bandwidth: Some(100_000), queue: 2500, ..Default::default() };
+  let socket = ImpairedSocket::bind(common::any_port(), impairment, 0).unwrap();
+  for i in 0u8..5 {
+    send(&socket, receiver.local_addr().unwrap(), &[i; 1000]);
+  }
+  let arrived: Vec<u8> = received(&receiver).await.iter().map(|datagram| datagram[0]).collect();
+  assert_eq!(arrived, [0, 1]);
+  assert_eq!(socket.stats().overflowed, 3);
+
+  // the queue drains at the link's pace
+  send(&socket, receiver.local_addr().unwrap(), &[5; 1000]);
+  assert_eq!(received(&receiver).await.len(), 1);
+}
+
+#[tokio::test]
+async fn datagrams_over_the_mtu_are_dropped() {
+  let receiver = UdpSocket::bind(common::any_port()).await.unwrap();
+  let socket = ImpairedSocket::bind(common::any_port(), Impairment { mtu: Some(1200), ..Default::default() }, 0).unwrap();
+  send(&socket, receiver.local_addr().unwrap(), &[0u8; 1201]);
+  send(&socket, receiver.local_addr().unwrap(), &[1u8; 1200]);
+  assert_eq!(received(&receiver).await, vec![vec![1u8; 1200]]);
+  assert_eq!(socket.stats().too_big, 1);
+
+  socket.set(Impairment::default());
+  send(&socket, receiver.local_addr().unwrap(), &[0u8; 1201]);
+  assert_eq!(received(&receiver).await.len(), 1);
+}
+
+// a basic server and a client, each sending through its own impaired socket
+async fn impaired_pair(
+  cert_dir: &Path,
+  root: &Path,
+  impairment: Impairment,
+) -> (Endpoint, SocketAddr, Arc<ImpairedSocket>, Arc<ImpairedSocket>) {
+  let (tls_config, _) = basic::server_tls(cert_dir).unwrap();
+  let server_socket = ImpairedSocket::bind(common::any_port(), impairment.clone(), 1).unwrap();
+  let server = impair::endpoint(server_socket.clone(), Some(scenarios::server_config(tls_config).unwrap())).unwrap();
+  let server_addr = server.local_addr().unwrap();
+  let qlog = Qlog::disabled(Vantage::Server);
+  tokio::spawn(basic::serve(server, common::admission(), root.to_path_buf(), qlog, Metrics::new("test")));
+
+  let client_socket = ImpairedSocket::bind(common::any_port(), impairment, 2).unwrap();
+  let mut client = impair::endpoint(client_socket.clone(), None).unwrap();
+  client.set_default_client_config(scenarios::client_config(scenarios::client_tls(cert_dir).unwrap()).unwrap());
+  (client, server_addr, client_socket, server_socket)
+}
+
+#[tokio::test]
+async fn transfer_completes_over_a_bad_network() {
+  let (cert_dir, root) = (common::cert_dir(), tempfile::tempdir().unwrap());
+  let big: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+  fs::write(root.path().join("big.bin"), &big).unwrap();
+
+  let impairment = Impairment {
+    loss: 0.1,
+    latency: Duration::from_millis(10),
+    jitter: Duration::from_millis(5),
+    reorder: 0.1,
+    duplicate: 0.05,
+    ..Default::default()
+  };
+  let (client, server_addr, client_socket, server_socket) = impaired_pair(cert_dir.path(), root.path(), impairment).await;
+  let conn = timeout(Duration::from_secs(10), scenarios::connect(&client, server_addr)).await.unwrap().unwrap();
+  let resp = timeout(Duration::from_secs(20), files::get(&conn, "big.bin")).await.unwrap().unwrap();
+  assert!(resp == big, "got {} bytes back", resp.len());
+  assert!(client_socket.stats().lost > 0);
+  assert!(server_socket.stats().lost > 0);
+}
+
+#[tokio::test]
+async fn lowering_the_mtu_mid_connection_still_delivers() {
+  let (cert_dir, root) = (common::cert_dir(), tempfile::tempdir().unwrap());
+  let big = vec![7u8; 100_000];
+  fs::write(root.path().join("big.bin"), &big).unwrap();
+
+  let (client, server_addr, _, server_socket) = impaired_pair(cert_dir.path(), root.path(), Impairment::default()).await;
+  let conn = scenarios::connect(&client, server_addr).await.unwrap();
+  assert!(files::get(&conn, "big.bin").await.unwrap() == big);
+
+  // Quinn has probed its way past 1200 bytes by now, and loses everything bigger
+  server_socket.set(Impairment { mtu: Some(1200), ..Default::default() });
+  let resp = timeout(Duration::from_secs(20), files::get(&conn, "big.bin")).await.unwrap().unwrap();
+  assert!(resp == big);
+  assert!(server_socket.stats().too_big > 0);
+}
@@ -0,0 +1,53 @@
+use std::{env, fs};
+use quic_rs_demo::keylog;
+
+mod common;
+
+// one test, since the key log is chosen from the process environment
+#[tokio::test]
+async fn secrets_are_logged_only_with_sslkeylogfile() {
+  common::install_provider();
+  env::remove_var("SSLKEYLOGFILE");
+  assert!(!keylog::from_env().will_log("CLIENT_HANDSHAKE_TRAFFIC_SECRET"));
+
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("keys.log");
+  env::set_var("SSLKEYLOGFILE", &path);
+
+  let (cert, key) = common::self_signed("localhost");
+  let mut server_tls = rustls::ServerConfig::builder()
+    .with_no_client_auth()
+    .with_single_cert(vec![cert.clone()], key)
+    .unwrap();
+  server_tls.key_log = keylog::from_env();
+  let server = common::server(server_tls);
+  let addr = server.local_addr().unwrap();
+  tokio::spawn(async move {
+    let conn = server.accept().await.unwrap().await.unwrap();
+    conn.closed().await;
+  });
+
+  let mut roots = rustls::RootCertStore::empty();
+  roots.add(cert).unwrap();
+  let mut client_tls = rustls::ClientConfig::builder()
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+  client_tls.key_log = keylog::from_env();
+  let client = common::client(client_tls);
+  let conn = common::connect(&client, addr).await;
+  conn.close(0u32.into(), b"done");
+  client.wait_idle().await;
+
+  // NSS key log format: <label> <client random> <secret>, from both ends
+  let log = fs::read_to_string(&path).unwrap();
+  let labels: Vec<&str> = log.lines().map(|line| line.split(' ').next().unwrap()).collect();
+  for label in [
+    "CLIENT_HANDSHAKE_TRAFFIC_SECRET",
+    "SERVER_HANDSHAKE_TRAFFIC_SECRET",
+    "CLIENT_TRAFFIC_SECRET_0",
+    "SERVER_TRAFFIC_SECRET_0",
+  ] {
+    assert_eq!(labels.iter().filter(|&&l| l == label).count(), 2, "{}", log);
+  }
+  env::remove_var("SSLKEYLOGFILE");
+}
@@ -0,0 +1,71 @@
+use std::{collections::BTreeMap, fs, net::SocketAddr, path::Path, time::Duration};
+use quic_rs_demo::load::{self, Limit, LoadConfig};
+use tempfile::TempDir;
+
+mod common;
+
+// serves a.txt and b.txt
+fn server(cert_dir: &Path) -> (SocketAddr, TempDir) {
+  let root = tempfile::tempdir().unwrap();
+  fs::write(root.path().join("a.txt"), b"aaaa").unwrap();
+  fs::write(root.path().join("b.txt"), b"bbbbbbbb").unwrap();
+  (common::file_server(cert_dir, root.path()), root)
+}
+
+fn config(files: &[&str], limit: Limit) -> LoadConfig {
+  LoadConfig {
+    connections: 3,
+    streams: 2,
+    files: files.iter().map(|file| file.to_string()).collect(),
+    limit,
+  }
+}
+
+#[tokio::test]
+async fn request_count_asks_for_each_file_in_turn() {
+  let cert_dir = common::cert_dir();
+  let (addr, _root) = server(cert_dir.path());
+  let config = config(&["a.txt", "b.txt", "missing.txt"], Limit::Requests(30));
+
+  let report = load::run(&common::file_client(cert_dir.path()), addr, &config).await.unwrap();
+  assert_eq!(report.requests, 20);
+  assert_eq!(report.failed, 10);
+  assert_eq!(report.bytes, 10 * 4 + 10 * 8);
+  let files = BTreeMap::from([("a.txt".to_string(), 10), ("b.txt".to_string(), 10)]);
+  assert_eq!(report.files, files);
+  let errors = BTreeMap::from([("server failed on missing.txt".to_string(), 10)]);
+  assert_eq!(report.errors, errors);
+  assert!(report.latency.p50_ms > 0.0 && report.latency.p50_ms <= report.latency.max_ms);
+  assert!(report.handshake.max_ms > 0.0);
+
+  let json = serde_json::to_value(&report).unwrap();
+  assert_eq!(json["requests"], 20);
+  assert!(json["latency"]["p99_ms"].is_f64());
+}
+
+#[tokio::test]
+async fn duration_limits_the_run() {
+  let cert_dir = common::cert_dir();
+  let (addr, _root) = server(cert_dir.path());
+  let config = config(&["a.txt"], Limit::Duration(Duration::from_millis(300)));
+
+  let report = load::run(&common::file_client(cert_dir.path()), addr, &config).await.unwrap();
+  assert!(report.elapsed_secs >= 0.3);
+  assert!(report.requests > 0);
+  assert_eq!(report.failed, 0);
+  assert!(report.requests_per_sec > 0.0);
+}
+
+#[tokio::test]
+async fn failed_handshakes_are_counted() {
+  let (server_certs, client_certs) = (common::cert_dir(), common::cert_dir());
+  let (addr, _root) = server(server_certs.path());
+  let config = config(&["a.txt"], Limit::Requests(10));
+
+  let report = load::run(&common::file_client(client_certs.path()), addr, &config).await.unwrap();
+  assert_eq!(report.requests, 0);
+  assert_eq!(report.errors.len(), 1);
+  let (error, count) = report.errors.iter().next().unwrap();
+  assert!(error.starts_with("handshake failed"), "{}", error);
+  assert_eq!(*count, 3);
+}
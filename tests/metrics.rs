@@ -0,0 +1,153 @@
+use std::{sync::Arc, time::{Duration, Instant}};
+use quic_rs_demo::metrics::Metrics;
+use quinn::Endpoint;
+use rustls::server::WebPkiClientVerifier;
+use tokio::{
+  io::{AsyncReadExt, AsyncWriteExt},
+  net::TcpStream,
+  time::timeout,
+};
+
+mod common;
+use common::TestCa;
+
+// the value of the sample `name{...}` in the text exposition
+fn value(text: &str, name: &str) -> f64 {
+  let prefix = format!("{}{{", name);
+  let line = text
+    .lines()
+    .find(|line| line.starts_with(&prefix))
+    .unwrap_or_else(|| panic!("{} missing from\n{}", name, text));
+  line.rsplit(' ').next().unwrap().parse().unwrap()
+}
+
+// accepts connections the way the example servers do, echoing each stream
+fn serve(endpoint: Endpoint, metrics: Arc<Metrics>) {
+  tokio::spawn(async move {
+    while let Some(incoming) = endpoint.accept().await {
+      let metrics = metrics.clone();
+      tokio::spawn(async move {
+        let started = Instant::now();
+        let Ok(conn) = incoming.await.inspect_err(|e| metrics.refused(Some(e))) else { return };
+        metrics.accepted(&conn, started.elapsed());
+        while let Ok((mut send, mut recv)) = conn.accept_bi().await {
+          tokio::spawn(metrics.stream(async move {
+            let req = recv.read_to_end(1024).await?;
+            send.write_all(&req).await?;
+            send.finish()?;
+            anyhow::Ok(())
+          }));
+        }
+      });
+    }
+  });
+}
+
+async fn eventually(metrics: &Metrics, name: &str, expected: f64) {
+  let waited = timeout(Duration::from_secs(5), async {
+    while value(&metrics.render(), name) != expected {
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+  }).await;
+  assert!(waited.is_ok(), "{} never reached {}:\n{}", name, expected, metrics.render());
+}
+
+#[tokio::test]
+async fn counts_connections_and_their_traffic() {
+  let (server, cert) = common::basic_server();
+  let addr = server.local_addr().unwrap();
+  let metrics = Metrics::new("test-server");
+  serve(server, metrics.clone());
+  let client = common::basic_client(&cert);
+
+  let conn = common::connect(&client, addr).await;
+  let (mut send, mut recv) = conn.open_bi().await.unwrap();
+  send.write_all(b"ping").await.unwrap();
+  send.finish().unwrap();
+  assert_eq!(recv.read_to_end(1024).await.unwrap(), b"ping");
+
+  let text = metrics.render();
+  assert!(text.contains("quic_connections_accepted_total{endpoint=\"test-server\"} 1\n"), "{}", text);
+  assert_eq!(value(&text, "quic_active_connections"), 1.0);
+  assert_eq!(value(&text, "quic_handshake_duration_seconds_count"), 1.0);
+  assert!(value(&text, "quic_sent_bytes_total") > 0.0);
+  assert!(value(&text, "quic_received_bytes_total") > 0.0);
+  assert_eq!(value(&text, "quic_rtt_seconds_count"), 0.0);
+
+  conn.close(0u32.into(), b"done");
+  eventually(&metrics, "quic_active_connections", 0.0).await;
+  let text = metrics.render();
+  assert_eq!(value(&text, "quic_rtt_seconds_count"), 1.0);
+  assert_eq!(value(&text, "quic_congestion_window_bytes_count"), 1.0);
+  // traffic of closed connections stays counted
+  assert!(value(&text, "quic_sent_bytes_total") > 0.0);
+  assert_eq!(value(&text, "quic_active_streams"), 0.0);
+}
+
+#[tokio::test]
+async fn active_streams_are_counted_until_dropped() {
+  let metrics = Metrics::new("test-server");
+  let stream = metrics.stream(std::future::pending::<()>());
+  assert_eq!(value(&metrics.render(), "quic_active_streams"), 1.0);
+  drop(stream);
+  assert_eq!(value(&metrics.render(), "quic_active_streams"), 0.0);
+}
+
+#[tokio::test]
+async fn unknown_client_certificate_is_an_auth_failure() {
+  common::install_provider();
+  let ca = TestCa::new("test-ca");
+  let (cert, key) = common::self_signed("localhost");
+  let verifier = WebPkiClientVerifier::builder(ca.roots()).build().unwrap();
+  let tls_config = rustls::ServerConfig::builder()
+    .with_client_cert_verifier(verifier)
+    .with_single_cert(vec![cert.clone()], key)
+    .unwrap();
+  let server = common::server(tls_config);
+  let addr = server.local_addr().unwrap();
+  let metrics = Metrics::new("test-server");
+  serve(server, metrics.clone());
+
+  let stranger = TestCa::new("other-ca");
+  let client = common::mtls_client(&cert, stranger.issue("client", 1));
+  let _ = client.connect(addr, "localhost").unwrap().await;
+  eventually(&metrics, "quic_connections_refused_total", 1.0).await;
+  let text = metrics.render();
+  assert_eq!(value(&text, "quic_auth_failures_total"), 1.0);
+  assert_eq!(value(&text, "quic_connections_accepted_total"), 0.0);
+}
+
+#[tokio::test]
+async fn serves_prometheus_text_over_http() {
+  let metrics = Metrics::new("test-server");
+  metrics.auth_failure();
+  let (addr, _task) = metrics.serve("127.0.0.1:0".parse().unwrap()).await.unwrap();
+
+  let get = |path: &'static str| async move {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes()).await.unwrap();
+    let mut resp = String::new();
+    stream.read_to_string(&mut resp).await.unwrap();
+    resp
+  };
+  let resp = get("/metrics").await;
+  assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{}", resp);
+  assert!(resp.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+  let (_, body) = resp.split_once("\r\n\r\n").unwrap();
+  assert!(body.contains("# TYPE quic_auth_failures_total counter\n"));
+  assert_eq!(value(body, "quic_auth_failures_total"), 1.0);
+  assert!(body.contains("quic_handshake_duration_seconds_bucket{endpoint=\"test-server\",le=\"+Inf\"} 0\n"));
+
+  assert!(get("/").await.starts_with("HTTP/1.1 404 Not Found\r\n"));
+}
+
+#[tokio::test]
+async fn scrapers_that_send_nothing_are_dropped() {
+  let metrics = Metrics::new("test-server");
+  let (addr, _task) = metrics.serve("127.0.0.1:0".parse().unwrap()).await.unwrap();
+  let mut stream = TcpStream::connect(addr).await.unwrap();
+  let mut resp = Vec::new();
+  let read = timeout(Duration::from_secs(10), stream.read_to_end(&mut resp)).await;
+  assert!(read.is_ok(), "the server kept waiting for a request");
+  assert!(resp.is_empty());
+}
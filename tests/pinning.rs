@@ -0,0 +1,60 @@
+use std::sync::Arc;
+use quic_rs_demo::pinning::{pin_for_cert, PinnedServerVerifier};
+use quinn::{ConnectionError, Endpoint};
+use rustls::pki_types::CertificateDer;
+
+mod common;
+
+const BACKUP_PIN: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+
+fn pinned_client(pins: &[&str]) -> Endpoint {
+  let verifier = PinnedServerVerifier::new(pins.iter().copied()).unwrap();
+  let tls_config = rustls::ClientConfig::builder()
+    .dangerous()
+    .with_custom_certificate_verifier(Arc::new(verifier))
+    .with_no_client_auth();
+  common::client(tls_config)
+}
+
+async fn connect(server: &Endpoint, pins: &[&str], server_name: &str) -> Result<quinn::Connection, ConnectionError> {
+  let acceptor = server.clone();
+  tokio::spawn(async move {
+    if let Some(incoming) = acceptor.accept().await {
+      let _ = incoming.await;
+    }
+  });
+  let client = pinned_client(pins);
+  client.connect(server.local_addr().unwrap(), server_name).unwrap().await
+}
+
+fn pin(cert: &CertificateDer<'_>) -> String {
+  pin_for_cert(cert).unwrap()
+}
+
+#[tokio::test]
+async fn pinned_key_is_trusted_alongside_a_backup_pin() {
+  let (server, cert) = common::basic_server();
+  assert!(connect(&server, &[BACKUP_PIN, &pin(&cert)], "localhost").await.is_ok());
+}
+
+#[tokio::test]
+async fn unpinned_key_is_rejected() {
+  let (server, _) = common::basic_server();
+  let err = connect(&server, &[BACKUP_PIN], "localhost").await.unwrap_err();
+  assert!(err.to_string().contains("ApplicationVerificationFailure"), "unexpected error: {}", err);
+}
+
+#[tokio::test]
+async fn hostname_is_still_checked() {
+  let (server, cert) = common::basic_server();
+  let err = connect(&server, &[&pin(&cert)], "example.com").await.unwrap_err();
+  assert!(err.to_string().contains("not valid for name"), "unexpected error: {}", err);
+}
+
+#[test]
+fn malformed_pins_are_refused() {
+  common::install_provider();
+  assert!(PinnedServerVerifier::from_list("").is_err());
+  assert!(PinnedServerVerifier::from_list("not-a-hash").is_err());
+  assert_eq!(PinnedServerVerifier::from_list(&format!(" {} ,", BACKUP_PIN)).unwrap().pins(), [BACKUP_PIN]);
+}
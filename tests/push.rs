@@ -0,0 +1,68 @@
+use std::{fs, time::{Duration, SystemTime}};
+use quic_rs_demo::push::{notify, FileWatcher, Notification, PushListener};
+use quinn::Connection;
+use tokio::{sync::mpsc, time::timeout};
+
+mod common;
+
+// a connected pair, the server's end first
+async fn pair() -> (Connection, Connection) {
+  let (server, cert) = common::basic_server();
+  let server_addr = server.local_addr().unwrap();
+  let accepted = tokio::spawn(async move { server.accept().await.unwrap().await.unwrap() });
+  let client = common::connect(&common::basic_client(&cert), server_addr).await;
+  (accepted.await.unwrap(), client)
+}
+
+// runs a listener on `conn`, forwarding `CHANGED` and `HELLO` notifications
+fn listen(conn: &Connection) -> mpsc::UnboundedReceiver<Notification> {
+  let (tx, rx) = mpsc::unbounded_channel();
+  let hello = tx.clone();
+  let listener = PushListener::new()
+    .on("CHANGED", move |n| { let _ = tx.send(n.clone()); })
+    .on("HELLO", move |n| { let _ = hello.send(n.clone()); });
+  tokio::spawn(listener.run(conn.clone()));
+  rx
+}
+
+async fn next(rx: &mut mpsc::UnboundedReceiver<Notification>) -> Notification {
+  timeout(Duration::from_secs(5), rx.recv()).await.expect("no notification in time").unwrap()
+}
+
+#[test]
+fn notifications_round_trip() {
+  let notification = Notification::new("CHANGED", "a b.txt");
+  let line = notification.encode();
+  assert_eq!(line, b"CHANGED a b.txt\r\n");
+  assert_eq!(Notification::parse(&line[..line.len() - 2]).unwrap(), notification);
+  assert!(Notification::parse(b"CHANGED").is_err());
+  assert!(Notification::parse(b" body").is_err());
+}
+
+#[tokio::test]
+async fn notify_reaches_the_handler_of_its_kind() {
+  let (server, client) = pair().await;
+  let mut pushed = listen(&client);
+  notify(&server, &Notification::new("UNKNOWN", "dropped")).await.unwrap();
+  notify(&server, &Notification::new("HELLO", "world")).await.unwrap();
+  assert_eq!(next(&mut pushed).await, Notification::new("HELLO", "world"));
+}
+
+#[tokio::test]
+async fn watcher_pushes_changes_to_subscribers() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("watched.txt");
+  fs::write(&path, b"one").unwrap();
+  let (server, client) = pair().await;
+  let mut pushed = listen(&client);
+
+  let watcher = FileWatcher::new();
+  watcher.subscribe(&server, &path);
+  let watching = watcher.watch(Duration::from_millis(20));
+  // set explicitly, filesystem timestamps can be too coarse to tell writes apart
+  let file = fs::File::options().write(true).open(&path).unwrap();
+  file.set_modified(SystemTime::now() + Duration::from_secs(1)).unwrap();
+
+  assert_eq!(next(&mut pushed).await, Notification::new("CHANGED", "watched.txt"));
+  watching.abort();
+}
@@ -0,0 +1,116 @@
+use std::{fs, path::Path, time::Duration};
+use quic_rs_demo::qlog::{Qlog, Vantage};
+use serde_json::Value;
+
+mod common;
+
+// JSON-SEQ: records start with 0x1e and end with a newline
+fn read_trace(dir: &Path) -> Vec<Value> {
+  let entries: Vec<_> = fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).collect();
+  assert_eq!(entries.len(), 1, "{:?}", entries);
+  let text = fs::read_to_string(&entries[0]).unwrap();
+  text
+    .split('\u{1e}')
+    .filter(|record| !record.is_empty())
+    .map(|record| serde_json::from_str(record.trim_end()).unwrap())
+    .collect()
+}
+
+fn names(records: &[Value]) -> Vec<&str> {
+  records.iter().filter_map(|r| r["name"].as_str()).collect()
+}
+
+#[tokio::test]
+async fn traces_a_connection_and_its_streams() {
+  common::install_provider();
+  let dir = tempfile::tempdir().unwrap();
+  let qlog = Qlog::new(dir.path(), Vantage::Server).unwrap();
+  let (server, cert) = common::basic_server();
+  let addr = server.local_addr().unwrap();
+  let client = common::basic_client(&cert);
+
+  let accept = tokio::spawn(async move {
+    let conn = server.accept().await.unwrap().await.unwrap();
+    let trace = qlog.trace(&conn);
+    let (mut send, mut recv) = conn.accept_bi().await.unwrap();
+    trace.stream(send.id(), async {
+      let req = recv.read_to_end(1024).await?;
+      send.write_all(&req).await?;
+      send.finish()?;
+      Ok(())
+    }).await.unwrap();
+    conn.closed().await;
+    // the sampler writes the close before it stops
+    tokio::time::sleep(Duration::from_millis(50)).await;
+  });
+
+  let conn = common::connect(&client, addr).await;
+  let (mut send, mut recv) = conn.open_bi().await.unwrap();
+  send.write_all(b"ping").await.unwrap();
+  send.finish().unwrap();
+  assert_eq!(recv.read_to_end(1024).await.unwrap(), b"ping");
+  conn.close(7u32.into(), b"done");
+  accept.await.unwrap();
+
+  let records = read_trace(dir.path());
+  assert_eq!(records[0]["qlog_format"], "JSON-SEQ");
+  assert_eq!(records[0]["trace"]["vantage_point"]["type"], "server");
+  let names = names(&records);
+  assert_eq!(names[0], "connectivity:connection_started");
+  assert_eq!(names.last(), Some(&"connectivity:connection_closed"));
+  for name in ["transport:datagrams_sent", "transport:datagrams_received", "recovery:metrics_updated"] {
+    assert!(names.contains(&name), "{} missing from {:?}", name, names);
+  }
+
+  let streams: Vec<&Value> = records.iter().filter(|r| r["name"] == "transport:stream_state_updated").collect();
+  assert_eq!(streams.len(), 2);
+  assert_eq!(streams[0]["data"]["new"], "open");
+  assert_eq!(streams[1]["data"]["new"], "closed");
+  assert_eq!(streams[1]["data"]["stream_type"], "bidirectional");
+
+  let closed = &records.last().unwrap()["data"];
+  assert_eq!(closed["owner"], "remote");
+  assert_eq!(closed["application_code"], 7);
+
+  // event times are relative to the start of the trace and never go back
+  let times: Vec<f64> = records[1..].iter().map(|r| r["time"].as_f64().unwrap()).collect();
+  assert!(times.windows(2).all(|w| w[0] <= w[1]), "{:?}", times);
+}
+
+#[tokio::test]
+async fn disabled_qlog_writes_nothing() {
+  common::install_provider();
+  let qlog = Qlog::disabled(Vantage::Client);
+  assert!(!qlog.is_enabled());
+  let (server, cert) = common::basic_server();
+  let addr = server.local_addr().unwrap();
+  tokio::spawn(async move {
+    let _conn = server.accept().await.unwrap().await.unwrap();
+    std::future::pending::<()>().await;
+  });
+  let client = common::basic_client(&cert);
+  let conn = common::connect(&client, addr).await;
+  let trace = qlog.trace(&conn);
+  trace.event("transport:ignored", serde_json::json!({}));
+  let stream = conn.open_uni().await.unwrap();
+  let result: anyhow::Result<()> = trace.stream(stream.id(), async { anyhow::bail!("failed") }).await;
+  assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn dropping_the_trace_lets_the_connection_close() {
+  let dir = tempfile::tempdir().unwrap();
+  let qlog = Qlog::new(dir.path(), Vantage::Client).unwrap();
+  let (server, cert) = common::basic_server();
+  let addr = server.local_addr().unwrap();
+  let accepted = tokio::spawn(async move { server.accept().await.unwrap().await.unwrap() });
+  let client = common::basic_client(&cert);
+  let conn = common::connect(&client, addr).await;
+  let trace = qlog.trace(&conn);
+  let server_conn = accepted.await.unwrap();
+
+  drop(trace);
+  drop(conn);
+  let closed = tokio::time::timeout(Duration::from_secs(2), server_conn.closed()).await;
+  assert!(closed.is_ok(), "the connection outlived its handles");
+}
@@ -0,0 +1,155 @@
+use std::{fs, sync::Arc, time::Duration};
+use quic_rs_demo::revocation::{load_crls, ReloadingClientVerifier, RevocationPolicy};
+use quinn::{ConnectionError, Endpoint};
+use rustls::pki_types::CertificateDer;
+use tokio::{sync::mpsc, time::timeout};
+
+mod common;
+use common::TestCa;
+
+struct Server {
+  endpoint: Endpoint,
+  cert: CertificateDer<'static>,
+  handshakes: mpsc::UnboundedReceiver<Result<quinn::Connection, ConnectionError>>,
+}
+
+fn server(verifier: Arc<ReloadingClientVerifier>) -> Server {
+  common::install_provider();
+  let (cert, key) = common::self_signed("localhost");
+  let tls_config = rustls::ServerConfig::builder()
+    .with_client_cert_verifier(verifier)
+    .with_single_cert(vec![cert.clone()], key)
+    .unwrap();
+  let endpoint = common::server(tls_config);
+  let (tx, handshakes) = mpsc::unbounded_channel();
+  let acceptor = endpoint.clone();
+  tokio::spawn(async move {
+    while let Some(incoming) = acceptor.accept().await {
+      let _ = tx.send(incoming.await);
+    }
+  });
+  Server { endpoint, cert, handshakes }
+}
+
+// the server decides after the client already considers itself connected,
+// so the verdict is read on the server side
+async fn handshake(server: &mut Server, ca: &TestCa, serial: u64) -> Result<quinn::Connection, ConnectionError> {
+  let client = common::mtls_client(&server.cert, ca.issue("client", serial));
+  let connecting = client.connect(server.endpoint.local_addr().unwrap(), "localhost").unwrap();
+  tokio::spawn(async move {
+    let _client = client;
+    let _ = connecting.await;
+  });
+  timeout(Duration::from_secs(5), server.handshakes.recv())
+    .await
+    .expect("handshake did not finish in time")
+    .unwrap()
+}
+
+fn verifier(ca: &TestCa, crl_path: &std::path::Path, policy: RevocationPolicy) -> Arc<ReloadingClientVerifier> {
+  Arc::new(ReloadingClientVerifier::new(ca.roots(), crl_path, policy).unwrap())
+}
+
+#[tokio::test]
+async fn revoked_client_is_rejected() {
+  let ca = TestCa::new("test-ca");
+  let dir = tempfile::tempdir().unwrap();
+  let crl_path = dir.path().join("client.crl");
+  fs::write(&crl_path, ca.crl_der(&[2])).unwrap();
+  let mut server = server(verifier(&ca, &crl_path, RevocationPolicy::RevokedOnly));
+
+  assert!(handshake(&mut server, &ca, 1).await.is_ok());
+  let err = handshake(&mut server, &ca, 2).await.unwrap_err();
+  assert!(err.to_string().contains("Revoked"), "unexpected error: {}", err);
+}
+
+#[tokio::test]
+async fn reload_picks_up_new_revocations() {
+  let ca = TestCa::new("test-ca");
+  let dir = tempfile::tempdir().unwrap();
+  let crl_path = dir.path().join("client.crl");
+  fs::write(&crl_path, ca.crl_der(&[])).unwrap();
+  let verifier = verifier(&ca, &crl_path, RevocationPolicy::RevokedOnly);
+  let mut server = server(verifier.clone());
+
+  assert!(handshake(&mut server, &ca, 7).await.is_ok());
+  fs::write(&crl_path, ca.crl_der(&[7])).unwrap();
+  assert_eq!(verifier.reload().unwrap(), 1);
+  assert!(handshake(&mut server, &ca, 7).await.is_err());
+}
+
+#[tokio::test]
+async fn failed_reload_keeps_previous_crls() {
+  let ca = TestCa::new("test-ca");
+  let dir = tempfile::tempdir().unwrap();
+  let crl_path = dir.path().join("client.crl");
+  fs::write(&crl_path, ca.crl_der(&[3])).unwrap();
+  let verifier = verifier(&ca, &crl_path, RevocationPolicy::FailIfUnknown);
+  let mut server = server(verifier.clone());
+
+  fs::remove_file(&crl_path).unwrap();
+  assert!(verifier.reload().is_err());
+  assert!(handshake(&mut server, &ca, 3).await.is_err());
+  assert!(handshake(&mut server, &ca, 4).await.is_ok());
+}
+
+#[tokio::test]
+async fn unknown_status_depends_on_policy() {
+  // the only CRL comes from a CA that did not issue the client cert
+  let ca = TestCa::new("test-ca");
+  let other_ca = TestCa::new("other-ca");
+  let dir = tempfile::tempdir().unwrap();
+  let crl_path = dir.path().join("other.crl");
+  fs::write(&crl_path, other_ca.crl_der(&[1])).unwrap();
+
+  let mut lenient = server(verifier(&ca, &crl_path, RevocationPolicy::RevokedOnly));
+  assert!(handshake(&mut lenient, &ca, 1).await.is_ok());
+
+  let mut strict = server(verifier(&ca, &crl_path, RevocationPolicy::FailIfUnknown));
+  let err = handshake(&mut strict, &ca, 1).await.unwrap_err();
+  assert!(err.to_string().contains("UnknownRevocationStatus"), "unexpected error: {}", err);
+}
+
+#[test]
+fn fail_if_unknown_requires_a_crl() {
+  common::install_provider();
+  let ca = TestCa::new("test-ca");
+  let dir = tempfile::tempdir().unwrap();
+  let missing = dir.path().join("missing.crl");
+  assert!(ReloadingClientVerifier::new(ca.roots(), &missing, RevocationPolicy::FailIfUnknown).is_err());
+  assert!(ReloadingClientVerifier::new(ca.roots(), &missing, RevocationPolicy::RevokedOnly).is_ok());
+}
+
+#[test]
+fn loads_der_and_pem_crls_from_a_dir() {
+  let ca = TestCa::new("test-ca");
+  let dir = tempfile::tempdir().unwrap();
+  fs::write(dir.path().join("a.crl"), ca.crl_der(&[1])).unwrap();
+  fs::write(dir.path().join("b.pem"), ca.crl(&[2]).pem().unwrap()).unwrap();
+  fs::write(dir.path().join(".c.crl.part"), b"half a CRL").unwrap();
+
+  // the hidden part file is skipped
+  assert_eq!(load_crls(dir.path()).unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn optional_client_auth_still_verifies_presented_certs() {
+  let ca = TestCa::new("test-ca");
+  let dir = tempfile::tempdir().unwrap();
+  let crl_path = dir.path().join("client.crl");
+  fs::write(&crl_path, ca.crl_der(&[2])).unwrap();
+  let verifier = ReloadingClientVerifier::new(ca.roots(), &crl_path, RevocationPolicy::RevokedOnly)
+    .unwrap()
+    .allow_unauthenticated();
+  let mut server = server(Arc::new(verifier));
+
+  let anonymous = common::basic_client(&server.cert);
+  let connecting = anonymous.connect(server.endpoint.local_addr().unwrap(), "localhost").unwrap();
+  let client_conn = connecting.await.unwrap();
+  let server_conn = server.handshakes.recv().await.unwrap().unwrap();
+  assert!(server_conn.peer_identity().is_none());
+  client_conn.close(0u32.into(), b"done");
+
+  assert!(handshake(&mut server, &ca, 1).await.unwrap().peer_identity().is_some());
+  assert!(handshake(&mut server, &ca, 2).await.is_err());
+}
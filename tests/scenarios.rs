@@ -0,0 +1,468 @@
+use std::{
+  fs, net::SocketAddr, path::Path, sync::Arc, time::{Duration, SystemTime},
+};
+use quic_rs_demo::{
+  acl::Acl,
+  admission::Admission,
+  early_data::{ZeroRtt, ZeroRttCounters},
+  files,
+  metrics::Metrics,
+  push::FileWatcher,
+  qlog::{Qlog, Vantage},
+  revocation::RevocationPolicy,
+  scenarios::{
+    self, alpn, basic, datagram, mtls,
+    multiplexing::{self, Pushed},
+    session::{self, Login, SessionStream, ADMIN_USERNAME},
+    zero_rtt::{self, Captured, Tickets},
+  },
+  upload::{upload, Confirmation, UploadService},
+};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use quinn::Endpoint;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+
+const SAMPLE: &[u8] = b"{\"hello\": \"world\"}\n";
+const ADMIN_PWD: &str = "admin_password";
+
+// a cert dir with a self-signed certificate for localhost, and a document root
+fn dirs() -> (TempDir, TempDir) {
+  (common::cert_dir(), root())
+}
+
+fn root() -> TempDir {
+  let root = tempfile::tempdir().unwrap();
+  fs::write(root.path().join("sample.json"), SAMPLE).unwrap();
+  root
+}
+
+fn qlog() -> Qlog {
+  Qlog::disabled(Vantage::Server)
+}
+
+// the value of the sample `name{...}` in the text exposition
+fn value(metrics: &Metrics, name: &str) -> u64 {
+  let text = metrics.render();
+  let prefix = format!("{}{{", name);
+  let line = text
+    .lines()
+    .find(|line| line.starts_with(&prefix))
+    .unwrap_or_else(|| panic!("{} missing from\n{}", name, text));
+  line.rsplit(' ').next().unwrap().parse().unwrap()
+}
+
+async fn eventually(metrics: &Metrics, name: &str, expected: u64) {
+  let waited = timeout(Duration::from_secs(5), async {
+    while value(metrics, name) != expected {
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+  }).await;
+  assert!(waited.is_ok(), "{} never reached {}:\n{}", name, expected, metrics.render());
+}
+
+#[tokio::test]
+async fn basic_get() {
+  let (cert_dir, root) = dirs();
+  let server_addr = common::file_server(cert_dir.path(), root.path());
+
+  let conn = scenarios::connect(&common::file_client(cert_dir.path()), server_addr).await.unwrap();
+  assert_eq!(files::get(&conn, "sample.json").await.unwrap(), SAMPLE);
+  assert_eq!(files::get(&conn, "missing.json").await.unwrap(), b"failed to handle request");
+  assert_eq!(files::get(&conn, "../sample.json").await.unwrap(), b"failed to handle request");
+}
+
+fn alpn_server(cert_dir: &Path, root: &Path) -> SocketAddr {
+  let endpoint = scenarios::server_endpoint(alpn::server_tls(cert_dir).unwrap(), common::any_port()).unwrap();
+  let addr = endpoint.local_addr().unwrap();
+  tokio::spawn(basic::serve(endpoint, common::admission(), root.to_path_buf(), qlog(), Metrics::new("test")));
+  addr
+}
+
+fn alpn_client(cert_dir: &Path, protocols: &[&[u8]]) -> Endpoint {
+  scenarios::client_endpoint(alpn::client_tls(cert_dir, protocols).unwrap(), common::any_port()).unwrap()
+}
+
+#[tokio::test]
+async fn alpn_match() {
+  let (cert_dir, root) = dirs();
+  let server_addr = alpn_server(cert_dir.path(), root.path());
+
+  let conn = scenarios::connect(&alpn_client(cert_dir.path(), &[b"h1"]), server_addr).await.unwrap();
+  assert_eq!(alpn::protocol(&conn).as_deref(), Some(&b"h1"[..]));
+  assert_eq!(files::get(&conn, "sample.json").await.unwrap(), SAMPLE);
+}
+
+#[tokio::test]
+async fn alpn_mismatch_fails_handshake() {
+  let (cert_dir, root) = dirs();
+  let server_addr = alpn_server(cert_dir.path(), root.path());
+
+  let err = scenarios::connect(&alpn_client(cert_dir.path(), &[b"h3"]), server_addr).await.unwrap_err();
+  assert!(format!("{:#}", err).contains("peer doesn't support any known protocol"), "{:#}", err);
+}
+
+#[tokio::test]
+async fn no_alpn_fails_handshake() {
+  let (cert_dir, root) = dirs();
+  let server_addr = alpn_server(cert_dir.path(), root.path());
+
+  let err = scenarios::connect(&alpn_client(cert_dir.path(), &[]), server_addr).await.unwrap_err();
+  assert!(format!("{:#}", err).contains("peer doesn't support any known protocol"), "{:#}", err);
+}
+
+fn session_server(cert_dir: &Path, root: &Path) -> (SocketAddr, Arc<Metrics>) {
+  let endpoint = scenarios::server_endpoint(scenarios::server_tls(cert_dir).unwrap(), common::any_port()).unwrap();
+  let addr = endpoint.local_addr().unwrap();
+  let metrics = Metrics::new("test");
+  tokio::spawn(session::serve(endpoint, common::admission(), root.to_path_buf(), qlog(), metrics.clone()));
+  (addr, metrics)
+}
+
+fn admin(password: &str) -> Login {
+  Login { username: ADMIN_USERNAME.to_string(), password: password.to_string() }
+}
+
+#[tokio::test]
+async fn session_login_and_get() {
+  let (cert_dir, root) = dirs();
+  let (server_addr, metrics) = session_server(cert_dir.path(), root.path());
+
+  let conn = scenarios::connect(&common::file_client(cert_dir.path()), server_addr).await.unwrap();
+  let mut stream = SessionStream::open(&conn).await.unwrap();
+  let session = stream.login(&admin(ADMIN_PWD)).await.unwrap();
+  for _ in 0..3 {
+    assert_eq!(stream.get(&session, "sample.json").await.unwrap(), SAMPLE);
+  }
+  stream.finish().unwrap();
+  assert_eq!(value(&metrics, "quic_auth_failures_total"), 0);
+}
+
+#[tokio::test]
+async fn session_tokens_are_32_random_bytes() {
+  let (cert_dir, root) = dirs();
+  let (server_addr, _metrics) = session_server(cert_dir.path(), root.path());
+
+  let conn = scenarios::connect(&common::file_client(cert_dir.path()), server_addr).await.unwrap();
+  let mut tokens = Vec::new();
+  for _ in 0..2 {
+    let session = SessionStream::open(&conn).await.unwrap().login(&admin(ADMIN_PWD)).await.unwrap();
+    tokens.push(BASE64_STANDARD.decode(&session.token).unwrap());
+  }
+  assert_eq!(tokens[0].len(), 32);
+  assert_ne!(tokens[0], tokens[1]);
+}
+
+#[tokio::test]
+async fn session_wrong_password_is_refused() {
+  let (cert_dir, root) = dirs();
+  let (server_addr, metrics) = session_server(cert_dir.path(), root.path());
+
+  let conn = scenarios::connect(&common::file_client(cert_dir.path()), server_addr).await.unwrap();
+  let mut stream = SessionStream::open(&conn).await.unwrap();
+  let err = stream.login(&admin("guess")).await.unwrap_err();
+  assert!(err.to_string().contains("AUTH ERROR"), "{:#}", err);
+  eventually(&metrics, "quic_auth_failures_total", 1).await;
+}
+
+#[tokio::test]
+async fn session_wrong_username_is_refused() {
+  let (cert_dir, root) = dirs();
+  let (server_addr, metrics) = session_server(cert_dir.path(), root.path());
+
+  let conn = scenarios::connect(&common::file_client(cert_dir.path()), server_addr).await.unwrap();
+  let mut stream = SessionStream::open(&conn).await.unwrap();
+  let login = Login { username: "guest".to_string(), password: ADMIN_PWD.to_string() };
+  let err = stream.login(&login).await.unwrap_err();
+  assert!(err.to_string().contains("AUTH ERROR"), "{:#}", err);
+  eventually(&metrics, "quic_auth_failures_total", 1).await;
+}
+
+#[tokio::test]
+async fn session_token_does_not_verify_on_another_connection() {
+  let (cert_dir, root) = dirs();
+  let (server_addr, metrics) = session_server(cert_dir.path(), root.path());
+  let client = common::file_client(cert_dir.path());
+
+  let conn = scenarios::connect(&client, server_addr).await.unwrap();
+  let session = SessionStream::open(&conn).await.unwrap().login(&admin(ADMIN_PWD)).await.unwrap();
+  conn.close(0u32.into(), b"done");
+
+  let conn = scenarios::connect(&client, server_addr).await.unwrap();
+  let mut stream = SessionStream::open(&conn).await.unwrap();
+  stream.login(&admin(ADMIN_PWD)).await.unwrap();
+  assert!(stream.get(&session, "sample.json").await.is_err());
+  eventually(&metrics, "quic_auth_failures_total", 1).await;
+}
+
+#[tokio::test]
+async fn session_with_a_forged_signature_is_refused() {
+  let (cert_dir, root) = dirs();
+  let (server_addr, metrics) = session_server(cert_dir.path(), root.path());
+
+  let conn = scenarios::connect(&common::file_client(cert_dir.path()), server_addr).await.unwrap();
+  let mut stream = SessionStream::open(&conn).await.unwrap();
+  let mut session = stream.login(&admin(ADMIN_PWD)).await.unwrap();
+  let mut signature = BASE64_STANDARD.decode(&session.signature).unwrap();
+  signature[0] ^= 1;
+  session.signature = BASE64_STANDARD.encode(signature);
+  assert!(stream.get(&session, "sample.json").await.is_err());
+  eventually(&metrics, "quic_auth_failures_total", 1).await;
+}
+
+#[tokio::test]
+async fn datagram_echo() {
+  let (cert_dir, _root) = dirs();
+  let endpoint = scenarios::server_endpoint(scenarios::server_tls(cert_dir.path()).unwrap(), common::any_port()).unwrap();
+  let server_addr = endpoint.local_addr().unwrap();
+  tokio::spawn(datagram::serve(endpoint, common::admission(), qlog(), Metrics::new("test")));
+
+  let conn = scenarios::connect(&common::file_client(cert_dir.path()), server_addr).await.unwrap();
+  assert_eq!(datagram::send(&conn, "Hello from client").await.unwrap(), "Hello from server");
+  assert_eq!(datagram::send(&conn, "again").await.unwrap(), "ack");
+}
+
+#[tokio::test]
+async fn multiplexing_streams_uploads_datagrams_and_pushes() {
+  let (cert_dir, root) = dirs();
+  let upload_dir = tempfile::tempdir().unwrap();
+  let uploads = Arc::new(UploadService::new(upload_dir.path(), 1024).unwrap());
+  let watcher = FileWatcher::new();
+  let _watching = watcher.watch(Duration::from_millis(20));
+  let endpoint = scenarios::server_endpoint(scenarios::server_tls(cert_dir.path()).unwrap(), common::any_port()).unwrap();
+  let server_addr = endpoint.local_addr().unwrap();
+  tokio::spawn(multiplexing::serve(
+    endpoint, common::admission(), root.path().to_path_buf(), uploads, watcher, qlog(), Metrics::new("test"),
+  ));
+
+  let conn = scenarios::connect(&common::file_client(cert_dir.path()), server_addr).await.unwrap();
+  let mut pushed = Pushed::listen(&conn);
+  let (first, second, hello) = tokio::try_join!(
+    files::get(&conn, "sample.json"),
+    files::get(&conn, "sample.json"),
+    datagram::send(&conn, "Hello from client"),
+  ).unwrap();
+  assert_eq!((first.as_slice(), second.as_slice()), (SAMPLE, SAMPLE));
+  assert_eq!(hello, "Hello from server");
+
+  upload(&conn, "small.json", SAMPLE).await.unwrap();
+  let confirmation = pushed.confirmation().await.unwrap();
+  assert_eq!(confirmation, Confirmation::Uploaded { filename: "small.json".into(), size: SAMPLE.len() as u64 });
+  assert_eq!(fs::read(upload_dir.path().join("small.json")).unwrap(), SAMPLE);
+
+  upload(&conn, "too_big.bin", &[0u8; 2048]).await.unwrap();
+  let confirmation = pushed.confirmation().await.unwrap();
+  assert!(matches!(confirmation, Confirmation::Rejected { .. }), "{}", confirmation);
+  assert!(!upload_dir.path().join("too_big.bin").exists());
+
+  fs::File::options()
+    .append(true)
+    .open(root.path().join("sample.json"))
+    .unwrap()
+    .set_modified(SystemTime::now() + Duration::from_secs(60))
+    .unwrap();
+  let change = pushed.change(Duration::from_secs(5)).await.unwrap();
+  assert_eq!((change.kind.as_str(), change.body.as_str()), ("CHANGED", "sample.json"));
+}
+
+// an mTLS server allowing the generated client `sample.json` and `partner.json`
+fn mtls_server(cert_dir: &Path) -> (SocketAddr, Arc<quic_rs_demo::revocation::ReloadingClientVerifier>) {
+  let root = root();
+  fs::write(root.path().join("secret.json"), b"{}").unwrap();
+  fs::write(root.path().join("acl.json"), br#"{
+    "rules": [
+      { "identity": "cn:quinn-client", "paths": ["sample.json", "partner.json"] }
+    ]
+  }"#).unwrap();
+  let acl = Arc::new(Acl::load(&root.path().join("acl.json")).unwrap());
+
+  let crl_path = cert_dir.join(mtls::CRL);
+  let (tls_config, verifier) = mtls::server_tls(cert_dir, &crl_path, RevocationPolicy::RevokedOnly, false).unwrap();
+  let endpoint = scenarios::server_endpoint(tls_config, common::any_port()).unwrap();
+  let addr = endpoint.local_addr().unwrap();
+  // the root goes away with the server, once the test ends
+  tokio::spawn(async move {
+    mtls::serve(endpoint, common::admission(), root.path().to_path_buf(), acl, qlog(), Metrics::new("test")).await;
+    drop(root);
+  });
+  (addr, verifier)
+}
+
+fn mtls_dir() -> TempDir {
+  common::install_provider();
+  let cert_dir = tempfile::tempdir().unwrap();
+  mtls::generate(cert_dir.path()).unwrap();
+  cert_dir
+}
+
+fn mtls_client(cert_dir: &Path, authenticated: bool) -> Endpoint {
+  scenarios::client_endpoint(mtls::client_tls(cert_dir, authenticated).unwrap(), common::any_port()).unwrap()
+}
+
+#[tokio::test]
+async fn mtls_client_cert_is_accepted_and_checked_against_acl() {
+  let cert_dir = mtls_dir();
+  let (server_addr, _) = mtls_server(cert_dir.path());
+
+  let conn = scenarios::connect(&mtls_client(cert_dir.path(), true), server_addr).await.unwrap();
+  assert_eq!(files::get(&conn, "sample.json").await.unwrap(), SAMPLE);
+  assert_eq!(files::get(&conn, "secret.json").await.unwrap(), "🔒 ACCESS DENIED\n".as_bytes());
+}
+
+#[tokio::test]
+async fn mtls_anonymous_client_is_rejected() {
+  let cert_dir = mtls_dir();
+  let (server_addr, _) = mtls_server(cert_dir.path());
+
+  // the server rejects the handshake after the client's Finished, so the
+  // client only notices once it uses the connection
+  let Ok(conn) = scenarios::connect(&mtls_client(cert_dir.path(), false), server_addr).await else { return };
+  assert!(files::get(&conn, "sample.json").await.is_err());
+}
+
+#[tokio::test]
+async fn mtls_revoked_client_is_rejected_after_crl_reload() {
+  let cert_dir = mtls_dir();
+  let (server_addr, verifier) = mtls_server(cert_dir.path());
+
+  let revocation = mtls::revoke(cert_dir.path(), &cert_dir.path().join(mtls::CLIENT_CERT)).unwrap();
+  assert!(matches!(revocation, mtls::Revocation::Revoked { crl_number: 2, count: 1, .. }), "{:?}", revocation);
+  let again = mtls::revoke(cert_dir.path(), &cert_dir.path().join(mtls::CLIENT_CERT)).unwrap();
+  assert!(matches!(again, mtls::Revocation::AlreadyRevoked { .. }), "{:?}", again);
+  verifier.reload().unwrap();
+
+  let Ok(conn) = scenarios::connect(&mtls_client(cert_dir.path(), true), server_addr).await else { return };
+  assert!(files::get(&conn, "sample.json").await.is_err());
+}
+
+struct ZeroRttServer {
+  addr: SocketAddr,
+  counters: Arc<ZeroRttCounters>,
+  metrics: Arc<Metrics>,
+  admission: Arc<Admission>,
+  _root: TempDir,
+}
+
+fn tickets(anti_replay: bool) -> Tickets {
+  Tickets { key_file: None, lifetime: Duration::from_secs(60), early_data: true, anti_replay, replicated: false }
+}
+
+fn zero_rtt_server(cert_dir: &Path, tickets: &Tickets) -> ZeroRttServer {
+  let endpoint = scenarios::server_endpoint(zero_rtt::server_tls(cert_dir, tickets).unwrap(), common::any_port()).unwrap();
+  let root = root();
+  let counters = Arc::new(ZeroRttCounters::default());
+  let metrics = Metrics::new("test");
+  let addr = endpoint.local_addr().unwrap();
+  let admission = common::admission();
+  let root_dir = root.path().to_path_buf();
+  tokio::spawn(zero_rtt::serve(endpoint, admission.clone(), root_dir, counters.clone(), qlog(), metrics.clone()));
+  ZeroRttServer { addr, counters, metrics, admission, _root: root }
+}
+
+fn zero_rtt_client(cert_dir: &Path) -> Endpoint {
+  scenarios::client_endpoint(zero_rtt::client_tls(cert_dir).unwrap(), common::any_port()).unwrap()
+}
+
+#[tokio::test]
+async fn zero_rtt_resume() {
+  let (cert_dir, _root) = dirs();
+  let server = zero_rtt_server(cert_dir.path(), &tickets(true));
+  let client = zero_rtt_client(cert_dir.path());
+
+  let conn = zero_rtt::resume(&client, server.addr).await.unwrap();
+  assert!(conn.is_handshake_done());
+  assert_eq!(zero_rtt::get(&conn, "sample.json").await.unwrap(), SAMPLE);
+  conn.connection().close(0u32.into(), b"done");
+
+  let conn = zero_rtt::resume(&client, server.addr).await.unwrap();
+  assert!(!conn.is_handshake_done());
+  assert_eq!(zero_rtt::get(&conn, "sample.json").await.unwrap(), SAMPLE);
+  assert_eq!(zero_rtt::post(&conn, "x").await.unwrap(), b"successfully post");
+  assert_eq!(conn.zero_rtt().await, ZeroRtt::Accepted);
+  conn.connection().close(0u32.into(), b"done");
+
+  eventually(&server.metrics, "quic_zero_rtt_accepted_total", 1).await;
+  assert_eq!(server.counters.get(ZeroRtt::Accepted), 1);
+}
+
+#[tokio::test]
+async fn stateful_tickets_expire_after_their_lifetime() {
+  let (cert_dir, _root) = dirs();
+  let server = zero_rtt_server(cert_dir.path(), &Tickets { lifetime: Duration::from_millis(200), ..tickets(true) });
+  let client = zero_rtt_client(cert_dir.path());
+
+  let conn = zero_rtt::resume(&client, server.addr).await.unwrap();
+  assert_eq!(zero_rtt::get(&conn, "sample.json").await.unwrap(), SAMPLE);
+  conn.connection().close(0u32.into(), b"done");
+
+  tokio::time::sleep(Duration::from_millis(300)).await;
+  let conn = zero_rtt::resume(&client, server.addr).await.unwrap();
+  assert_eq!(conn.zero_rtt().await, ZeroRtt::Rejected);
+  assert_eq!(zero_rtt::get(&conn, "sample.json").await.unwrap(), SAMPLE);
+}
+
+#[tokio::test]
+async fn zero_rtt_connections_hold_their_admission_slot() {
+  let (cert_dir, _root) = dirs();
+  let server = zero_rtt_server(cert_dir.path(), &tickets(true));
+  let client = zero_rtt_client(cert_dir.path());
+
+  let conn = zero_rtt::resume(&client, server.addr).await.unwrap();
+  assert_eq!(zero_rtt::get(&conn, "sample.json").await.unwrap(), SAMPLE);
+  tokio::time::sleep(Duration::from_millis(50)).await;
+  assert_eq!(server.admission.open(), 1);
+
+  conn.connection().close(0u32.into(), b"done");
+  let released = timeout(Duration::from_secs(5), async {
+    while server.admission.open() != 0 {
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+  }).await;
+  assert!(released.is_ok(), "the slot was never given back");
+}
+
+// resumes with 0-RTT through a recording proxy and replays what the client
+// sent once the server forgot the connection, returning how many streams
+// the server handled, two of them before the replay
+async fn replay(tickets: Tickets) -> u64 {
+  let (cert_dir, _root) = dirs();
+  let server = zero_rtt_server(cert_dir.path(), &tickets);
+  let client = zero_rtt_client(cert_dir.path());
+
+  let conn = scenarios::connect(&client, server.addr).await.unwrap();
+  files::get(&conn, "sample.json").await.unwrap();
+  conn.close(0u32.into(), b"done");
+
+  let captured = Captured::default();
+  let proxy_addr = zero_rtt::recording_proxy(common::any_port(), server.addr, captured.clone()).await.unwrap();
+  let conn = zero_rtt::resume(&client, proxy_addr).await.unwrap();
+  assert!(!conn.is_handshake_done());
+  zero_rtt::get(&conn, "sample.json").await.unwrap();
+  assert_eq!(conn.zero_rtt().await, ZeroRtt::Accepted);
+  conn.connection().close(0u32.into(), b"done");
+  client.wait_idle().await;
+  eventually(&server.metrics, "quic_streams_total", 2).await;
+
+  tokio::time::sleep(Duration::from_millis(500)).await;
+  let datagrams = captured.lock().unwrap().clone();
+  let _attacker = zero_rtt::replay(&datagrams, server.addr).await.unwrap();
+  tokio::time::sleep(Duration::from_millis(500)).await;
+  value(&server.metrics, "quic_streams_total")
+}
+
+#[tokio::test]
+async fn replayed_0rtt_is_refused_with_anti_replay() {
+  assert_eq!(replay(Tickets { replicated: true, ..tickets(true) }).await, 2);
+}
+
+#[tokio::test]
+async fn replayed_0rtt_is_handled_again_without_anti_replay() {
+  assert_eq!(replay(Tickets { replicated: true, ..tickets(false) }).await, 3);
+}
+
+#[tokio::test]
+async fn replayed_0rtt_is_refused_by_a_cache_that_takes_tickets() {
+  assert_eq!(replay(tickets(false)).await, 2);
+}
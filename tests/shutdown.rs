@@ -0,0 +1,148 @@
+use std::{sync::Arc, time::Duration};
+use quic_rs_demo::{
+  cancellation::AppError,
+  metrics::Metrics,
+  qlog::{Qlog, Vantage},
+  scenarios::throughput::{self, Direction, Test},
+  shutdown::Drain,
+};
+use quinn::{Connection, ConnectionError, Endpoint, ReadError, ReadToEndError};
+use rustls::pki_types::CertificateDer;
+use tokio::{sync::Notify, task::JoinHandle, time::timeout};
+
+mod common;
+
+struct Server {
+  endpoint: Endpoint,
+  cert: CertificateDer<'static>,
+  stop: Arc<Notify>,
+  task: JoinHandle<()>,
+}
+
+// a throughput server draining for `drain` once stopped
+fn server(drain: Duration) -> Server {
+  let (endpoint, cert) = common::basic_server();
+  let metrics = Metrics::new("test");
+  let stop = Arc::new(Notify::new());
+  let task = tokio::spawn({
+    let (endpoint, stop) = (endpoint.clone(), stop.clone());
+    async move {
+      let qlog = Qlog::disabled(Vantage::Server);
+      let serving = throughput::serve(endpoint.clone(), common::admission(), qlog, metrics.clone());
+      let stop = || {
+        let stop = stop.clone();
+        async move { stop.notified().await }
+      };
+      Drain { timeout: drain }.serve_until(&endpoint, &metrics, serving, stop).await
+    }
+  });
+  Server { endpoint, cert, stop, task }
+}
+
+impl Server {
+  async fn connect(&self) -> Connection {
+    common::connect(&common::basic_client(&self.cert), self.endpoint.local_addr().unwrap()).await
+  }
+}
+
+fn test(direction: Direction, duration: Duration) -> Test {
+  Test { direction, streams: 2, duration, interval: Duration::from_millis(100) }
+}
+
+async fn shut_down_with(conn: &Connection) -> Option<AppError> {
+  match conn.closed().await {
+    ConnectionError::ApplicationClosed(close) => AppError::from_code(close.error_code),
+    e => panic!("unexpected close: {}", e),
+  }
+}
+
+#[tokio::test]
+async fn streams_in_flight_finish_and_new_connections_are_refused() {
+  let server = server(Duration::from_secs(10));
+  let conn = server.connect().await;
+  let upload = tokio::spawn({
+    let conn = conn.clone();
+    async move { throughput::run(&conn, &test(Direction::Upload, Duration::from_millis(600)), |_| {}).await }
+  });
+
+  tokio::time::sleep(Duration::from_millis(200)).await;
+  server.stop.notify_one();
+  tokio::time::sleep(Duration::from_millis(50)).await;
+  let refused = common::basic_client(&server.cert).connect(server.endpoint.local_addr().unwrap(), "localhost").unwrap().await;
+  assert!(matches!(refused, Err(ConnectionError::ConnectionClosed(_))), "{:?}", refused.map(|_| ()));
+
+  let summary = upload.await.unwrap().unwrap();
+  assert!(summary.bytes > 0);
+  assert_eq!(shut_down_with(&conn).await, Some(AppError::ShuttingDown));
+  timeout(Duration::from_secs(5), server.task).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn new_streams_are_refused_while_draining() {
+  let server = server(Duration::from_secs(10));
+  let conn = server.connect().await;
+  let upload = tokio::spawn({
+    let conn = conn.clone();
+    async move { throughput::run(&conn, &test(Direction::Upload, Duration::from_millis(600)), |_| {}).await }
+  });
+
+  tokio::time::sleep(Duration::from_millis(200)).await;
+  server.stop.notify_one();
+  tokio::time::sleep(Duration::from_millis(50)).await;
+  // a client that keeps opening streams does not hold the drain open
+  for _ in 0..20 {
+    let (mut send, mut recv) = conn.open_bi().await.unwrap();
+    let _ = send.write_all(b"STATS
+").await;
+    let _ = send.finish();
+    let err = recv.read_to_end(1024).await.unwrap_err();
+    let ReadToEndError::Read(ReadError::Reset(code)) = err else {
+      panic!("unexpected error: {}", err);
+    };
+    assert_eq!(AppError::from_code(code), Some(AppError::ShuttingDown));
+    tokio::time::sleep(Duration::from_millis(10)).await;
+  }
+
+  assert!(upload.await.unwrap().unwrap().bytes > 0);
+  assert_eq!(shut_down_with(&conn).await, Some(AppError::ShuttingDown));
+  timeout(Duration::from_secs(5), server.task).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn streams_past_the_drain_deadline_are_closed() {
+  let server = server(Duration::from_millis(200));
+  let conn = server.connect().await;
+  let upload = tokio::spawn({
+    let conn = conn.clone();
+    async move { throughput::run(&conn, &test(Direction::Upload, Duration::from_secs(10)), |_| {}).await }
+  });
+
+  tokio::time::sleep(Duration::from_millis(100)).await;
+  server.stop.notify_one();
+  assert!(upload.await.unwrap().is_err());
+  assert_eq!(shut_down_with(&conn).await, Some(AppError::ShuttingDown));
+  timeout(Duration::from_secs(5), server.task).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn a_second_stop_cuts_the_drain_short() {
+  let server = server(Duration::from_secs(60));
+  let conn = server.connect().await;
+  let upload = tokio::spawn({
+    let conn = conn.clone();
+    async move { throughput::run(&conn, &test(Direction::Upload, Duration::from_secs(10)), |_| {}).await }
+  });
+
+  tokio::time::sleep(Duration::from_millis(100)).await;
+  server.stop.notify_one();
+  tokio::time::sleep(Duration::from_millis(100)).await;
+  server.stop.notify_one();
+  timeout(Duration::from_secs(5), server.task).await.unwrap().unwrap();
+  assert!(upload.await.unwrap().is_err());
+  assert_eq!(shut_down_with(&conn).await, Some(AppError::ShuttingDown));
+}
+
+#[test]
+fn drain_timeout_defaults_to_ten_seconds() {
+  assert_eq!(Drain::default().timeout, Duration::from_secs(10));
+}
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+use quic_rs_demo::sni::{server_name, HostMap, SniResolver};
+use rustls::{crypto::aws_lc_rs, pki_types::CertificateDer, sign::CertifiedKey};
+
+mod common;
+
+fn hosts() -> HostMap<&'static str> {
+  let mut hosts = HostMap::new();
+  hosts.insert("a.localhost", "a").unwrap();
+  hosts.insert("*.wild.localhost", "wild").unwrap();
+  hosts.insert("exact.wild.localhost", "exact").unwrap();
+  hosts.set_default("default");
+  hosts
+}
+
+#[test]
+fn exact_names_win_over_wildcards_and_default() {
+  let hosts = hosts();
+  assert_eq!(hosts.get(Some("a.localhost")), Some(&"a"));
+  assert_eq!(hosts.get(Some("A.LocalHost")), Some(&"a"));
+  assert_eq!(hosts.get(Some("exact.wild.localhost")), Some(&"exact"));
+  assert_eq!(hosts.get(Some("x.wild.localhost")), Some(&"wild"));
+}
+
+#[test]
+fn wildcards_match_exactly_one_label() {
+  let hosts = hosts();
+  assert_eq!(hosts.get(Some("wild.localhost")), Some(&"default"));
+  assert_eq!(hosts.get(Some("y.x.wild.localhost")), Some(&"default"));
+  assert_eq!(hosts.get(None), Some(&"default"));
+  assert_eq!(HostMap::<()>::new().get(Some("a.localhost")), None);
+}
+
+#[test]
+fn malformed_patterns_are_refused() {
+  let mut hosts = HostMap::new();
+  assert!(hosts.insert("", ()).is_err());
+  assert!(hosts.insert("*.", ()).is_err());
+  assert!(hosts.insert("a.*.localhost", ()).is_err());
+}
+
+#[tokio::test]
+async fn each_name_gets_its_own_certificate() {
+  common::install_provider();
+  let provider = aws_lc_rs::default_provider();
+  let mut certs = HostMap::new();
+  let mut roots = rustls::RootCertStore::empty();
+  for name in ["a.localhost", "b.localhost"] {
+    let (cert, key) = common::self_signed(name);
+    roots.add(cert.clone()).unwrap();
+    let key = provider.key_provider.load_private_key(key).unwrap();
+    certs.insert(name, Arc::new(CertifiedKey::new(vec![cert], key))).unwrap();
+  }
+  let tls_config = rustls::ServerConfig::builder()
+    .with_no_client_auth()
+    .with_cert_resolver(Arc::new(SniResolver::new(certs)));
+  let server = common::server(tls_config);
+  let server_addr = server.local_addr().unwrap();
+  let client = common::client(rustls::ClientConfig::builder().with_root_certificates(roots).with_no_client_auth());
+
+  let mut seen = Vec::new();
+  for name in ["a.localhost", "b.localhost"] {
+    let connecting = client.connect(server_addr, name).unwrap();
+    let (accepted, conn) = tokio::join!(async { server.accept().await.unwrap().await.unwrap() }, connecting);
+    let conn = conn.unwrap();
+    assert_eq!(server_name(&accepted).as_deref(), Some(name));
+    let chain = conn.peer_identity().unwrap().downcast::<Vec<CertificateDer<'static>>>().unwrap();
+    seen.push(chain[0].clone());
+  }
+  assert_ne!(seen[0], seen[1]);
+}
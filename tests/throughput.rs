@@ -0,0 +1,101 @@
+use std::{path::Path, sync::Arc, time::Duration};
+use quic_rs_demo::{
+  metrics::Metrics,
+  qlog::{Qlog, Vantage},
+  scenarios::{self, throughput::{self, Direction, SenderStats, Test}},
+  transport::Congestion,
+};
+use quinn::{Connection, Endpoint, TransportConfig};
+use tempfile::TempDir;
+
+mod common;
+
+fn transport(congestion: Congestion) -> Arc<TransportConfig> {
+  let mut transport_config = TransportConfig::default();
+  congestion.apply(&mut transport_config);
+  Arc::new(transport_config)
+}
+
+async fn connect(congestion: Congestion) -> (Connection, TempDir) {
+  let cert_dir = common::cert_dir();
+  let mut server_config = scenarios::server_config(scenarios::server_tls(cert_dir.path()).unwrap()).unwrap();
+  server_config.transport_config(transport(congestion));
+  let server = Endpoint::server(server_config, common::any_port()).unwrap();
+  let server_addr = server.local_addr().unwrap();
+  tokio::spawn(throughput::serve(server, common::admission(), Qlog::disabled(Vantage::Server), Metrics::new("test")));
+
+  let conn = scenarios::connect(&client(cert_dir.path(), congestion), server_addr).await.unwrap();
+  (conn, cert_dir)
+}
+
+fn client(cert_dir: &Path, congestion: Congestion) -> Endpoint {
+  let mut client_config = scenarios::client_config(scenarios::client_tls(cert_dir).unwrap()).unwrap();
+  client_config.transport_config(transport(congestion));
+  let mut endpoint = Endpoint::client(common::any_port()).unwrap();
+  endpoint.set_default_client_config(client_config);
+  endpoint
+}
+
+fn test(direction: Direction) -> Test {
+  Test {
+    direction,
+    streams: 2,
+    duration: Duration::from_millis(500),
+    interval: Duration::from_millis(100),
+  }
+}
+
+#[tokio::test]
+async fn upload_reports_intervals_and_the_bytes_the_server_received() {
+  let (conn, _cert_dir) = connect(Congestion::Cubic).await;
+  let mut intervals = Vec::new();
+
+  let summary = throughput::run(&conn, &test(Direction::Upload), |interval| intervals.push(*interval)).await.unwrap();
+  assert!(summary.elapsed >= Duration::from_millis(500));
+  assert!(summary.bytes > 0);
+  assert!(summary.goodput() > 0.0);
+  assert!(summary.sender.sent_packets > 0);
+  assert!(intervals.len() >= 4, "{} intervals", intervals.len());
+  assert!(intervals.iter().all(|interval| interval.lost_packets.is_some() && interval.cwnd.is_some()));
+  assert!(intervals[0].to_string().contains(" MiB sent "), "{}", intervals[0]);
+  // the last, partial interval is not reported
+  assert!(intervals.iter().map(|interval| interval.bytes).sum::<u64>() <= summary.bytes);
+}
+
+#[tokio::test]
+async fn download_reports_the_servers_sender_stats() {
+  for congestion in [Congestion::NewReno, Congestion::Bbr] {
+    let (conn, _cert_dir) = connect(congestion).await;
+    let mut intervals = Vec::new();
+
+    let summary = throughput::run(&conn, &test(Direction::Download), |interval| intervals.push(*interval)).await.unwrap();
+    assert!(summary.bytes > 0);
+    assert!(intervals.iter().all(|interval| interval.lost_packets.is_none()));
+    assert!(intervals[0].to_string().contains(" MiB received "), "{}", intervals[0]);
+    assert!(intervals.iter().map(|interval| interval.bytes).sum::<u64>() <= summary.bytes);
+    // the server sent the data, far more packets than the client's requests and acks
+    assert!(summary.sender.sent_packets > conn.stats().path.sent_packets, "{:?}", summary.sender);
+  }
+}
+
+#[test]
+fn congestion_controllers_parse() {
+  assert_eq!("BBR".parse::<Congestion>().unwrap(), Congestion::Bbr);
+  assert_eq!("newreno".parse::<Congestion>().unwrap(), Congestion::NewReno);
+  assert_eq!(Congestion::Cubic.to_string().parse::<Congestion>().unwrap(), Congestion::Cubic);
+  assert!("vegas".parse::<Congestion>().is_err());
+}
+
+#[test]
+fn sender_stats_round_trip() {
+  let stats = SenderStats {
+    sent_packets: 1000,
+    lost_packets: 12,
+    lost_bytes: 14400,
+    congestion_events: 3,
+    rtt: Duration::from_micros(1234),
+    cwnd: 65536,
+  };
+  assert_eq!(stats.to_string().parse::<SenderStats>().unwrap(), stats);
+  assert!("1 2 3".parse::<SenderStats>().is_err());
+}
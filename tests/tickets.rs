@@ -0,0 +1,96 @@
+use std::{fs, path::Path, time::{Duration, SystemTime, UNIX_EPOCH}};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use quic_rs_demo::tickets::{read_key_file, rotate_key_file, KeyFileTicketer};
+use rustls::server::ProducesTickets;
+
+const LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+fn now() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn write_keys(path: &Path, created: &[u64]) {
+  let lines: Vec<String> = created
+    .iter()
+    .enumerate()
+    .map(|(i, created)| format!("{} {}", created, STANDARD.encode([i as u8 + 1; 32])))
+    .collect();
+  fs::write(path, lines.join("\n")).unwrap();
+}
+
+#[test]
+fn servers_sharing_a_key_file_resume_each_others_tickets() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("ticket_keys");
+  rotate_key_file(&path, LIFETIME).unwrap();
+
+  let a = KeyFileTicketer::new(&path, LIFETIME).unwrap();
+  let b = KeyFileTicketer::new(&path, LIFETIME).unwrap();
+  let ticket = a.encrypt(b"session").unwrap();
+  assert_eq!(b.decrypt(&ticket).as_deref(), Some(&b"session"[..]));
+  assert_eq!(a.lifetime(), 60 * 60);
+}
+
+#[test]
+fn tampered_tickets_are_refused() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("ticket_keys");
+  rotate_key_file(&path, LIFETIME).unwrap();
+  let ticketer = KeyFileTicketer::new(&path, LIFETIME).unwrap();
+
+  let mut ticket = ticketer.encrypt(b"session").unwrap();
+  let last = ticket.len() - 1;
+  ticket[last] ^= 1;
+  assert_eq!(ticketer.decrypt(&ticket), None);
+  assert_eq!(ticketer.decrypt(b"short"), None);
+}
+
+#[test]
+fn rotation_keeps_old_tickets_working() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("ticket_keys");
+  rotate_key_file(&path, LIFETIME).unwrap();
+  let ticketer = KeyFileTicketer::new(&path, LIFETIME).unwrap();
+  let old_ticket = ticketer.encrypt(b"old").unwrap();
+
+  rotate_key_file(&path, LIFETIME).unwrap();
+  assert_eq!(ticketer.reload().unwrap(), 2);
+  let new_ticket = ticketer.encrypt(b"new").unwrap();
+  // tickets start with the id of the key that encrypted them
+  assert_ne!(old_ticket[..4], new_ticket[..4]);
+  assert_eq!(ticketer.decrypt(&old_ticket).as_deref(), Some(&b"old"[..]));
+  assert_eq!(ticketer.decrypt(&new_ticket).as_deref(), Some(&b"new"[..]));
+}
+
+#[test]
+fn keys_are_erased_once_their_tickets_expired() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("ticket_keys");
+  let hour = LIFETIME.as_secs();
+  // the oldest key was replaced two hours ago, its tickets have expired
+  write_keys(&path, &[now() - hour / 2, now() - 2 * hour, now() - 3 * hour]);
+
+  let ticketer = KeyFileTicketer::new(&path, LIFETIME).unwrap();
+  assert_eq!(ticketer.keys().len(), 2);
+
+  // the new key joins, the expired one leaves the file
+  let keys = rotate_key_file(&path, LIFETIME).unwrap();
+  assert_eq!(keys.len(), 3);
+  assert_eq!(read_key_file(&path).unwrap().len(), 3);
+  assert!(keys.iter().all(|key| key.created > now() - 3 * hour));
+}
+
+#[test]
+fn malformed_key_files_are_rejected() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("ticket_keys");
+
+  fs::write(&path, "# nothing here\n").unwrap();
+  assert!(KeyFileTicketer::new(&path, LIFETIME).is_err());
+
+  fs::write(&path, format!("{} {}\n", now(), STANDARD.encode([1u8; 16]))).unwrap();
+  let err = KeyFileTicketer::new(&path, LIFETIME).unwrap_err();
+  assert!(format!("{:#}", err).contains("32 bytes"), "{:#}", err);
+
+  assert!(KeyFileTicketer::new(dir.path().join("missing"), LIFETIME).is_err());
+}
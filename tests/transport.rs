@@ -0,0 +1,40 @@
+use std::env;
+use quic_rs_demo::transport::FlowControl;
+use quinn::TransportConfig;
+
+const VARS: [&str; 5] = ["MAX_BIDI_STREAMS", "MAX_UNI_STREAMS", "STREAM_RECEIVE_WINDOW", "RECEIVE_WINDOW", "SEND_WINDOW"];
+
+// one test, the environment is shared by every test in the binary
+#[test]
+fn flow_control_reads_the_environment() {
+  for var in VARS {
+    env::remove_var(var);
+  }
+  assert_eq!(FlowControl::from_env().unwrap(), FlowControl::default());
+
+  env::set_var("MAX_BIDI_STREAMS", "8");
+  env::set_var("STREAM_RECEIVE_WINDOW", "65536");
+  env::set_var("SEND_WINDOW", "1048576");
+  let flow_control = FlowControl::from_env().unwrap();
+  assert_eq!(flow_control, FlowControl {
+    max_concurrent_bidi_streams: 8,
+    stream_receive_window: 65536,
+    send_window: 1048576,
+    ..FlowControl::default()
+  });
+
+  env::set_var("MAX_UNI_STREAMS", "-1");
+  let err = FlowControl::from_env().unwrap_err();
+  assert!(err.to_string().starts_with("invalid MAX_UNI_STREAMS=\"-1\""), "{}", err);
+
+  for var in VARS {
+    env::remove_var(var);
+  }
+}
+
+#[test]
+fn receive_window_past_a_varint_is_refused() {
+  let flow_control = FlowControl { receive_window: u64::MAX, ..FlowControl::default() };
+  assert!(flow_control.apply(&mut TransportConfig::default()).is_err());
+  assert!(FlowControl::default().apply(&mut TransportConfig::default()).is_ok());
+}
@@ -0,0 +1,127 @@
+use std::{fs, sync::Arc};
+use quic_rs_demo::upload::{read_confirmation, upload, Confirmation, UploadHeader, UploadService};
+use quinn::Connection;
+use tempfile::TempDir;
+
+mod common;
+
+// a server storing uploads in a temporary directory under `quota`
+async fn connect(quota: u64) -> (Connection, TempDir) {
+  let dir = tempfile::tempdir().unwrap();
+  let uploads = Arc::new(UploadService::new(dir.path(), quota).unwrap());
+  let (server, cert) = common::basic_server();
+  let server_addr = server.local_addr().unwrap();
+  tokio::spawn(async move {
+    let conn = server.accept().await.unwrap().await.unwrap();
+    while let Ok(recv) = conn.accept_uni().await {
+      let (uploads, conn) = (uploads.clone(), conn.clone());
+      tokio::spawn(async move { uploads.handle_uni_stream(&conn, recv).await });
+    }
+  });
+  let conn = common::connect(&common::basic_client(&cert), server_addr).await;
+  (conn, dir)
+}
+
+fn rejected(confirmation: Confirmation) -> String {
+  match confirmation {
+    Confirmation::Rejected { reason, .. } => reason,
+    confirmation => panic!("expected a rejection, got {}", confirmation),
+  }
+}
+
+// no part files left behind
+fn stored(dir: &TempDir) -> Vec<String> {
+  let mut names: Vec<String> = fs::read_dir(dir.path())
+    .unwrap()
+    .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+    .collect();
+  names.sort();
+  names
+}
+
+#[tokio::test]
+async fn upload_is_stored_once_verified() {
+  let (conn, dir) = connect(1024).await;
+  upload(&conn, "a.txt", b"hello").await.unwrap();
+  let confirmation = read_confirmation(&conn).await.unwrap();
+  assert_eq!(confirmation, Confirmation::Uploaded { filename: "a.txt".to_string(), size: 5 });
+  assert_eq!(fs::read(dir.path().join("a.txt")).unwrap(), b"hello");
+  assert_eq!(stored(&dir), ["a.txt"]);
+}
+
+#[tokio::test]
+async fn checksum_mismatch_is_rejected() {
+  let (conn, dir) = connect(1024).await;
+  let mut header = UploadHeader::for_data("a.txt", b"hello");
+  header.sha256[0] ^= 1;
+  let mut send = conn.open_uni().await.unwrap();
+  send.write_all(&header.encode()).await.unwrap();
+  send.write_all(b"hello").await.unwrap();
+  send.finish().unwrap();
+
+  let reason = rejected(read_confirmation(&conn).await.unwrap());
+  assert!(reason.contains("checksum mismatch"), "{}", reason);
+  assert!(stored(&dir).is_empty());
+
+  // the rejected upload gave its space back
+  upload(&conn, "b.txt", &[b'b'; 1024]).await.unwrap();
+  assert!(matches!(read_confirmation(&conn).await.unwrap(), Confirmation::Uploaded { .. }));
+}
+
+#[tokio::test]
+async fn uploads_past_the_quota_are_rejected() {
+  let (conn, dir) = connect(8).await;
+  upload(&conn, "a.txt", b"hello").await.unwrap();
+  assert!(matches!(read_confirmation(&conn).await.unwrap(), Confirmation::Uploaded { .. }));
+
+  upload(&conn, "b.txt", b"world").await.unwrap();
+  let reason = rejected(read_confirmation(&conn).await.unwrap());
+  assert!(reason.contains("quota exceeded"), "{}", reason);
+  assert_eq!(stored(&dir), ["a.txt"]);
+
+  // replacing a file only counts the difference
+  upload(&conn, "a.txt", b"hi").await.unwrap();
+  assert!(matches!(read_confirmation(&conn).await.unwrap(), Confirmation::Uploaded { .. }));
+}
+
+#[tokio::test]
+async fn oversized_headers_are_rejected() {
+  let (conn, dir) = connect(1024).await;
+  upload(&conn, "a.txt", b"hello").await.unwrap();
+  assert!(matches!(read_confirmation(&conn).await.unwrap(), Confirmation::Uploaded { .. }));
+
+  let mut header = UploadHeader::for_data("b.txt", b"world");
+  header.size = u64::MAX;
+  let mut send = conn.open_uni().await.unwrap();
+  send.write_all(&header.encode()).await.unwrap();
+  let _ = send.write_all(b"world").await;
+  let _ = send.finish();
+  let reason = rejected(read_confirmation(&conn).await.unwrap());
+  assert!(reason.contains("quota exceeded"), "{}", reason);
+
+  // the quota is still counted right
+  upload(&conn, "c.txt", &[b'c'; 1019]).await.unwrap();
+  assert!(matches!(read_confirmation(&conn).await.unwrap(), Confirmation::Uploaded { .. }));
+  assert_eq!(stored(&dir), ["a.txt", "c.txt"]);
+}
+
+#[tokio::test]
+async fn concurrent_uploads_of_one_name_do_not_mix() {
+  let (conn, dir) = connect(4 * 1024 * 1024).await;
+  let (a, b) = (vec![b'a'; 1024 * 1024], vec![b'b'; 1024 * 1024]);
+  let (sent_a, sent_b) = tokio::join!(upload(&conn, "same.bin", &a), upload(&conn, "same.bin", &b));
+  sent_a.unwrap();
+  sent_b.unwrap();
+  for _ in 0..2 {
+    let confirmation = read_confirmation(&conn).await.unwrap();
+    assert!(matches!(confirmation, Confirmation::Uploaded { .. }), "{}", confirmation);
+  }
+  let data = fs::read(dir.path().join("same.bin")).unwrap();
+  assert!(data == a || data == b);
+  assert_eq!(stored(&dir), ["same.bin"]);
+
+  // only one of them is counted against the quota
+  upload(&conn, "rest.bin", &vec![b'r'; 3 * 1024 * 1024]).await.unwrap();
+  let confirmation = read_confirmation(&conn).await.unwrap();
+  assert!(matches!(confirmation, Confirmation::Uploaded { .. }), "{}", confirmation);
+}
@@ -0,0 +1,69 @@
+use std::{net::SocketAddr, path::Path, sync::Arc, time::Duration};
+use anyhow::{bail, Context, Result};
+use log::info;
+use quinn::{Endpoint, TransportConfig};
+use rustls::crypto::{CryptoProvider, aws_lc_rs};
+use quic_rs_demo::{
+  config::env_or,
+  logging,
+  qlog::{Qlog, Vantage},
+  scenarios::{self, throughput::{self, Direction, Test}},
+  transport::{Congestion, FlowControl},
+};
+
+const CERT_DIR: &str = "/tmp/quinn_certs";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+  logging::init();
+  let qlog = Qlog::from_env(Vantage::Client)?;
+  CryptoProvider::install_default(
+    aws_lc_rs::default_provider()
+  ).expect("failed to install default crypto provider");
+
+  let test = Test {
+    direction: env_or("DIRECTION", Direction::Upload)?,
+    streams: env_or("STREAMS", 1)?,
+    duration: Duration::from_secs(env_or("DURATION_SECS", 10)?),
+    interval: Duration::from_secs(env_or("INTERVAL_SECS", 1)?),
+  };
+  if test.interval.is_zero() {
+    bail!("INTERVAL_SECS must be at least 1");
+  }
+
+  // the congestion controller of the sending side is the one that matters
+  let flow_control = FlowControl::from_env()?;
+  let congestion = Congestion::from_env()?;
+  let mut transport_config = TransportConfig::default();
+  flow_control.apply(&mut transport_config)?;
+  congestion.apply(&mut transport_config);
+
+  let tls_config = scenarios::client_tls(Path::new(CERT_DIR))?;
+  let mut client_config = scenarios::client_config(tls_config)?;
+  client_config.transport_config(Arc::new(transport_config));
+  let mut endpoint = Endpoint::client("0.0.0.0:0".parse()?)?;
+  endpoint.set_default_client_config(client_config);
+
+  let server_addr = env_or("SERVER_ADDR", "127.0.0.1:4843".parse::<SocketAddr>()?)?;
+  // the name on the server's certificate
+  let server_name = env_or("SERVER_NAME", String::from("localhost"))?;
+  let conn = endpoint
+    .connect(server_addr, &server_name)?
+    .await
+    .context("failed to connect to server")?;
+  let _trace = qlog.trace(&conn);
+  info!(
+    conn_id = conn.stable_id(),
+    remote:% = server_addr,
+    streams = test.streams,
+    congestion:%;
+    "🏁 {} for {:?}", test.direction, test.duration,
+  );
+
+  let summary = throughput::run(&conn, &test, |interval| println!("{}", interval)).await?;
+  println!("{}", summary);
+
+  conn.close(0u32.into(), b"done");
+  endpoint.wait_idle().await;
+  Ok(())
+}
@@ -0,0 +1,49 @@
+use rustls::crypto::{CryptoProvider, aws_lc_rs};
+use std::{net::SocketAddr, path::Path, sync::Arc};
+use anyhow::Result;
+use quinn::{Endpoint, TransportConfig};
+use log::info;
+use quic_rs_demo::{
+  admission::Admission,
+  config::env_or,
+  logging,
+  metrics::Metrics,
+  qlog::{Qlog, Vantage},
+  scenarios::{self, throughput},
+  shutdown,
+  transport::{Congestion, FlowControl},
+};
+
+const CERT_DIR: &str = "/tmp/quinn_certs";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+  logging::init();
+  CryptoProvider::install_default(
+    aws_lc_rs::default_provider()
+  ).expect("failed to install default crypto provider");
+
+  let flow_control = FlowControl::from_env()?;
+  let congestion = Congestion::from_env()?;
+  let mut transport_config = TransportConfig::default();
+  flow_control.apply(&mut transport_config)?;
+  congestion.apply(&mut transport_config);
+
+  let tls_config = scenarios::server_tls(Path::new(CERT_DIR))?;
+  let mut server_config = scenarios::server_config(tls_config)?;
+  server_config.transport_config(Arc::new(transport_config));
+
+  // listen on another address to measure between hosts
+  let addr = env_or("LISTEN_ADDR", "127.0.0.1:4843".parse::<SocketAddr>()?)?;
+  let endpoint = Endpoint::server(server_config, addr)?;
+
+  let addr = endpoint.local_addr()?;
+  info!(addr:% = addr; "🚀 QUIC server listening");
+  info!(congestion:%, flow_control:%; "transport");
+  let qlog = Qlog::from_env(Vantage::Server)?;
+  let metrics = Metrics::from_env("throughput-server").await?;
+  let admission = Admission::from_env()?;
+
+  let serving = throughput::serve(endpoint.clone(), admission, qlog, metrics.clone());
+  shutdown::serve(&endpoint, &metrics, serving).await
+}
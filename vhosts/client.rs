@@ -0,0 +1,99 @@
+use std::{
+  fs, io::{self, Write}, net::SocketAddr, path::Path, sync::Arc
+};
+use anyhow::{Context, Result};
+use log::{error, info};
+use quic_rs_demo::{keylog, logging, qlog::{Qlog, Vantage}};
+use rustls::{
+  pki_types::CertificateDer,
+  crypto::{CryptoProvider, aws_lc_rs},
+};
+
+use quinn::{
+  crypto::rustls::QuicClientConfig,
+  Connection,
+  Endpoint,
+  ClientConfig,
+};
+const CERT_DIR: &str = "/tmp/quinn_certs/vhosts";
+
+// an IP address as server name means no SNI is sent
+const SERVER_NAMES: [&str; 4] = ["a.localhost", "b.localhost", "anything.wild.localhost", "127.0.0.1"];
+// falls back to the default certificate, which does not cover it
+const UNKNOWN_NAME: &str = "unknown.localhost";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+  logging::init();
+  let qlog = Qlog::from_env(Vantage::Client)?;
+  // trust every generated certificate, the server picks which one we get
+  let mut cert_root = rustls::RootCertStore::empty();
+  for entry in fs::read_dir(Path::new(CERT_DIR))? {
+    let path = entry?.path();
+    if path.to_string_lossy().ends_with(".cert.der") {
+      cert_root.add(CertificateDer::from(fs::read(path)?))?;
+    }
+  }
+
+  CryptoProvider::install_default(
+    aws_lc_rs::default_provider()
+  ).expect("failed to install default crypto provider");
+
+  let mut tls_config = rustls::ClientConfig::builder()
+    .with_root_certificates(cert_root)
+    .with_no_client_auth();
+  tls_config.key_log = keylog::from_env();
+  let quic_config = QuicClientConfig::try_from(tls_config)?;
+  let client_config = ClientConfig::new(Arc::new(quic_config));
+
+  let addr: SocketAddr = "127.0.0.1:4385".parse()?;
+  let mut endpoint = Endpoint::client(addr)?;
+  endpoint.set_default_client_config(client_config);
+
+  let server_addr = "127.0.0.1:4843".parse()?;
+  for server_name in SERVER_NAMES {
+    let conn = endpoint
+      .connect(server_addr, server_name)?
+      .await
+      .context("failed to connect to server")?;
+    info!(conn_id = conn.stable_id(), remote:% = server_addr, server_name; "connected to server");
+    let _trace = qlog.trace(&conn);
+
+    let resp = get(&conn, "index.json").await?;
+    info!(conn_id = conn.stable_id(), bytes = resp.len(); "response received");
+    io::stdout().write_all(&resp).unwrap();
+    io::stdout().flush().unwrap();
+    conn.close(0u32.into(), b"done");
+  }
+
+  info!(remote:% = server_addr, server_name = UNKNOWN_NAME; "connecting to server...");
+  match endpoint.connect(server_addr, UNKNOWN_NAME)?.await {
+    Ok(_) => {
+      error!("❌ Unexpected success: the default certificate does not cover {}", UNKNOWN_NAME);
+      std::process::exit(1);
+    }
+    Err(e) => info!("✅ Expected Error: {}", e),
+  }
+
+  endpoint.wait_idle().await;
+  Ok(())
+}
+
+async fn get(conn: &Connection, filename: &str) -> Result<Vec<u8>> {
+  let (mut send, mut recv) = conn
+  .open_bi()
+  .await
+  .context("failed to open stream")?;
+
+  let req = format!("GET {}\r\n", filename);
+  send.write_all(req.as_bytes())
+    .await
+    .context("failed to send request")?;
+  send.finish().unwrap();
+
+  let resp = recv
+    .read_to_end(usize::MAX)
+    .await
+    .context("failed to read response")?;
+  Ok(resp)
+}
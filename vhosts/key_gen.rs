@@ -0,0 +1,64 @@
+use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
+use std::{
+  path::Path,
+  fs,
+};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+const CERT_DIR: &str = "/tmp/quinn_certs/vhosts";
+
+#[derive(Deserialize)]
+struct HostsConfig {
+  default: DefaultHost,
+  hosts: Vec<Host>,
+}
+
+#[derive(Deserialize)]
+struct DefaultHost {
+  names: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Host {
+  pattern: String,
+}
+
+fn main() -> Result<()>{
+  let cert_dir = Path::new(CERT_DIR);
+  if cert_dir.exists() {
+    fs::remove_dir_all(cert_dir).context("failed to clear cert_dir")?;
+  }
+  fs::create_dir_all(cert_dir).context("failed to create cert_dir")?;
+
+  let hosts_path = Path::new(file!()).parent().unwrap().join("hosts.json");
+  let hosts: HostsConfig = serde_json::from_slice(&fs::read(hosts_path)?).context("failed to parse hosts.json")?;
+
+  write_cert(cert_dir, "default", hosts.default.names)?;
+  for host in hosts.hosts {
+    write_cert(cert_dir, &cert_name(&host.pattern), vec![host.pattern])?;
+  }
+  println!("✅ Finished generating keys!");
+
+  Ok(())
+}
+
+// `*.wild.localhost` is stored as `_.wild.localhost`
+fn cert_name(pattern: &str) -> String {
+  pattern.replace('*', "_")
+}
+
+fn write_cert(cert_dir: &Path, name: &str, sans: Vec<String>) -> Result<()> {
+  let cert_path = cert_dir.join(format!("{}.cert.der", name));
+  let key_path = cert_dir.join(format!("{}.key.der", name));
+
+  let cert_key = rcgen::generate_simple_self_signed(sans.clone())
+    .with_context(|| format!("failed to generate a certificate for {}", name))?;
+  let priv_key = PrivatePkcs8KeyDer::from(cert_key.key_pair.serialize_der());
+  let cert: CertificateDer<'_> = cert_key.cert.into();
+
+  fs::write(&cert_path, &cert).context("failed to write certificate")?;
+  fs::write(&key_path, priv_key.secret_pkcs8_der()).context("failed to write private key")?;
+  println!("  📄 {} [{}]\n  🔑 {}", cert_path.display(), sans.join(", "), key_path.display());
+  Ok(())
+}
@@ -0,0 +1,149 @@
+use rustls::crypto::{CryptoProvider, aws_lc_rs};
+use std::{
+  fs, net::SocketAddr, path::{Path, PathBuf}, sync::Arc, time::Instant,
+};
+use anyhow::{Context, Result};
+use quinn::{
+  crypto::rustls::QuicServerConfig,
+  Endpoint,
+  ServerConfig,
+};
+use log::info;
+use serde::Deserialize;
+use quic_rs_demo::{
+  admission::Admission,
+  certs::load_certified_key,
+  files,
+  keylog,
+  logging::{self, spawn_logged},
+  metrics::Metrics,
+  qlog::{Qlog, Vantage},
+  scenarios,
+  shutdown,
+  sni::{server_name, HostMap, SniResolver},
+};
+
+const CERT_DIR: &str = "/tmp/quinn_certs/vhosts";
+
+#[derive(Deserialize)]
+struct HostsConfig {
+  default: DefaultHost,
+  hosts: Vec<Host>,
+}
+
+#[derive(Deserialize)]
+struct DefaultHost {
+  root: String,
+}
+
+#[derive(Deserialize)]
+struct Host {
+  pattern: String,
+  root: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+  logging::init();
+  CryptoProvider::install_default(
+    aws_lc_rs::default_provider()
+  ).expect("failed to install default crypto provider");
+  let provider = CryptoProvider::get_default().unwrap();
+
+  let example_dir = Path::new(file!()).parent().unwrap();
+  let hosts: HostsConfig = serde_json::from_slice(&fs::read(example_dir.join("hosts.json"))?)
+    .context("failed to parse hosts.json")?;
+
+  // one map picks the certificate during the handshake, the other the document root afterwards
+  let cert_dir = Path::new(CERT_DIR);
+  let mut certs = HostMap::new();
+  let mut roots = HostMap::new();
+  let load = |name: &str| load_certified_key(
+    &cert_dir.join(format!("{}.cert.der", name)),
+    &cert_dir.join(format!("{}.key.der", name)),
+    provider,
+  ).map(Arc::new);
+
+  certs.set_default(load("default")?);
+  roots.set_default(example_dir.join(&hosts.default.root));
+  info!(root = hosts.default.root.as_str(); "🏠 default host");
+  for host in &hosts.hosts {
+    certs.insert(&host.pattern, load(&host.pattern.replace('*', "_"))?)?;
+    roots.insert(&host.pattern, example_dir.join(&host.root))?;
+    info!(pattern = host.pattern.as_str(), root = host.root.as_str(); "🌐 virtual host");
+  }
+
+  let mut tls_config = rustls::ServerConfig::builder()
+  .with_no_client_auth()
+  .with_cert_resolver(Arc::new(SniResolver::new(certs)));
+  tls_config.key_log = keylog::from_env();
+
+  let quic_config = QuicServerConfig::try_from(tls_config)?;
+  let server_config = ServerConfig::with_crypto(Arc::new(quic_config));
+
+  let addr: SocketAddr = "127.0.0.1:4843".parse()?;
+  let endpoint = Endpoint::server(server_config, addr)?;
+
+  let addr = endpoint.local_addr()?;
+  info!(addr:% = addr; "🚀 QUIC server listening");
+  let qlog = Qlog::from_env(Vantage::Server)?;
+  let metrics = Metrics::from_env("vhosts-server").await?;
+  let admission = Admission::from_env()?;
+
+  let roots = Arc::new(roots);
+  let serving = {
+    let (endpoint, metrics) = (endpoint.clone(), metrics.clone());
+    async move {
+      while let Some((conn, slot)) = admission.accept(&endpoint, &metrics).await {
+        let remote = conn.remote_address();
+        info!(remote:%; "accepting connection");
+        spawn_logged(
+          format!("connection from {}", remote),
+          slot.hold(handle_conn(conn, roots.clone(), qlog.clone(), metrics.clone())),
+        );
+      }
+    }
+  };
+  shutdown::serve(&endpoint, &metrics, serving).await
+}
+
+async fn handle_conn(
+  incomming: quinn::Incoming,
+  roots: Arc<HostMap<PathBuf>>,
+  qlog: Qlog,
+  metrics: Arc<Metrics>,
+) -> Result<()> {
+  let started = Instant::now();
+  let conn = incomming.await.inspect_err(|e| metrics.refused(Some(e)))?;
+  metrics.accepted(&conn, started.elapsed());
+  let sni = server_name(&conn);
+  let root = roots.get(sni.as_deref()).context("no document root")?.clone();
+  let conn_id = conn.stable_id();
+  info!(
+    conn_id,
+    remote:% = conn.remote_address(),
+    sni = sni.as_deref().unwrap_or("<no SNI>"),
+    root:% = root.display();
+    "established connection",
+  );
+  let trace = qlog.trace(&conn);
+  let root = Arc::new(root);
+  loop {
+    let stream = scenarios::accept_bi(&conn, &metrics).await;
+    let stream = match stream {
+      Err(quinn::ConnectionError::ApplicationClosed { .. }) => {
+        info!(conn_id; "connection closed");
+        return Ok(());
+    }
+      Err(e) => {
+        return Err(e.into());
+      }
+      Ok(s) => s
+    };
+    let root = root.clone();
+    let id = stream.0.id();
+    spawn_logged(format!("stream on connection {}", conn_id), metrics.stream(trace.stream(id, async move {
+      files::respond(conn_id, stream, |req| files::read(&root, req)).await
+    })));
+  }
+}
@@ -1,24 +1,38 @@
 use std::{
-  fs, io::{self, Write}, net::{SocketAddr, UdpSocket}, path::Path, sync::Arc, time::Duration,
+  io::{self, Write}, net::SocketAddr, path::Path, time::Duration,
 };
 use anyhow::{Context, Result};
-use rustls::pki_types::CertificateDer;
-
-use quinn::{
-  EndpointConfig,
-  TokioRuntime,
-  Endpoint,
-  Connection,
-  ClientConfig,
+use rustls::crypto::{CryptoProvider, aws_lc_rs};
+use log::info;
+use quic_rs_demo::{
+  config::env_or,
+  logging::{self, spawn_logged},
+  early_data::{EarlyClient, ZeroRttCounters},
+  qlog::{Qlog, Vantage},
+  scenarios::{self, zero_rtt},
 };
+
 const CERT_DIR: &str = "/tmp/quinn_certs";
 
 #[tokio::main]
 async fn main() -> Result<()> {
-  let endpoint = endpoint();
+  logging::init();
+  let qlog = Qlog::from_env(Vantage::Client)?;
+  CryptoProvider::install_default(
+    aws_lc_rs::default_provider()
+  ).expect("failed to install default crypto provider");
+
+  // tickets live in this endpoint's in-memory session store, so only this
+  // process can resume with 0-RTT, see "Resuming from a New Process" in the README
+  let tls_config = zero_rtt::client_tls(Path::new(CERT_DIR))?;
+  let addr: SocketAddr = "127.0.0.1:4385".parse()?;
+  let endpoint = scenarios::client_endpoint(tls_config, addr)?;
   let server_addr = "127.0.0.1:4843".parse()?;
+  // another server of the pool, sharing the ticket keys
+  let resume_addr = env_or("RESUME_ADDR", server_addr)?;
+  let counters = ZeroRttCounters::default();
 
-  println!("initial connection...");
+  info!("initial connection...");
   let conn = endpoint
   .connect(server_addr, "localhost")
   .unwrap()
@@ -28,132 +42,82 @@ async fn main() -> Result<()> {
   .await
   .expect("connect");
 
-  println!("connected to server {}", server_addr.to_string());
+  info!(conn_id = conn.stable_id(), remote:% = server_addr; "connected to server");
+  let _trace = qlog.trace(&conn);
+  let conn = EarlyClient::established(conn);
 
   get_sample(&conn).await.context("failed to get sample.json")?;
-  println!("posting something in full handshake...");
+  info!("posting something in full handshake...");
   post_something(&conn).await.context("failed to post something")?;
-  drop(conn);
+  report(&conn, &counters).await;
+  conn.connection().close(0u32.into(), b"done");
 
-  println!("\nresuming connection...");
+  info!("resuming connection...");
 
-  let (conn, _zero_rtt) = endpoint
-  .connect(server_addr, "localhost")
-  .unwrap()
-  .into_0rtt()
-  .unwrap_or_else(|_| panic!("resuming connection with 0-RTT failed"));
-  
-  println!("0-RTT connected server {}", server_addr.to_string());
+  let conn = zero_rtt::resume(&endpoint, resume_addr).await?;
+  let _trace = qlog.trace(conn.connection());
   get_sample(&conn).await.context("failed to get sample.json")?;
-  println!("resending request...");
+  info!("resending request...");
   get_sample(&conn).await.context("failed to get sample.json")?;
-  println!("posting something after 0-rtt...");
+  info!("posting something after 0-rtt...");
   post_something(&conn).await.context("failed to post something after 0-RTT")?;
-  drop(conn);
+  report(&conn, &counters).await;
+  conn.connection().close(0u32.into(), b"done");
 
-  println!("\nresuming connection again...");
-  let (conn, _zero_rtt) = endpoint
-  .connect(server_addr, "localhost")
-  .unwrap()
-  .into_0rtt()
-  .unwrap_or_else(|_| panic!("resuming connection with 0-RTT failed"));
-  
-  println!("0-RTT connected server {}", server_addr.to_string());
-  println!("posting something in 0-rtt...");
+  info!("resuming connection again...");
+  let conn = zero_rtt::resume(&endpoint, resume_addr).await?;
+  let _trace = qlog.trace(conn.connection());
+  info!("posting something in 0-rtt...");
   post_something(&conn).await?;
-  drop(conn);
+  report(&conn, &counters).await;
+  conn.connection().close(0u32.into(), b"done");
 
-  println!("\nresuming connection for replay attack...");
-  let (conn, _zero_rtt) = endpoint
-  .connect(server_addr, "localhost")
-  .unwrap()
-  .into_0rtt()
-  .unwrap_or_else(|_| panic!("resuming connection with 0-RTT failed"));
-  
-  println!("0-RTT connected server {}", server_addr.to_string());
-  println!("replay requests in 0-rtt...");
+  info!("resuming connection for replay attack...");
+  let conn = zero_rtt::resume(&endpoint, resume_addr).await?;
+  let _trace = qlog.trace(conn.connection());
+  info!("replay requests in 0-rtt...");
   replay_attack(&conn).await?;
+  report(&conn, &counters).await;
+  endpoint.wait_idle().await;
+
+  info!("📊 0-RTT over all connections: {}", counters);
   Ok(())
 }
 
-async fn get_sample(conn: &Connection) -> Result<()> {
-  let (mut send, mut recv) = conn
-  .open_bi()
-  .await
-  .context("failed to open bi_stream")?;
-  let req = format!("GET {}\r\n", "sample.json");
-  send.write_all(req.as_bytes())
-    .await
-    .context("failed to send request")?;
-  send.finish().unwrap();
-
-  let resp = recv
-    .read_to_end(usize::MAX)
-    .await
-    .context("failed to read response")?;
-  io::stdout().write_all(&resp).unwrap();
-  io::stdout().flush().unwrap();
-  println!("");
+// the outcome is known once the handshake is done
+async fn report(conn: &EarlyClient, counters: &ZeroRttCounters) {
+  let outcome = conn.zero_rtt().await;
+  counters.record(outcome);
+  info!(conn_id = conn.connection().stable_id(), zero_rtt:% = outcome; "📊 0-RTT {}", outcome);
+}
+
+async fn get_sample(conn: &EarlyClient) -> Result<()> {
+  let resp = zero_rtt::get(conn, "sample.json").await?;
+  print(&resp);
   Ok(())
 }
 
-async fn post_something(conn: &Connection) -> Result<()> {
-  let (mut send, mut recv) = conn
-  .open_bi()
-  .await
-  .context("failed to open bi_stream")?;
-  let req = format!("POST /something {}\r\n", "some important thing");
-  send.write_all(req.as_bytes())
-    .await
-    .context("failed to send request")?;
-  send.finish().unwrap();
-
-  let resp = recv
-    .read_to_end(usize::MAX)
-    .await
-    .context("failed to read response")?;
-  io::stdout().write_all(&resp).unwrap();
-  io::stdout().flush().unwrap();
-  println!("");
+async fn post_something(conn: &EarlyClient) -> Result<()> {
+  let resp = zero_rtt::post(conn, "some important thing").await?;
+  print(&resp);
   Ok(())
 }
 
-async fn replay_attack(conn: &Connection) -> Result<()> {
+fn print(resp: &[u8]) {
+  io::stdout().write_all(resp).unwrap();
+  io::stdout().flush().unwrap();
+  println!();
+}
+
+async fn replay_attack(conn: &EarlyClient) -> Result<()> {
   for _ in 0..5 {
     let c = conn.clone();
-    tokio::spawn(async move {
+    spawn_logged("replayed request", async move {
       get_sample(&c).await
     });
   }
   tokio::time::sleep(Duration::from_millis(80)).await;
-  conn.close(0u32.into(), b"done");
+  conn.connection().close(0u32.into(), b"done");
   tokio::time::sleep(Duration::from_millis(20)).await;
   Ok(())
 }
-
-// TODO: 0RTT only works if we setup server and client endpoint with Endpoint::new
-// instead of Endpoint::server and Endpoint::client. WHY?
-fn endpoint() -> Endpoint {
-  let cert_dir: &Path = Path::new(CERT_DIR);
-  let cert_path= cert_dir.join("cert.der");
-
-  let bytes = fs::read(cert_path).context("failed to read certificate").unwrap();
-  let cert = CertificateDer::try_from(bytes).unwrap();
-
-  let mut roots = rustls::RootCertStore::empty();
-  roots.add(cert.clone()).unwrap();
-
-  let client_config = ClientConfig::with_root_certificates(Arc::new(roots)).unwrap();
-
-  let addr: SocketAddr = "127.0.0.1:4385".parse().unwrap();
-  let mut endpoint = Endpoint::new(
-    EndpointConfig::default(),
-    None,
-    UdpSocket::bind(addr).unwrap(),
-    Arc::new(TokioRuntime),
-  ).unwrap();
-
-  endpoint.set_default_client_config(client_config);
-
-  endpoint
-}
\ No newline at end of file
@@ -1,29 +1,47 @@
-use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
 use std::{
+  env,
   path::Path,
   fs,
+  time::Duration,
 };
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use quic_rs_demo::{certs, config::env_or, tickets::rotate_key_file};
 
 const CERT_DIR: &str = "/tmp/quinn_certs";
+const DEFAULT_TICKET_LIFETIME_SECS: u64 = 6 * 60 * 60;
 
 fn main() -> Result<()>{
+  let args: Vec<String> = env::args().skip(1).collect();
   let cert_dir = Path::new(CERT_DIR);
+  match args.first().map(String::as_str) {
+    None => generate(cert_dir),
+    Some("rotate-ticket-key") => {
+      let key_file = match args.get(1) {
+        Some(path) => Path::new(path).to_path_buf(),
+        None => cert_dir.join("ticket_keys"),
+      };
+      // keys are erased once every ticket they encrypted has expired
+      let lifetime = Duration::from_secs(env_or("TICKET_LIFETIME", DEFAULT_TICKET_LIFETIME_SECS)?);
+      let keys = rotate_key_file(&key_file, lifetime)?;
+      println!("✅ Rotated session ticket keys in {}", key_file.display());
+      for key in keys {
+        println!("  🔑 created {}", key.created);
+      }
+      Ok(())
+    }
+    Some(other) => bail!("unknown command {:?}, expected no command or `rotate-ticket-key [file]`", other),
+  }
+}
+
+fn generate(cert_dir: &Path) -> Result<()> {
   if cert_dir.exists() {
     fs::remove_dir_all(cert_dir).context("failed to clear cert_dir")?;
   }
-  fs::create_dir_all(cert_dir).context("failed to create cert_dir")?;
+  certs::generate_self_signed(cert_dir)?;
 
   let cert_path = cert_dir.join("cert.der");
   let key_path = cert_dir.join("key.der");
-
-  let cert_key = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
-  let priv_key = PrivatePkcs8KeyDer::from(cert_key.key_pair.serialize_der());
-  let cert: CertificateDer<'_> = cert_key.cert.into();
-  
-  fs::write(&cert_path, &cert).context("failed to write certificate")?;
-  fs::write(&key_path, &priv_key.secret_pkcs8_der()).context("failed to write private key")?;
   println!("✅ Finished generating key!\n  📄 Cert: {}\n  🔑 Key:  {}", cert_path.display(), key_path.display());
 
   Ok(())
-}
\ No newline at end of file
+}
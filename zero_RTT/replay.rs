@@ -0,0 +1,66 @@
+use std::{
+  io::{self, Write}, net::SocketAddr, path::Path, time::Duration,
+};
+use anyhow::Result;
+use rustls::crypto::{CryptoProvider, aws_lc_rs};
+use log::info;
+use quic_rs_demo::{
+  files,
+  logging,
+  qlog::{Qlog, Vantage},
+  scenarios::{self, zero_rtt::{self, Captured}},
+};
+
+const CERT_DIR: &str = "/tmp/quinn_certs";
+const SERVER_ADDR: &str = "127.0.0.1:4843";
+const PROXY_ADDR: &str = "127.0.0.1:4844";
+// long enough for the server to forget the original connection
+const DRAIN_WAIT: Duration = Duration::from_secs(4);
+
+#[tokio::main]
+async fn main() -> Result<()> {
+  logging::init();
+  let qlog = Qlog::from_env(Vantage::Client)?;
+  CryptoProvider::install_default(
+    aws_lc_rs::default_provider()
+  ).expect("failed to install default crypto provider");
+  let tls_config = zero_rtt::client_tls(Path::new(CERT_DIR))?;
+  let endpoint = scenarios::client_endpoint(tls_config, "127.0.0.1:4386".parse()?)?;
+  let server_addr: SocketAddr = SERVER_ADDR.parse()?;
+
+  info!("initial connection for a session ticket...");
+  let conn = scenarios::connect(&endpoint, server_addr).await?;
+  let _trace = qlog.trace(&conn);
+  print(&files::get(&conn, "sample.json").await?);
+  conn.close(0u32.into(), b"done");
+  info!("got a session ticket");
+
+  // an on-path attacker records everything the client sends
+  let captured = Captured::default();
+  let proxy_addr = zero_rtt::recording_proxy(PROXY_ADDR.parse()?, server_addr, captured.clone()).await?;
+
+  info!(proxy:% = proxy_addr; "resuming with 0-RTT through the recording proxy...");
+  let (conn, zero_rtt) = endpoint
+    .connect(proxy_addr, "localhost")?
+    .into_0rtt()
+    .unwrap_or_else(|_| panic!("resuming connection with 0-RTT failed"));
+  let _trace = qlog.trace(&conn);
+  print(&files::get(&conn, "sample.json").await?);
+  zero_rtt.await;
+  conn.close(0u32.into(), b"done");
+  endpoint.wait_idle().await;
+
+  tokio::time::sleep(DRAIN_WAIT).await;
+  let datagrams = captured.lock().unwrap().clone();
+  info!(datagrams = datagrams.len(); "📼 replaying captured datagrams from a new address...");
+  let _attacker = zero_rtt::replay(&datagrams, server_addr).await?;
+  tokio::time::sleep(Duration::from_secs(1)).await;
+  info!("done, check the server output for a second 0-RTT request");
+  Ok(())
+}
+
+fn print(resp: &[u8]) {
+  io::stdout().write_all(resp).unwrap();
+  io::stdout().flush().unwrap();
+  println!();
+}
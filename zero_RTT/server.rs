@@ -1,177 +1,57 @@
-use rustls::pki_types::{CertificateDer, PrivateKeyDer};
-use core::ascii;
-use std::{
-  fmt::Write, fs, net::{SocketAddr, UdpSocket}, path::Path, sync::Arc, str,
+use rustls::crypto::{CryptoProvider, aws_lc_rs};
+use std::{net::SocketAddr, path::{Path, PathBuf}, sync::Arc, time::Duration};
+use anyhow::Result;
+use log::{info, warn};
+use quic_rs_demo::{
+  admission::Admission,
+  config::env_or,
+  early_data::ZeroRttCounters,
+  logging,
+  metrics::Metrics,
+  qlog::{Qlog, Vantage},
+  scenarios::{self, zero_rtt::{self, Tickets}},
+  shutdown,
 };
-use anyhow::{Context, Result, bail, anyhow};
-use quinn::{
-  EndpointConfig,
-  TokioRuntime,
-  Endpoint,
-  ServerConfig,
-  Connection,
-  Incoming,
-  SendStream, 
-  RecvStream,
-};
-use colored::*;
-// use  proto::crypto::rustls::QuicServerConfig,
 
 const CERT_DIR: &str = "/tmp/quinn_certs";
+const DEFAULT_TICKET_LIFETIME_SECS: u64 = 6 * 60 * 60;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-  let endpoint = endpoint();
-  let addr = endpoint.local_addr()?;
-  println!(
-    "{} {}",
-    "🚀 QUIC server listening at:".bold().green(),
-    addr.to_string().blue());
-
-  while let Some(incomming) = endpoint.accept().await {
-    println!("accepting incomming connection from {}", incomming.remote_address());
-    tokio::spawn(async move {
-      handle_incomming(incomming).await
-    }); 
-  }
-  Ok(())
-}
-
-async fn handle_incomming(incomming: Incoming) -> Result<()> {
-  let connecting = incomming.accept()?;
-  // into_0rtt is degraded to full handshake if 0-rtt is rejected
-  // TODO: zero_rtt is always false even 0RTT is accepted. IS THIS A BUG?
-  let Ok((conn, _zero_rtt))= connecting.into_0rtt() else {
-    return Err(anyhow!("failed establishing connection"));
+  logging::init();
+  CryptoProvider::install_default(
+    aws_lc_rs::default_provider()
+  ).expect("failed to install default crypto provider");
+
+  let listen: SocketAddr = env_or("LISTEN", "127.0.0.1:4843".parse()?)?;
+  let key_file = env_or("TICKET_KEY_FILE", String::new())?;
+  let tickets = Tickets {
+    key_file: Some(PathBuf::from(key_file)).filter(|p| !p.as_os_str().is_empty()),
+    lifetime: Duration::from_secs(env_or("TICKET_LIFETIME", DEFAULT_TICKET_LIFETIME_SECS)?),
+    early_data: env_or("EARLY_DATA", true)?,
+    anti_replay: env_or("ANTI_REPLAY", true)?,
+    replicated: env_or("REPLICATED_CACHE", false)?,
   };
-  println!("established connection from {}", conn.remote_address());
-
-  tokio::spawn(async move {
-    handle_conn(conn).await
-  });
-
-  Ok(())
-}
-
-async fn handle_conn(conn: Connection) -> Result<()> {
-  loop {
-    let stream = conn.accept_bi().await;
-    let (send, recv) = match stream {
-      Err(quinn::ConnectionError::ApplicationClosed { .. }) => {
-        println!("connection closed\n");
-        return Ok(());
-    }
-      Err(e) => {
-        return Err(e.into());
-      }
-      Ok(s) => s
-    };
-    tokio::spawn(async move {
-      handle_stream(send, recv).await
-    });
-  }
-}
-
-async fn handle_stream(mut send: SendStream, mut recv: RecvStream) -> Result<()> {
-  let is_0rtt = recv.is_0rtt();
-  let req = recv
-  .read_to_end(64*1024)
-  .await
-  .context("failed reading request")?;
-
-  let mut escaped = String::new();
-  for &x in &req {
-    for c in ascii::escape_default(x) {
-      escaped.write_char(c as char).unwrap();
-    }
-  }
-  println!("req {}", escaped);
-
-  let resp = handle_req(&req, is_0rtt).unwrap_or_else(
-    |e| {
-      println!("handle request failed: {}", e);
-      String::from("failed to handle request").into_bytes()
-  });
-  send.write_all(&resp).await.context("failed to send response")?;
-  send.finish().unwrap();
-  println!("complete stream handling!");
-  Ok(())
-}
-
-fn handle_req(req: &[u8], is_0rtt: bool) -> Result<Vec<u8>> {
-  println!("req is_0rtt {}", is_0rtt);
-  if is_get(req) {
-    return handle_get(&req[4..]);
-  }
-  if is_0rtt {
-    bail!("0-RTT is not applied to POST")
-  }
-  if is_post(req) {
-    return handle_post(&req[5..]);
-  }
-  bail!("not a GET or POST request");
-}
-
-fn is_get(req: &[u8]) -> bool {
-  req.len() > 4 && &req[0..4] == b"GET "
-}
-
-fn handle_get(req: &[u8]) ->  Result<Vec<u8>> {
-  if req.len() < 2 || &req[req.len() - 2..] != b"\r\n" {
-    bail!("missing \\r\\n");
-  }
-  let filename = &req[..req.len()-2];
-  let filename = str::from_utf8(&filename).context("filename is malformed UTF-8")?;
-  let path = Path::new(file!());
-  let path = path.parent().unwrap().join(filename);
-  let bytes = fs::read(&path).context("failed reading file")?;
-  Ok(bytes)
-}
-
-fn is_post(req: &[u8]) -> bool {
-  req.len() > 5 && &req[0..5] == b"POST "
-}
-
-fn handle_post(req: &[u8]) ->  Result<Vec<u8>> {
-  if req.len() < 2 || &req[req.len() - 2..] != b"\r\n" {
-    bail!("missing \\r\\n");
-  }
-  let req = &req[..req.len()-2];
-  if !req.starts_with(b"/something ") {
-    bail!("something is missing");
-  }
-  let body = &req[10..];
-  let str = String::from_utf8(body.to_vec())?;
-  println!("client post: {}", str);
-  Ok(b"successfully post".to_vec())
+  let tls_config = zero_rtt::server_tls(Path::new(CERT_DIR), &tickets)?;
+  let endpoint = scenarios::server_endpoint(tls_config, listen)?;
+  let addr = endpoint.local_addr()?;
+  info!(addr:% = addr; "🚀 QUIC server listening");
+  let qlog = Qlog::from_env(Vantage::Server)?;
+  let metrics = Metrics::from_env("zeroRTT-server").await?;
+  let admission = Admission::from_env()?;
+  if let Some(key_file) = &tickets.key_file {
+    info!(key_file:% = key_file.display(), lifetime_secs = tickets.lifetime.as_secs(); "🔑 session tickets encrypted with keys from a shared file");
+    warn!("⚠️  rustls accepts 0-RTT only with stateful tickets, clients resume without early data");
+  } else if !tickets.early_data {
+    info!("🐢 0-RTT is off, resumed connections wait for the handshake");
+  } else if tickets.anti_replay {
+    info!("🛡️  each session ticket can be redeemed once");
+  } else {
+    warn!("⚠️  anti-replay is off, a captured 0-RTT flight can be replayed");
+  }
+
+  let counters = Arc::new(ZeroRttCounters::default());
+  let root = Path::new(file!()).parent().unwrap().to_path_buf();
+  let serving = zero_rtt::serve(endpoint.clone(), admission, root, counters, qlog, metrics.clone());
+  shutdown::serve(&endpoint, &metrics, serving).await
 }
-
-// TODO: 0RTT only works if we setup server and client endpoint with Endpoint::new
-// instead of Endpoint::server and Endpoint::client. WHY?
-fn endpoint() -> Endpoint {
-  let cert_dir: &Path = Path::new(CERT_DIR);
-  let cert_path= cert_dir.join("cert.der");
-  let key_path = cert_dir.join("key.der");
-
-  let bytes = fs::read(cert_path).context("failed to read certificate").unwrap();
-  let cert = CertificateDer::try_from(bytes).unwrap();
-
-  let bytes = fs::read(key_path).context("failed to read private key").unwrap();
-  let key =  PrivateKeyDer::try_from(bytes).map_err(anyhow::Error::msg).unwrap();
-
-  let server_config = ServerConfig::with_single_cert(
-  vec![cert.clone()], key).unwrap();
-  let mut roots = rustls::RootCertStore::empty();
-  roots.add(cert.clone()).unwrap();
-
-  let addr: SocketAddr = "127.0.0.1:4843".parse().unwrap();
-
-  let endpoint = Endpoint::new(
-    EndpointConfig::default(),
-    Some(server_config),
-    UdpSocket::bind(addr).unwrap(),
-    Arc::new(TokioRuntime),
-  ).unwrap();
-
-  endpoint
-}
\ No newline at end of file